@@ -1,15 +1,67 @@
+use futures::{Stream, StreamExt};
+
 use crate::error::VfsResult;
+use crate::Access;
 use crate::CanRead;
 use crate::CanWrite;
 use crate::Dir;
+use crate::DirEntry;
 use crate::File;
 use crate::FileHandle;
+use crate::NodeKind;
+use crate::NodeType;
+use crate::OpenResult;
 use crate::ReadOnly;
 use crate::Stat;
 use crate::WalkResult;
+use crate::WriteOnly;
+use crate::error::VfsError;
+use crate::mode::{OREAD, OTRUNC, OWRITE, OpenMode};
+use crate::path::canonicalize;
+use crate::wire::try_encode_stat;
+
+/// Chunk size used by the default whole-file helpers
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Bytes read from the start of a file to guess its content type
+const SNIFF_LEN: usize = 512;
+
+/// Guess a MIME type from the first bytes of a file
+fn sniff_content_type(prefix: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+    ];
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| prefix.starts_with(magic)) {
+        return mime;
+    }
+
+    // The prefix may cut a character in half; only an error before the end counts
+    let utf8 = match std::str::from_utf8(prefix) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    let control = prefix
+        .iter()
+        .any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c));
+    if utf8 && !control {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
 
 /// Core trait implemented by all backends.
 /// Note: not `dyn`-compatible because of generic methods.
+///
+/// A backend only needs the eight primitives (`walk` through `readdir`);
+/// the remaining methods have defaults built on them and can be overridden
+/// with faster versions. Being generic, the primitives can't be called
+/// through `dyn VfsBackend`, so neither can the defaults. Whole-file and
+/// path-level helpers such as `read_all` live in [`VfsBackendExt`].
 #[async_trait::async_trait]
 pub trait VfsBackend: Send + Sync + 'static {
     async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult>;
@@ -18,13 +70,17 @@ pub trait VfsBackend: Send + Sync + 'static {
 
     async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
     where
-        M: Send + Sync + 'static,
-        T: Send + Sync + 'static;
+        M: Access,
+        T: NodeType;
 
+    /// Create a node at `path`, exclusively like `O_CREAT | O_EXCL`.
+    ///
+    /// Fails with `AlreadyExists` if anything is at `path`, whatever its
+    /// kind.
     async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
     where
         M: Send + Sync + 'static,
-        T: Send + Sync + 'static;
+        T: NodeType;
 
     async fn read<M: CanRead>(
         &self,
@@ -42,5 +98,572 @@ pub trait VfsBackend: Send + Sync + 'static {
 
     async fn remove<T>(&self, path: &str) -> VfsResult<()>;
 
-    async fn readdir(&self, handle: &FileHandle<Dir, ReadOnly>) -> VfsResult<Vec<Stat>>;
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>>;
+
+    /// Walk a slash-separated path from the root.
+    ///
+    /// `/` walks nothing and returns no qids.
+    async fn walk_path(&self, path: &str) -> VfsResult<WalkResult> {
+        let path = canonicalize(path)?;
+        let names: Vec<String> = path
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+        self.walk("/", &names).await
+    }
+
+    /// Walk `names` relative to an open directory, like a 9P walk from a fid.
+    async fn walk_from<M: Send + Sync + 'static>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        names: &[String],
+    ) -> VfsResult<WalkResult> {
+        self.walk(&handle.path, names).await
+    }
+
+    /// Open the file behind `handle` again with another access mode.
+    ///
+    /// The path is checked exactly as by a fresh `open`, so the new mode
+    /// must still be allowed. `handle` stays open; clunk it separately.
+    async fn reopen<M2, M>(&self, handle: &FileHandle<File, M>, mode: u32) -> VfsResult<FileHandle<File, M2>>
+    where
+        M2: Access,
+        M: Send + Sync + 'static,
+    {
+        self.open::<M2, File>(&handle.path, mode).await
+    }
+
+    /// Like `walk`, but fails instead of returning a partial result.
+    ///
+    /// The `NotFound` error names the path of the first missing component.
+    async fn walk_strict(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        let result = self.walk(start, names).await?;
+        if result.qids.len() < names.len() {
+            let walked = &names[..=result.qids.len()];
+            let base = start.trim_end_matches('/');
+            return Err(VfsError::NotFound(format!("{}/{}", base, walked.join("/"))));
+        }
+        Ok(result)
+    }
+
+    /// Release a fid once its handle is no longer needed.
+    ///
+    /// Backends that don't track fids have nothing to release.
+    async fn clunk(&self, _fid: u64) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// Hint that about `additional` more bytes will be written to a file.
+    ///
+    /// Never changes the file's size or contents. Backends without
+    /// preallocation ignore it.
+    async fn reserve<M: CanWrite>(&self, _handle: &FileHandle<File, M>, _additional: u64) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// Whether a path is a file or a directory.
+    async fn kind(&self, path: &str) -> VfsResult<NodeKind> {
+        let stat = self.stat(path).await?;
+        Ok(if stat.is_dir() {
+            NodeKind::Dir
+        } else {
+            NodeKind::File
+        })
+    }
+
+    /// List a directory with each child's name, qid, and stat in one call.
+    ///
+    /// Names are relative to the directory, with no `.` or `..` entries,
+    /// and entries are sorted by name.
+    async fn readdirplus<M: CanRead>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+    ) -> VfsResult<Vec<DirEntry>> {
+        let mut entries: Vec<DirEntry> = self
+            .readdir(handle)
+            .await?
+            .into_iter()
+            .map(|stat| DirEntry {
+                name: stat.name.clone(),
+                qid: stat.qid,
+                stat,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Rename `from` to `to`, failing with `AlreadyExists` if `to` exists.
+    ///
+    /// The default fails with `Unsupported`. Decorators must forward this
+    /// to their inner backend or reject it on purpose, not inherit the
+    /// default, or wrapping a backend would quietly take the method away.
+    async fn rename(&self, _from: &str, _to: &str) -> VfsResult<()> {
+        Err(VfsError::Unsupported("rename"))
+    }
+
+    /// Read up to `buf.len()` bytes at `offset` into `buf`.
+    ///
+    /// Returns how many bytes were copied, 0 at the end of the file, like
+    /// `std::io::Read::read`. The default goes through `read`; backends
+    /// can override it to skip the intermediate allocation.
+    async fn read_into<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let chunk = self.read(handle, offset, buf.len()).await?;
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+}
+
+/// Whole-file and path-level helpers for every [`VfsBackend`].
+///
+/// Built only on the backend's methods and implemented for every backend
+/// by a blanket impl, like `StreamExt` for streams, so backend authors get
+/// them for free. Bring the trait into scope to call them.
+#[async_trait::async_trait]
+pub trait VfsBackendExt: VfsBackend {
+    /// Read a whole file into memory.
+    async fn read_all(&self, path: &str) -> VfsResult<Vec<u8>> {
+        let handle = self.open::<ReadOnly, File>(path, 0).await?;
+        let mut data = Vec::new();
+        let result = loop {
+            match self.read(&handle, data.len() as u64, READ_CHUNK).await {
+                Ok(chunk) if chunk.is_empty() => break Ok(data),
+                Ok(chunk) => data.extend_from_slice(&chunk),
+                Err(e) => break Err(e),
+            }
+        };
+        self.clunk(handle.fid).await?;
+        result
+    }
+
+    /// Fill `buf` from `offset`, looping over short reads.
+    ///
+    /// Fails with `UnexpectedEof` if the file ends first, like
+    /// `std::io::Read::read_exact`; `buf` then holds whatever was read.
+    async fn read_exact<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let chunk = self
+                .read(handle, offset + filled as u64, buf.len() - filled)
+                .await?;
+            if chunk.is_empty() {
+                return Err(VfsError::UnexpectedEof);
+            }
+            let n = chunk.len().min(buf.len() - filled);
+            buf[filled..filled + n].copy_from_slice(&chunk[..n]);
+            filled += n;
+        }
+        Ok(())
+    }
+
+    /// Read a whole file as UTF-8 text.
+    async fn read_to_string(&self, path: &str) -> VfsResult<String> {
+        let data = self.read_all(path).await?;
+        String::from_utf8(data).map_err(|_| VfsError::InvalidArgument("invalid utf-8".into()))
+    }
+
+    /// Guess the MIME type of a file from its first bytes.
+    ///
+    /// Recognizes PNG, JPEG, GIF, and PDF signatures, and calls anything
+    /// else that is UTF-8 without control characters plain text. Only a
+    /// short prefix is read. Directories fail with `IsADirectory`.
+    async fn content_type(&self, path: &str) -> VfsResult<&'static str> {
+        let handle = self.open::<ReadOnly, File>(path, 0).await?;
+        let result = self.read(&handle, 0, SNIFF_LEN).await;
+        self.clunk(handle.fid).await?;
+        Ok(sniff_content_type(&result?))
+    }
+
+    /// Replace the contents of a file, creating it if needed.
+    ///
+    /// An existing file is truncated in place, so its hard links and open
+    /// handles see the new contents.
+    async fn write_all(&self, path: &str, data: &[u8]) -> VfsResult<()> {
+        let handle = match self.open::<WriteOnly, File>(path, OWRITE | OTRUNC).await {
+            Err(VfsError::NotFound(_)) => self.create::<WriteOnly, File>(path, 0o644).await?,
+            opened => opened?,
+        };
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < data.len() {
+            match self.write(&handle, written as u64, &data[written..]).await {
+                // Nothing written would never finish, like a short read_exact
+                Ok(0) => {
+                    result = Err(VfsError::Io(std::io::ErrorKind::WriteZero.into()));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.clunk(handle.fid).await?;
+        result
+    }
+
+    /// Write every chunk of `stream` to an open file, starting at offset 0.
+    ///
+    /// Chunks are written one at a time as they arrive, so a slow writer
+    /// slows down consumption of the stream. An error from the stream stops
+    /// the copy and is returned. Returns the total bytes written.
+    async fn write_stream<M, S, B>(&self, handle: &FileHandle<File, M>, stream: S) -> VfsResult<u64>
+    where
+        M: CanWrite,
+        S: Stream<Item = VfsResult<B>> + Send,
+        B: AsRef<[u8]> + Send,
+    {
+        let mut stream = std::pin::pin!(stream);
+        let mut offset = 0u64;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            let mut data = chunk.as_ref();
+            while !data.is_empty() {
+                let n = self.write(handle, offset, data).await?;
+                if n == 0 {
+                    return Err(VfsError::Io(std::io::ErrorKind::WriteZero.into()));
+                }
+                offset += n as u64;
+                data = &data[n..];
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Whether a path exists.
+    async fn exists(&self, path: &str) -> VfsResult<bool> {
+        match self.stat(path).await {
+            Ok(_) => Ok(true),
+            Err(VfsError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Open `path` as whatever kind of node it is.
+    ///
+    /// Files come back with the access `mode` asks for, and directories as
+    /// a read-only handle. `mode` is passed to `open` either way, so a write mode
+    /// on a directory fails as it would for any read-only handle. The kind is looked up
+    /// before opening, so a node replaced in between may fail to open.
+    async fn open_any(&self, path: &str, mode: u32) -> VfsResult<OpenResult> {
+        let open_mode = OpenMode::parse(mode)?;
+        Ok(match self.kind(path).await? {
+            NodeKind::File if !open_mode.wants_write() => {
+                OpenResult::ReadFile(self.open(path, mode).await?)
+            }
+            NodeKind::File if matches!(open_mode.access, OREAD | OWRITE) => {
+                OpenResult::WriteFile(self.open(path, mode).await?)
+            }
+            NodeKind::File => OpenResult::File(self.open(path, mode).await?),
+            NodeKind::Dir => OpenResult::Dir(self.open(path, mode).await?),
+        })
+    }
+
+    /// Read a directory as a 9P server would: packed wire-format stats.
+    ///
+    /// Entries are sorted by name and encoded with
+    /// [`try_encode_stat`](crate::wire::try_encode_stat) one after another,
+    /// and `offset` is a byte position in that stream. Only whole entries that
+    /// fit in `count` bytes are returned, so the next read resumes at
+    /// `offset` plus the length returned; an empty buffer means the end,
+    /// including for an `offset` past it.
+    /// An `offset` inside an entry is `BadOffset`, and a `count` too small
+    /// for the next entry, or an entry too large for a 9P stat, is
+    /// `InvalidArgument`. Entries added or removed
+    /// between reads shift the later ones, as with any 9P directory read.
+    async fn readdir_wire<M: CanRead>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        let mut stats = self.readdir(handle).await?;
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut buf = Vec::new();
+        let mut entry = Vec::new();
+        let mut position = 0u64;
+        for stat in &stats {
+            entry.clear();
+            try_encode_stat(stat, &mut entry)?;
+            let start = position;
+            position += entry.len() as u64;
+            if start < offset {
+                if position > offset {
+                    return Err(VfsError::BadOffset);
+                }
+                continue;
+            }
+            if buf.len() + entry.len() > count {
+                if buf.is_empty() {
+                    return Err(VfsError::InvalidArgument(format!(
+                        "count {} is too small for a {}-byte stat",
+                        count,
+                        entry.len()
+                    )));
+                }
+                break;
+            }
+            buf.extend_from_slice(&entry);
+        }
+        Ok(buf)
+    }
+
+    /// Copy a file's contents to `to`, replacing any existing file there.
+    async fn copy(&self, from: &str, to: &str) -> VfsResult<()> {
+        let data = self.read_all(from).await?;
+        self.write_all(to, &data).await
+    }
+
+    /// Create a directory and any missing ancestors.
+    async fn create_dir_all(&self, path: &str) -> VfsResult<()> {
+        let mut current = String::new();
+        for name in path.split('/').filter(|n| !n.is_empty()) {
+            current.push('/');
+            current.push_str(name);
+
+            match self.stat(&current).await {
+                Ok(stat) if stat.is_dir() => {}
+                Ok(_) => return Err(VfsError::NotADirectory(current)),
+                Err(VfsError::NotFound(_)) => {
+                    let handle = self.create::<ReadOnly, Dir>(&current, 0o755).await?;
+                    self.clunk(handle.fid).await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: VfsBackend> VfsBackendExt for B {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::VfsMem;
+
+    /// Backend implementing only the primitives, to exercise the defaults
+    struct Minimal(VfsMem);
+
+    #[async_trait::async_trait]
+    impl VfsBackend for Minimal {
+        async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+            self.0.walk(start, names).await
+        }
+
+        async fn stat(&self, path: &str) -> VfsResult<Stat> {
+            self.0.stat(path).await
+        }
+
+        async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Access,
+            T: NodeType,
+        {
+            self.0.open(path, mode).await
+        }
+
+        async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Send + Sync + 'static,
+            T: NodeType,
+        {
+            self.0.create(path, mode).await
+        }
+
+        async fn read<M: CanRead>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            count: usize,
+        ) -> VfsResult<Vec<u8>> {
+            self.0.read(handle, offset, count).await
+        }
+
+        async fn write<M: CanWrite>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            data: &[u8],
+        ) -> VfsResult<usize> {
+            self.0.write(handle, offset, data).await
+        }
+
+        async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+            self.0.remove::<T>(path).await
+        }
+
+        async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+            self.0.readdir(handle).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_all_refuses_directory() {
+        let backend = Minimal(VfsMem::new());
+        backend.create_dir_all("/emptydir").await.unwrap();
+        assert!(matches!(
+            backend.write_all("/emptydir", b"data").await,
+            Err(VfsError::IsADirectory(p)) if p == "/emptydir"
+        ));
+        assert_eq!(backend.kind("/emptydir").await.unwrap(), NodeKind::Dir);
+    }
+
+    #[tokio::test]
+    async fn test_write_all_keeps_hard_links() {
+        let backend = Minimal(VfsMem::new());
+        backend.write_all("/f", b"old contents").await.unwrap();
+        backend.0.link("/f", "/g").await.unwrap();
+        let reader = backend.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+        backend.write_all("/f", b"new").await.unwrap();
+        assert_eq!(backend.read_all("/g").await.unwrap(), b"new");
+        assert_eq!(backend.read(&reader, 0, 100).await.unwrap(), b"new");
+    }
+
+    #[tokio::test]
+    async fn test_default_clunk_is_a_no_op() {
+        let backend = Minimal(VfsMem::new());
+        backend.clunk(12345).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_exact() {
+        let backend = Minimal(VfsMem::new());
+        backend.write_all("/f", b"0123456789").await.unwrap();
+        let handle = backend.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+        let mut buf = [0; 10];
+        backend.read_exact(&handle, 0, &mut buf).await.unwrap();
+        assert_eq!(&buf, b"0123456789");
+
+        let mut buf = [0; 4];
+        backend.read_exact(&handle, 6, &mut buf).await.unwrap();
+        assert_eq!(&buf, b"6789");
+
+        let mut buf = [0; 11];
+        assert!(matches!(
+            backend.read_exact(&handle, 0, &mut buf).await,
+            Err(VfsError::UnexpectedEof)
+        ));
+        backend.read_exact(&handle, 10, &mut []).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_into_fixed_buffers() {
+        let content = b"the quick brown fox jumps over the lazy dog";
+
+        // Once through the default and once through the VfsMem override
+        let minimal = Minimal(VfsMem::new());
+        minimal.write_all("/f", content).await.unwrap();
+        let vfs = VfsMem::new();
+        vfs.write_all("/f", content).await.unwrap();
+
+        for reassembled in [read_in_chunks(&minimal).await, read_in_chunks(&vfs).await] {
+            assert_eq!(reassembled, content);
+        }
+    }
+
+    async fn read_in_chunks(backend: &impl VfsBackend) -> Vec<u8> {
+        let handle = backend.open::<ReadOnly, File>("/f", 0).await.unwrap();
+        let mut buf = [0u8; 8];
+        let mut out = Vec::new();
+        loop {
+            let n = backend.read_into(&handle, out.len() as u64, &mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn test_readdirplus_matches_stat() {
+        let backend = Minimal(VfsMem::new());
+        backend.create_dir_all("/d/sub").await.unwrap();
+        backend.write_all("/d/b.txt", b"bee").await.unwrap();
+        backend.write_all("/d/a.txt", b"a").await.unwrap();
+
+        let dir = backend.open::<ReadOnly, Dir>("/d", 0).await.unwrap();
+        let entries = backend.readdirplus(&dir).await.unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "sub"]);
+
+        for entry in &entries {
+            let stat = backend.stat(&format!("/d/{}", entry.name)).await.unwrap();
+            assert_eq!(entry.qid, stat.qid);
+            assert_eq!(entry.stat, stat);
+        }
+        assert!(entries[2].qid.is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_default_methods() {
+        let backend = Minimal(VfsMem::new());
+
+        backend.create_dir_all("/a/b/c").await.unwrap();
+        assert!(backend.exists("/a/b/c").await.unwrap());
+        assert!(!backend.exists("/a/missing").await.unwrap());
+        // Already present is fine
+        backend.create_dir_all("/a/b").await.unwrap();
+
+        backend.write_all("/a/b/file", b"longer contents").await.unwrap();
+        backend.write_all("/a/b/file", b"short").await.unwrap();
+        assert_eq!(backend.read_all("/a/b/file").await.unwrap(), b"short");
+
+        backend.copy("/a/b/file", "/a/copy").await.unwrap();
+        assert_eq!(backend.read_to_string("/a/copy").await.unwrap(), "short");
+
+        assert!(matches!(
+            backend.create_dir_all("/a/b/file/sub").await,
+            Err(VfsError::NotADirectory(_))
+        ));
+    }
+
+    /// Both a full backend and one with only the primitives get the helpers
+    async fn round_trip(backend: &impl VfsBackendExt) -> String {
+        backend.create_dir_all("/ext").await.unwrap();
+        backend.write_all("/ext/note", b"via the blanket impl").await.unwrap();
+        backend.read_to_string("/ext/note").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ext_blanket_impl() {
+        assert_eq!(round_trip(&VfsMem::new()).await, "via the blanket impl");
+        assert_eq!(round_trip(&Minimal(VfsMem::new())).await, "via the blanket impl");
+    }
+
+    #[tokio::test]
+    async fn test_content_type() {
+        let backend = Minimal(VfsMem::new());
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0; 2048]);
+        backend.write_all("/image", &png).await.unwrap();
+        backend.write_all("/notes", "caf\u{e9} au lait\n".as_bytes()).await.unwrap();
+        backend.write_all("/blob", &[0x00, 0x01, 0xfe, 0x7f]).await.unwrap();
+        backend.create_dir_all("/dir").await.unwrap();
+
+        assert_eq!(backend.content_type("/image").await.unwrap(), "image/png");
+        assert_eq!(backend.content_type("/notes").await.unwrap(), "text/plain; charset=utf-8");
+        assert_eq!(backend.content_type("/blob").await.unwrap(), "application/octet-stream");
+        assert!(matches!(
+            backend.content_type("/dir").await,
+            Err(VfsError::IsADirectory(_))
+        ));
+    }
 }
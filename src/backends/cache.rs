@@ -0,0 +1,492 @@
+//! Caching backend decorator.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::{VfsBackend, VfsBackendExt};
+use crate::error::VfsResult;
+use crate::mode::OTRUNC;
+use crate::path::canonicalize;
+use crate::types::{Access, Dir, DirEntry, File, FileHandle, NodeType, Stat, WalkResult};
+use crate::{CanRead, CanWrite};
+
+/// A `stat` result and when it was fetched
+#[derive(Debug)]
+struct CachedStat {
+    stat: Stat,
+    fetched: Instant,
+}
+
+/// Whole contents of a file as of `version`
+#[derive(Debug)]
+struct CachedData {
+    version: u32,
+    data: Arc<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    stats: HashMap<String, CachedStat>,
+    contents: HashMap<String, CachedData>,
+    /// Current path of each fid opened through the cache, moved along by
+    /// renames made through it
+    paths: HashMap<u64, String>,
+}
+
+/// Parent of a canonical path
+fn parent_of(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+/// Backend wrapper that caches `stat` results and file contents.
+///
+/// A cached `stat` is served for `ttl` after it was fetched. Contents are
+/// cached whole and tagged with the file's qid version: once the `stat`
+/// expires it is fetched again, and the contents are only re-read if the
+/// version changed. Writes, creates, removes, renames, and truncating
+/// opens made through the cache invalidate what they touch; changes made
+/// directly to the inner backend show up once the `ttl` runs out.
+/// Handles opened through the cache read and write the file at its current
+/// path after a rename. Changing a file with several hard links drops
+/// everything cached, since the cache can't tell which paths are its links.
+///
+/// Every read is served from the cached copy, so the first read of a file
+/// costs a full read of it.
+#[derive(Debug)]
+pub struct CacheBackend<B> {
+    inner: B,
+    ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+impl<B: VfsBackend> CacheBackend<B> {
+    /// Wrap `inner`, trusting cached metadata for `ttl`
+    pub fn new(inner: B, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Get the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get the configured time to live
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Drop everything cached
+    pub fn clear(&self) -> VfsResult<()> {
+        let mut state = self.state.lock()?;
+        state.stats.clear();
+        state.contents.clear();
+        Ok(())
+    }
+
+    /// Drop what is cached for `path`, everything below it, and its
+    /// parent's metadata
+    fn invalidate(&self, path: &str) -> VfsResult<()> {
+        let path = canonicalize(path)?;
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        let parent = parent_of(&path);
+        let stale = |key: &String| *key == path || key.starts_with(&prefix);
+
+        let mut state = self.state.lock()?;
+        state.stats.retain(|key, _| !stale(key) && key != parent);
+        state.contents.retain(|key, _| !stale(key));
+        Ok(())
+    }
+
+    /// Drop what is cached for the file at `path` after its contents
+    /// changed, and everything if other links share those contents
+    async fn invalidate_file(&self, path: &str) -> VfsResult<()> {
+        self.invalidate(path)?;
+        if self.stat(path).await.is_ok_and(|stat| stat.nlink > 1) {
+            self.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Remember that `fid` was opened on `path`
+    fn track(&self, fid: u64, path: &str) -> VfsResult<()> {
+        self.state.lock()?.paths.insert(fid, path.to_string());
+        Ok(())
+    }
+
+    /// Path the file behind `handle` is at now, as far as the cache knows
+    fn handle_path<T, M>(&self, handle: &FileHandle<T, M>) -> VfsResult<String> {
+        let state = self.state.lock()?;
+        Ok(state.paths.get(&handle.fid).unwrap_or(&handle.path).clone())
+    }
+
+    /// Move the tracked paths of fids under `from` to `to`
+    fn moved(&self, from: &str, to: &str) -> VfsResult<()> {
+        let (from, to) = (canonicalize(from)?, canonicalize(to)?);
+        let prefix = format!("{}/", from.trim_end_matches('/'));
+        for path in self.state.lock()?.paths.values_mut() {
+            if *path == from || path.starts_with(&prefix) {
+                *path = format!("{}{}", to, &path[from.len()..]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whole contents of the file at `path`, re-read only if its version moved
+    async fn contents(&self, path: &str) -> VfsResult<Arc<Vec<u8>>> {
+        let version = self.stat(path).await?.qid.version;
+        let key = canonicalize(path)?;
+
+        if let Some(cached) = self.state.lock()?.contents.get(&key)
+            && cached.version == version
+        {
+            return Ok(cached.data.clone());
+        }
+
+        let data = Arc::new(self.inner.read_all(path).await?);
+        self.state.lock()?.contents.insert(
+            key,
+            CachedData {
+                version,
+                data: data.clone(),
+            },
+        );
+        Ok(data)
+    }
+}
+
+#[async_trait::async_trait]
+impl<B: VfsBackend> VfsBackend for CacheBackend<B> {
+    async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        self.inner.walk(start, names).await
+    }
+
+    async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        let key = canonicalize(path)?;
+        if let Some(cached) = self.state.lock()?.stats.get(&key)
+            && cached.fetched.elapsed() < self.ttl
+        {
+            return Ok(cached.stat.clone());
+        }
+
+        let stat = self.inner.stat(path).await?;
+        self.state.lock()?.stats.insert(
+            key,
+            CachedStat {
+                stat: stat.clone(),
+                fetched: Instant::now(),
+            },
+        );
+        Ok(stat)
+    }
+
+    async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        let handle = self.inner.open::<M, T>(path, mode).await?;
+        self.track(handle.fid, &handle.path)?;
+        if mode & OTRUNC != 0 {
+            self.invalidate_file(&handle.path).await?;
+        }
+        Ok(handle)
+    }
+
+    async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        let handle = self.inner.create::<M, T>(path, mode).await?;
+        self.track(handle.fid, &handle.path)?;
+        self.invalidate(path)?;
+        Ok(handle)
+    }
+
+    async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        let data = self.contents(&self.handle_path(handle)?).await?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+        let end = start + count.min(data.len() - start);
+        Ok(data[start..end].to_vec())
+    }
+
+    async fn read_into<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let data = self.contents(&self.handle_path(handle)?).await?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        let path = self.handle_path(handle)?;
+        let result = self.inner.write(handle, offset, data).await;
+        self.invalidate_file(&path).await?;
+        result
+    }
+
+    async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        let result = self.inner.remove::<T>(path).await;
+        self.invalidate(path)?;
+        result
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        let result = self.inner.rename(from, to).await;
+        self.invalidate(from)?;
+        self.invalidate(to)?;
+        if result.is_ok() {
+            self.moved(from, to)?;
+        }
+        result
+    }
+
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        self.inner.readdir(handle).await
+    }
+
+    async fn readdirplus<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<DirEntry>> {
+        self.inner.readdirplus(handle).await
+    }
+
+    async fn walk_path(&self, path: &str) -> VfsResult<WalkResult> {
+        self.inner.walk_path(path).await
+    }
+
+    async fn walk_from<M: Send + Sync + 'static>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        names: &[String],
+    ) -> VfsResult<WalkResult> {
+        self.inner.walk_from(handle, names).await
+    }
+
+    async fn reopen<M2, M>(&self, handle: &FileHandle<File, M>, mode: u32) -> VfsResult<FileHandle<File, M2>>
+    where
+        M2: Access,
+        M: Send + Sync + 'static,
+    {
+        let path = self.handle_path(handle)?;
+        let reopened = self.inner.reopen::<M2, M>(handle, mode).await?;
+        self.track(reopened.fid, &path)?;
+        if mode & OTRUNC != 0 {
+            self.invalidate_file(&path).await?;
+        }
+        Ok(reopened)
+    }
+
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.state.lock()?.paths.remove(&fid);
+        self.inner.clunk(fid).await
+    }
+
+    async fn reserve<M: CanWrite>(&self, handle: &FileHandle<File, M>, additional: u64) -> VfsResult<()> {
+        self.inner.reserve(handle, additional).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ReadOnly, ReadWrite};
+    use crate::VfsError;
+    use crate::backends::VfsMem;
+    use crate::backends::memory::VfsEvent;
+    use crate::mode::ORDWR;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Backend counting the stats and reads that reach it
+    #[derive(Default)]
+    struct Counting {
+        inner: VfsMem,
+        stats: AtomicUsize,
+        reads: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl VfsBackend for Counting {
+        async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+            self.inner.walk(start, names).await
+        }
+
+        async fn stat(&self, path: &str) -> VfsResult<Stat> {
+            self.stats.fetch_add(1, Ordering::SeqCst);
+            self.inner.stat(path).await
+        }
+
+        async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Access,
+            T: NodeType,
+        {
+            self.inner.open(path, mode).await
+        }
+
+        async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Send + Sync + 'static,
+            T: NodeType,
+        {
+            self.inner.create(path, mode).await
+        }
+
+        async fn read<M: CanRead>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            count: usize,
+        ) -> VfsResult<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read(handle, offset, count).await
+        }
+
+        async fn write<M: CanWrite>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            data: &[u8],
+        ) -> VfsResult<usize> {
+            self.inner.write(handle, offset, data).await
+        }
+
+        async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+            self.inner.remove::<T>(path).await
+        }
+
+        async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+            self.inner.readdir(handle).await
+        }
+
+        async fn clunk(&self, fid: u64) -> VfsResult<()> {
+            self.inner.clunk(fid).await
+        }
+    }
+
+    impl Counting {
+        fn hits(&self) -> (usize, usize) {
+            (self.stats.load(Ordering::SeqCst), self.reads.load(Ordering::SeqCst))
+        }
+    }
+
+    async fn cached(ttl: Duration) -> CacheBackend<Counting> {
+        let inner = Counting::default();
+        inner.inner.write_all("/f", b"hello world").await.unwrap();
+        CacheBackend::new(inner, ttl)
+    }
+
+    #[tokio::test]
+    async fn test_reads_within_ttl_hit_inner_once() {
+        let cache = cached(Duration::from_secs(60)).await;
+        let handle = cache.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+        assert_eq!(cache.read(&handle, 0, 5).await.unwrap(), b"hello");
+        let after_first = cache.inner().hits();
+        assert_eq!(after_first.0, 1);
+
+        assert_eq!(cache.read(&handle, 6, 100).await.unwrap(), b"world");
+        assert_eq!(cache.stat("/f").await.unwrap().size, 11);
+        assert_eq!(cache.inner().hits(), after_first);
+    }
+
+    #[tokio::test]
+    async fn test_expired_stat_revalidates_by_version() {
+        let cache = cached(Duration::ZERO).await;
+        let handle = cache.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+        cache.read(&handle, 0, 5).await.unwrap();
+        let (stats, reads) = cache.inner().hits();
+
+        // Unchanged version: the stat is redone but the contents are reused
+        cache.read(&handle, 0, 5).await.unwrap();
+        assert_eq!(cache.inner().hits(), (stats + 1, reads));
+
+        // A change behind the cache's back is picked up by version
+        let inner = &cache.inner().inner;
+        let writer = inner.open::<ReadWrite, File>("/f", ORDWR).await.unwrap();
+        inner.write(&writer, 0, b"HELLO").await.unwrap();
+        assert_eq!(cache.read(&handle, 0, 5).await.unwrap(), b"HELLO");
+    }
+
+    #[tokio::test]
+    async fn test_writes_and_removes_invalidate() {
+        let cache = cached(Duration::from_secs(60)).await;
+        assert_eq!(cache.read_all("/f").await.unwrap(), b"hello world");
+
+        let handle = cache.open::<ReadWrite, File>("/f", ORDWR).await.unwrap();
+        cache.write(&handle, 0, b"HELLO").await.unwrap();
+        assert_eq!(cache.read(&handle, 0, 11).await.unwrap(), b"HELLO world");
+        let version = cache.inner().inner.stat("/f").await.unwrap().qid.version;
+        assert_eq!(cache.stat("/f").await.unwrap().qid.version, version);
+        cache.clunk(handle.fid).await.unwrap();
+
+        cache.remove::<File>("/f").await.unwrap();
+        assert!(cache.stat("/f").await.is_err());
+        assert!(!cache.exists("/f").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rename_forwards_and_invalidates() {
+        let vfs = VfsMem::new();
+        vfs.write_all("/a", b"data").await.unwrap();
+        let cache = CacheBackend::new(vfs.clone(), Duration::from_secs(60));
+        assert_eq!(cache.stat("/a").await.unwrap().size, 4);
+
+        let mut events = vfs.subscribe();
+        cache.rename("/a", "/b").await.unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            VfsEvent::Renamed { from: "/a".into(), to: "/b".into() }
+        );
+        assert!(matches!(cache.stat("/a").await, Err(VfsError::NotFound(_))));
+        assert_eq!(cache.read_all("/b").await.unwrap(), b"data");
+
+        // A backend without rename keeps refusing it through the cache
+        let plain = cached(Duration::from_secs(60)).await;
+        assert!(matches!(plain.rename("/f", "/g").await, Err(VfsError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_handles_follow_renames_and_links() {
+        let vfs = VfsMem::new();
+        vfs.write_all("/a", b"data").await.unwrap();
+        vfs.link("/a", "/link").await.unwrap();
+        let cache = CacheBackend::new(vfs.clone(), Duration::from_secs(60));
+
+        let handle = cache.open::<ReadWrite, File>("/a", ORDWR).await.unwrap();
+        assert_eq!(cache.read(&handle, 0, 4).await.unwrap(), b"data");
+        cache.rename("/a", "/b").await.unwrap();
+        vfs.write_all("/a", b"other").await.unwrap();
+        assert_eq!(cache.read(&handle, 0, 4).await.unwrap(), b"data");
+
+        // The write lands on the inode "/link" shares, so its copy goes too
+        assert_eq!(cache.read_all("/link").await.unwrap(), b"data");
+        cache.write(&handle, 0, b"DATA").await.unwrap();
+        assert_eq!(cache.read_all("/link").await.unwrap(), b"DATA");
+        let mut buf = [0; 4];
+        assert_eq!(cache.read_into(&handle, 0, &mut buf).await.unwrap(), 4);
+        assert_eq!(&buf, b"DATA");
+    }
+}
@@ -0,0 +1,228 @@
+//! Encryption-at-rest backend decorator.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::backend::{VfsBackend, VfsBackendExt};
+use crate::error::{VfsError, VfsResult};
+use crate::types::{Access, Dir, File, FileHandle, NodeKind, NodeType, Stat, WalkResult};
+use crate::{CanRead, CanWrite};
+
+/// Bytes of nonce stored in front of each file's ciphertext
+const NONCE_LEN: usize = 12;
+
+/// Bytes of authentication tag appended to each file's ciphertext
+const TAG_LEN: usize = 16;
+
+/// Plaintext length of a stored file of `stored` bytes
+fn plain_len(stored: u64) -> u64 {
+    stored.saturating_sub((NONCE_LEN + TAG_LEN) as u64)
+}
+
+/// Backend wrapper that keeps file contents encrypted in the inner backend.
+///
+/// Each file is stored as `nonce || ciphertext || tag` using
+/// ChaCha20-Poly1305, with a fresh random nonce on every write. Names and
+/// metadata are not encrypted. Sizes reported by `stat` and `readdir` are
+/// plaintext sizes, derived from the stored length.
+///
+/// The whole file is sealed as one message, so every `read` decrypts the
+/// entire file and every `write` decrypts, patches, and re-encrypts it.
+/// This suits small files; a partial read of a large file costs a full one.
+pub struct EncryptedBackend<B> {
+    inner: B,
+    cipher: ChaCha20Poly1305,
+}
+
+impl<B: VfsBackend> EncryptedBackend<B> {
+    /// Wrap `inner`, encrypting contents with `key`
+    pub fn new(inner: B, key: [u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// Get the wrapped backend, which holds only ciphertext
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Read and decrypt the whole file at `path`
+    async fn load(&self, path: &str) -> VfsResult<Vec<u8>> {
+        let stored = self.inner.read_all(path).await?;
+        if stored.is_empty() {
+            return Ok(Vec::new());
+        }
+        if stored.len() < NONCE_LEN + TAG_LEN {
+            return Err(VfsError::InvalidArgument("decryption failed".into()));
+        }
+
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| VfsError::InvalidArgument("decryption failed".into()))
+    }
+
+    /// Encrypt `plain` under a fresh nonce
+    fn seal(&self, plain: &[u8]) -> VfsResult<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| VfsError::InvalidArgument("encryption failed".into()))?;
+
+        let mut stored = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        stored.extend_from_slice(&nonce);
+        stored.extend_from_slice(&ciphertext);
+        Ok(stored)
+    }
+
+    /// Report the plaintext size of files
+    fn plain_stat(stat: Stat) -> Stat {
+        if stat.is_dir() {
+            return stat;
+        }
+        Stat {
+            size: plain_len(stat.size),
+            ..stat
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<B: VfsBackend> VfsBackend for EncryptedBackend<B> {
+    async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        self.inner.walk(start, names).await
+    }
+
+    async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        self.inner.stat(path).await.map(Self::plain_stat)
+    }
+
+    async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        self.inner.open::<M, T>(path, mode).await
+    }
+
+    async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        self.inner.create::<M, T>(path, mode).await
+    }
+
+    async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        let plain = self.load(&handle.path).await?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(plain.len());
+        let end = start.saturating_add(count).min(plain.len());
+        Ok(plain[start..end].to_vec())
+    }
+
+    async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        let mut plain = self.load(&handle.path).await?;
+        let offset = usize::try_from(offset).map_err(|_| VfsError::BadOffset)?;
+        if plain.len() < offset + data.len() {
+            plain.resize(offset + data.len(), 0);
+        }
+        plain[offset..offset + data.len()].copy_from_slice(data);
+
+        // Plaintext never shrinks here, so the new ciphertext covers the old
+        let stored = self.seal(&plain)?;
+        let mut written = 0;
+        while written < stored.len() {
+            written += self
+                .inner
+                .write(handle, written as u64, &stored[written..])
+                .await?;
+        }
+        Ok(data.len())
+    }
+
+    async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        self.inner.remove::<T>(path).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.inner.rename(from, to).await
+    }
+
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        let stats = self.inner.readdir(handle).await?;
+        Ok(stats.into_iter().map(Self::plain_stat).collect())
+    }
+
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.inner.clunk(fid).await
+    }
+
+    async fn kind(&self, path: &str) -> VfsResult<NodeKind> {
+        self.inner.kind(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::VfsMem;
+    use crate::types::{ReadOnly, ReadWrite};
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[tokio::test]
+    async fn test_inner_holds_ciphertext() {
+        let backend = EncryptedBackend::new(VfsMem::new(), KEY);
+        let secret = b"attack at dawn";
+
+        let fh = backend.create::<ReadWrite, File>("/secret", 0o600).await.unwrap();
+        backend.write(&fh, 0, secret).await.unwrap();
+
+        let stored = backend.inner().read_all("/secret").await.unwrap();
+        assert_eq!(stored.len(), secret.len() + NONCE_LEN + TAG_LEN);
+        assert!(!stored.windows(secret.len()).any(|w| w == secret));
+
+        assert_eq!(backend.read(&fh, 0, 100).await.unwrap(), secret);
+        assert_eq!(backend.read(&fh, 7, 2).await.unwrap(), b"at");
+        assert_eq!(backend.stat("/secret").await.unwrap().size, secret.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_partial_writes_and_listing() {
+        let backend = EncryptedBackend::new(VfsMem::new(), KEY);
+        let fh = backend.create::<ReadWrite, File>("/f", 0o600).await.unwrap();
+        backend.write(&fh, 0, b"hello world").await.unwrap();
+        backend.write(&fh, 6, b"there, world").await.unwrap();
+        assert_eq!(backend.read_to_string("/f").await.unwrap(), "hello there, world");
+
+        let dir = backend.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+        let stats = backend.readdir(&dir).await.unwrap();
+        assert_eq!(stats[0].size, 18);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_fails() {
+        let backend = EncryptedBackend::new(VfsMem::new(), KEY);
+        let fh = backend.create::<ReadWrite, File>("/f", 0o600).await.unwrap();
+        backend.write(&fh, 0, b"data").await.unwrap();
+
+        let other = EncryptedBackend::new(backend.inner().clone(), [8; 32]);
+        assert!(matches!(
+            other.read_all("/f").await,
+            Err(VfsError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,374 @@
+//! Tar archive import and export for the in-memory filesystem.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{VfsError, VfsResult};
+use crate::mode::OREAD;
+use crate::types::NodeKind;
+
+use super::fs::{VfsMem, touch_parent};
+use super::index::NodeMap;
+use super::journal::Record;
+use super::node::Node;
+
+/// One archive member, copied out of the tree so the archive can be written
+/// without holding the lock
+struct ExportEntry {
+    rel: String,
+    mode: u32,
+    mtime: SystemTime,
+    /// File contents, or `None` for a directory
+    data: Option<Vec<u8>>,
+}
+
+impl ExportEntry {
+    fn of(rel: String, node: &Node) -> Self {
+        ExportEntry {
+            rel,
+            mode: node.mode(),
+            mtime: node.mtime(),
+            data: node.inode().map(|inode| inode.data.to_vec()),
+        }
+    }
+}
+
+/// Convert an archive member path into an absolute VFS path
+fn archive_path(raw: &str) -> String {
+    let clean: Vec<&str> = raw
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    format!("/{}", clean.join("/"))
+}
+
+/// Convert tar header seconds into a `SystemTime`
+fn header_mtime(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Convert a `SystemTime` into tar header seconds
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// What [`VfsMem::import_tar_with`] does with an entry whose path exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// Keep the existing node and skip the entry
+    Merge,
+    /// Replace the existing node with the entry
+    Overwrite,
+    /// Fail before importing anything
+    Fail,
+}
+
+/// Number of archive entries an import added, overwrote, and skipped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub added: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+}
+
+impl VfsMem {
+    /// Load every file and directory of a tar archive into the filesystem.
+    ///
+    /// Member paths are normalized (`..` is rejected) and missing parent
+    /// directories are created. Existing nodes of the same kind are replaced.
+    /// Entry types other than files and directories are skipped.
+    /// Returns the number of entries loaded.
+    pub async fn import_tar<R: Read>(&self, reader: R) -> VfsResult<usize> {
+        let report = self.import_tar_with(reader, ImportPolicy::Overwrite).await?;
+        Ok(report.added + report.overwritten)
+    }
+
+    /// Like [`import_tar`](Self::import_tar), choosing what happens when an
+    /// entry's path already exists.
+    ///
+    /// With `Fail`, the first such path is returned as `AlreadyExists`
+    /// before anything is imported. With `Overwrite`, an entry whose kind
+    /// differs from the existing node fails the same way; with `Merge` it is
+    /// skipped like any other collision. An entry below a file fails with
+    /// `NotADirectory`, also before anything is imported.
+    ///
+    /// Imported directories keep the mtimes from their headers even when
+    /// later entries are added to them.
+    pub async fn import_tar_with<R: Read>(
+        &self,
+        reader: R,
+        policy: ImportPolicy,
+    ) -> VfsResult<ImportReport> {
+        let entries = self.read_archive(reader)?;
+        let mut nodes = self.write_nodes("import_tar", "/")?;
+
+        self.check_import(&nodes, &entries, policy)?;
+
+        let mut report = ImportReport::default();
+        let mut dir_mtimes = Vec::new();
+        for (path, node) in entries {
+            self.ensure_ancestors(&path, &mut nodes)?;
+            let existing = match self.lookup(&nodes, &path) {
+                Some(_) if policy == ImportPolicy::Merge => {
+                    report.skipped += 1;
+                    continue;
+                }
+                Some(existing) => {
+                    report.overwritten += 1;
+                    Some(existing.kind())
+                }
+                None => {
+                    report.added += 1;
+                    None
+                }
+            };
+            self.journal_import(&path, &node, existing)?;
+            match &node {
+                Node::Dir { mtime, .. } => dir_mtimes.push((path.clone(), *mtime)),
+                Node::File { .. } => self.touch(&path)?,
+            }
+            nodes.insert(path.clone(), node);
+            touch_parent(&mut nodes, &path, self.now());
+        }
+
+        // Adding children stamped the directories; put their own mtimes back
+        for (path, header_mtime) in dir_mtimes {
+            if let Some(Node::Dir { mtime, .. }) = nodes.get_mut(&path) {
+                *mtime = header_mtime;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Check that every entry of an import can be applied to `nodes`, so a
+    /// failing import changes nothing
+    fn check_import(
+        &self,
+        nodes: &NodeMap,
+        entries: &[(String, Node)],
+        policy: ImportPolicy,
+    ) -> VfsResult<()> {
+        // Kinds of the paths the import will have created so far
+        let mut planned: HashMap<&str, NodeKind> = HashMap::new();
+        let kind_at = |planned: &HashMap<&str, NodeKind>, path: &str| {
+            planned
+                .get(path)
+                .copied()
+                .or_else(|| self.lookup(nodes, path).map(Node::kind))
+        };
+
+        for (path, node) in entries {
+            let conflict = match (self.lookup(nodes, path), policy) {
+                (Some(_), ImportPolicy::Fail) => true,
+                (Some(existing), ImportPolicy::Overwrite) => existing.kind() != node.kind(),
+                _ => false,
+            };
+            if conflict {
+                return Err(VfsError::AlreadyExists(path.clone()));
+            }
+
+            for (end, _) in path.match_indices('/').skip(1) {
+                let ancestor = &path[..end];
+                match kind_at(&planned, ancestor) {
+                    Some(NodeKind::Dir) => {}
+                    Some(NodeKind::File) => {
+                        return Err(VfsError::NotADirectory(ancestor.to_string()));
+                    }
+                    None => {
+                        planned.insert(ancestor, NodeKind::Dir);
+                    }
+                }
+            }
+            match kind_at(&planned, path) {
+                Some(_) if policy == ImportPolicy::Merge => {}
+                Some(kind) if kind != node.kind() => {
+                    return Err(VfsError::AlreadyExists(path.clone()));
+                }
+                _ => {
+                    planned.insert(path, node.kind());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create any missing ancestor directories of `path`
+    fn ensure_ancestors(&self, path: &str, nodes: &mut NodeMap) -> VfsResult<()> {
+        let mut current = String::new();
+        let components: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+        for component in &components[..components.len() - 1] {
+            current.push('/');
+            current.push_str(component);
+            match self.lookup(nodes, &current) {
+                Some(node) if node.is_dir() => {}
+                Some(_) => return Err(VfsError::NotADirectory(current)),
+                None => {
+                    self.journal(|| Record::Create {
+                        path: current.clone(),
+                        kind: NodeKind::Dir,
+                        mode: OREAD,
+                        perm: None,
+                    })?;
+                    nodes.insert(current.clone(), Node::new_dir(self.now()));
+                    touch_parent(nodes, &current, self.now());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Journal importing `node` at `path` over a node of kind `existing`
+    fn journal_import(&self, path: &str, node: &Node, existing: Option<NodeKind>) -> VfsResult<()> {
+        let Some(inode) = node.inode() else {
+            // Replacing a directory keeps its entries, so only the mode changes
+            return match existing {
+                Some(_) => self.journal(|| Record::Chmod {
+                    path: path.to_string(),
+                    mode: node.mode(),
+                }),
+                None => self.journal(|| Record::Create {
+                    path: path.to_string(),
+                    kind: NodeKind::Dir,
+                    mode: OREAD,
+                    perm: Some(node.mode()),
+                }),
+            };
+        };
+        if existing.is_some() {
+            self.journal(|| Record::Remove { path: path.to_string() })?;
+        }
+        self.journal(|| Record::Create {
+            path: path.to_string(),
+            kind: NodeKind::File,
+            mode: OREAD,
+            perm: Some(inode.mode),
+        })?;
+        if inode.data.len() > 0 {
+            self.journal(|| Record::write(path, 0, &[&inode.data.to_vec()]))?;
+        }
+        Ok(())
+    }
+
+    /// Read every file and directory entry of a tar archive, by path
+    fn read_archive<R: Read>(&self, reader: R) -> VfsResult<Vec<(String, Node)>> {
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = Vec::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let raw = entry.path()?;
+            let raw = raw
+                .to_str()
+                .ok_or_else(|| VfsError::InvalidPath("non UTF-8 archive path".into()))?;
+            let path = self.resolve_path(&archive_path(raw))?;
+            if path == "/" {
+                continue;
+            }
+
+            let header = entry.header();
+            let mode = header.mode()? & 0o7777;
+            let mtime = header_mtime(header.mtime()?);
+            let entry_type = header.entry_type();
+
+            if entry_type.is_dir() {
+                entries.push((
+                    path,
+                    Node::Dir {
+                        mtime,
+                        mode,
+                        version: 0,
+                        owner: self.default_owner.clone(),
+                        changed: 0,
+                        generation: 0,
+                    },
+                ));
+            } else if entry_type.is_file() {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                // Store the contents the way new files are, deduplicated or
+                // compressed if configured
+                let mut node = self.new_file_node()?;
+                if let Node::File { inode, .. } = &node {
+                    let mut inode = inode.write()?;
+                    inode.data.write(0, &data)?;
+                    inode.mtime = mtime;
+                }
+                node.set_mode(mode)?;
+                node.set_owner(self.default_owner.clone())?;
+                entries.push((path, node));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Write the subtree under `root` as a tar archive.
+    ///
+    /// Member paths are relative to `root`, directories are emitted before
+    /// their contents, and sizes, modes, and mtimes come from the nodes.
+    /// A file `root` produces a single entry named after the file. Expired
+    /// files are left out.
+    pub async fn export_tar<W: Write>(&self, root: &str, writer: W) -> VfsResult<()> {
+        let root = self.resolve_path(root)?;
+
+        // Snapshot entries under the lock, write the archive without it
+        let mut entries: Vec<ExportEntry> = {
+            let nodes = self.read_nodes("export_tar", &root)?;
+            let now = self.now();
+            let root_node = self
+                .lookup(&nodes, &root)
+                .ok_or_else(|| VfsError::NotFound(root.clone()))?;
+
+            if root_node.is_file() {
+                let name = root.rsplit('/').next().unwrap_or(&root).to_string();
+                vec![ExportEntry::of(name, root_node)]
+            } else {
+                let prefix = if root == "/" {
+                    "/".to_string()
+                } else {
+                    format!("{}/", root)
+                };
+                nodes
+                    .iter()
+                    .filter(|(_, node)| !node.is_expired(now))
+                    .filter_map(|(path, node)| {
+                        path.strip_prefix(&prefix)
+                            .filter(|rel| !rel.is_empty())
+                            .map(|rel| ExportEntry::of(rel.to_string(), node))
+                    })
+                    .collect()
+            }
+        };
+        entries.sort_by(|a, b| a.rel.cmp(&b.rel));
+
+        let mut builder = tar::Builder::new(writer);
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(entry.mode);
+            header.set_mtime(unix_secs(entry.mtime));
+
+            match entry.data {
+                Some(data) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(data.len() as u64);
+                    builder.append_data(&mut header, &entry.rel, &data[..])?;
+                }
+                None => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_size(0);
+                    builder.append_data(&mut header, format!("{}/", entry.rel), std::io::empty())?;
+                }
+            }
+        }
+        builder.finish()?;
+
+        Ok(())
+    }
+}
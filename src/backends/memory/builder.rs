@@ -0,0 +1,67 @@
+//! Construction-time configuration of the in-memory filesystem.
+
+use super::fs::VfsMem;
+use super::node::{Node, Owner};
+
+/// Settings applied while a [`VfsMem`] is created.
+///
+/// Everything that can be changed afterwards stays a `with_*` method on
+/// `VfsMem` itself, so the two chain:
+/// `VfsMem::builder().root_mode(0o700).build().with_strict_offsets()`.
+#[derive(Debug, Clone)]
+pub struct VfsMemBuilder {
+    root_mode: u32,
+    root_owner: Owner,
+}
+
+impl Default for VfsMemBuilder {
+    fn default() -> Self {
+        Self {
+            root_mode: 0o755,
+            root_owner: Owner::default(),
+        }
+    }
+}
+
+impl VfsMemBuilder {
+    /// Permission bits of the root directory (default `0o755`)
+    pub fn root_mode(mut self, mode: u32) -> Self {
+        self.root_mode = mode & 0o7777;
+        self
+    }
+
+    /// User and group owning the root directory
+    pub fn root_owner(mut self, uid: impl Into<String>, gid: impl Into<String>) -> Self {
+        self.root_owner = Owner {
+            uid: uid.into(),
+            gid: gid.into(),
+        };
+        self
+    }
+
+    /// Create the filesystem
+    pub fn build(self) -> VfsMem {
+        let vfs = VfsMem::new();
+        if let Ok(mut nodes) = vfs.nodes.write() {
+            nodes.insert(
+                "/".to_string(),
+                Node::Dir {
+                    mtime: vfs.now(),
+                    mode: self.root_mode,
+                    version: 0,
+                    owner: self.root_owner,
+                    changed: 0,
+                    generation: 0,
+                },
+            );
+        }
+        vfs
+    }
+}
+
+impl VfsMem {
+    /// Start configuring a filesystem whose root isn't the default one
+    pub fn builder() -> VfsMemBuilder {
+        VfsMemBuilder::default()
+    }
+}
@@ -0,0 +1,45 @@
+//! Change tokens for polling the in-memory filesystem for modifications.
+
+use crate::error::{VfsError, VfsResult};
+use crate::types::ChangeToken;
+
+use super::fs::{VfsMem, join_path};
+
+impl VfsMem {
+    /// A token for the current state, to pass to `changed_since` later
+    pub fn change_token(&self) -> VfsResult<ChangeToken> {
+        Ok(ChangeToken::new(self.read_nodes("change_token", "/")?.generation()))
+    }
+
+    /// Whether anything at or below `path` changed after `token` was issued.
+    ///
+    /// Creates, removes, renames, writes, truncation, and ownership changes
+    /// all count. Files expiring by TTL don't, since nothing is modified
+    /// when they do. Also returns a fresh token to poll with next time. A
+    /// `path` that no longer exists is `NotFound`.
+    pub async fn changed_since(
+        &self,
+        path: &str,
+        token: ChangeToken,
+    ) -> VfsResult<(bool, ChangeToken)> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("changed_since", &path)?;
+        // Taken before the scan, so a change racing with it shows up next time
+        let fresh = ChangeToken::new(nodes.generation());
+
+        let mut pending = vec![path.clone()];
+        let mut latest = 0;
+        while let Some(current) = pending.pop() {
+            let Some(node) = self.lookup(&nodes, &current) else {
+                if current == path {
+                    return Err(VfsError::NotFound(path));
+                }
+                continue;
+            };
+            latest = latest.max(node.changed());
+            pending.extend(nodes.children(&current).map(|name| join_path(&current, name)));
+        }
+
+        Ok((latest > token.generation(), fresh))
+    }
+}
@@ -0,0 +1,432 @@
+//! File content storage for the in-memory filesystem.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock, Weak};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::error::{VfsError, VfsResult};
+
+/// Size of a deduplicated block in bytes
+pub(super) const BLOCK_SIZE: usize = 4096;
+
+/// Smallest gap a flat write may leave before the file turns sparse
+const SPARSE_GAP: usize = BLOCK_SIZE;
+
+/// Content-addressed block store shared by every deduplicated file.
+///
+/// The store only holds weak references, so a block is freed as soon as no
+/// file uses it anymore.
+pub(super) type BlockStore = Arc<RwLock<HashMap<[u8; 32], Weak<Vec<u8>>>>>;
+
+/// Contents of a file node
+#[derive(Debug, Clone)]
+pub(super) enum FileData {
+    /// Contiguous bytes, shared with any reads still holding a slice of them.
+    ///
+    /// `capacity` is that of the buffer behind `bytes`, which `Bytes` can't
+    /// report itself.
+    Flat { bytes: Bytes, capacity: usize },
+    /// Fixed-size blocks interned in a shared store
+    Blocks {
+        blocks: Vec<Arc<Vec<u8>>>,
+        len: usize,
+        store: BlockStore,
+    },
+    /// Written extents keyed by offset; everything else reads as zeros.
+    ///
+    /// Extents never overlap or touch, so each maximal written run is one
+    /// entry.
+    Sparse {
+        extents: BTreeMap<usize, Vec<u8>>,
+        len: usize,
+    },
+    /// Deflate-compressed contents, rewritten whole on every change
+    #[cfg(feature = "compression")]
+    Compressed { bytes: Vec<u8>, len: usize },
+}
+
+/// Return the stored block with the same content, or add this one
+fn intern(store: &BlockStore, bytes: Vec<u8>) -> VfsResult<Arc<Vec<u8>>> {
+    let hash: [u8; 32] = Sha256::digest(&bytes).into();
+    let mut blocks = store.write()?;
+
+    if let Some(existing) = blocks.get(&hash).and_then(Weak::upgrade) {
+        return Ok(existing);
+    }
+
+    let block = Arc::new(bytes);
+    blocks.insert(hash, Arc::downgrade(&block));
+    Ok(block)
+}
+
+/// Number of distinct live blocks in a store, dropping dead entries
+pub(super) fn live_blocks(store: &BlockStore) -> VfsResult<usize> {
+    let mut blocks = store.write()?;
+    blocks.retain(|_, block| block.strong_count() > 0);
+    Ok(blocks.len())
+}
+
+#[cfg(feature = "compression")]
+fn compress(plain: &[u8]) -> VfsResult<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(plain)?;
+    Ok(encoder.finish()?)
+}
+
+/// Inflate contents this module deflated itself
+#[cfg(feature = "compression")]
+fn decompress(bytes: &[u8]) -> Vec<u8> {
+    use std::io::Read;
+
+    let mut plain = Vec::new();
+    flate2::read::DeflateDecoder::new(bytes)
+        .read_to_end(&mut plain)
+        .expect("in-memory deflate stream is valid");
+    plain
+}
+
+impl FileData {
+    /// Create empty compressed contents
+    #[cfg(feature = "compression")]
+    pub fn new_compressed() -> VfsResult<Self> {
+        Ok(FileData::Compressed {
+            bytes: compress(&[])?,
+            len: 0,
+        })
+    }
+
+    /// Create empty block-backed contents using `store`
+    pub fn new_blocks(store: BlockStore) -> Self {
+        FileData::Blocks {
+            blocks: Vec::new(),
+            len: 0,
+            store,
+        }
+    }
+
+    /// Logical length in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            FileData::Flat { bytes, .. } => bytes.len(),
+            FileData::Blocks { len, .. } | FileData::Sparse { len, .. } => *len,
+            #[cfg(feature = "compression")]
+            FileData::Compressed { len, .. } => *len,
+        }
+    }
+
+    /// Bytes actually stored, excluding holes
+    pub fn allocated(&self) -> usize {
+        match self {
+            FileData::Flat { bytes, .. } => bytes.len(),
+            FileData::Blocks { blocks, .. } => blocks.iter().map(|b| b.len()).sum(),
+            FileData::Sparse { extents, .. } => extents.values().map(Vec::len).sum(),
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, .. } => bytes.len(),
+        }
+    }
+
+    /// Heap bytes held for the contents, counting spare capacity.
+    ///
+    /// Blocks are counted in full even when other files share them.
+    pub fn capacity(&self) -> usize {
+        match self {
+            FileData::Flat { capacity, .. } => *capacity,
+            FileData::Blocks { blocks, .. } => blocks.iter().map(|b| b.capacity()).sum(),
+            FileData::Sparse { extents, .. } => extents.values().map(Vec::capacity).sum(),
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, .. } => bytes.capacity(),
+        }
+    }
+
+    /// Whether any part of the file is an unallocated hole
+    pub fn is_sparse(&self) -> bool {
+        matches!(self, FileData::Sparse { .. }) && self.allocated() < self.len()
+    }
+
+    /// Read up to `count` bytes at `offset`; empty past the end
+    pub fn read(&self, offset: usize, count: usize) -> Vec<u8> {
+        let len = self.len();
+        if offset >= len {
+            return Vec::new();
+        }
+        let end = offset + std::cmp::min(count, len - offset);
+
+        match self {
+            FileData::Flat { bytes, .. } => bytes[offset..end].to_vec(),
+            FileData::Blocks { blocks, .. } => {
+                let mut out = Vec::with_capacity(end - offset);
+                let mut pos = offset;
+                while pos < end {
+                    let block = &blocks[pos / BLOCK_SIZE];
+                    let within = pos % BLOCK_SIZE;
+                    let take = std::cmp::min(block.len() - within, end - pos);
+                    out.extend_from_slice(&block[within..within + take]);
+                    pos += take;
+                }
+                out
+            }
+            FileData::Sparse { extents, .. } => {
+                let mut out = vec![0; end - offset];
+                for (&start, extent) in extents.range(..end) {
+                    let from = std::cmp::max(start, offset);
+                    let to = std::cmp::min(start + extent.len(), end);
+                    if from < to {
+                        out[from - offset..to - offset].copy_from_slice(&extent[from - start..to - start]);
+                    }
+                }
+                out
+            }
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, .. } => decompress(bytes)[offset..end].to_vec(),
+        }
+    }
+
+    /// Write `data` at `offset`, zero-filling any gap past the end.
+    ///
+    /// A flat file written well past its end becomes sparse instead, so the
+    /// gap costs nothing.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> VfsResult<()> {
+        // Writing nothing never extends the file, however far out
+        if data.is_empty() {
+            return Ok(());
+        }
+        if let FileData::Flat { bytes, .. } = self
+            && offset >= bytes.len() + SPARSE_GAP
+        {
+            let flat = Vec::from(std::mem::take(bytes));
+            let len = flat.len();
+            let mut extents = BTreeMap::new();
+            if len > 0 {
+                extents.insert(0, flat);
+            }
+            *self = FileData::Sparse { extents, len };
+        }
+
+        match self {
+            FileData::Flat { bytes, capacity } => {
+                // Reuses the buffer unless a read still holds a slice of it
+                let mut file_data = Vec::from(std::mem::take(bytes));
+                if offset + data.len() > file_data.len() {
+                    file_data.resize(offset + data.len(), 0);
+                }
+                file_data[offset..offset + data.len()].copy_from_slice(data);
+                *capacity = file_data.capacity();
+                *bytes = file_data.into();
+            }
+            FileData::Blocks { blocks, len, store } => {
+                let end = offset + data.len();
+                // Rewrite every block overlapping the written range or the gap
+                let first = std::cmp::min(offset, *len);
+                if end <= first {
+                    return Ok(());
+                }
+                let new_len = std::cmp::max(*len, end);
+
+                for idx in first / BLOCK_SIZE..=(end - 1) / BLOCK_SIZE {
+                    let block_start = idx * BLOCK_SIZE;
+                    let block_end = std::cmp::min(block_start + BLOCK_SIZE, new_len);
+                    let mut buf = vec![0; block_end - block_start];
+
+                    if let Some(old) = blocks.get(idx) {
+                        buf[..old.len()].copy_from_slice(old);
+                    }
+
+                    let from = std::cmp::max(offset, block_start);
+                    let to = std::cmp::min(end, block_end);
+                    if from < to {
+                        buf[from - block_start..to - block_start]
+                            .copy_from_slice(&data[from - offset..to - offset]);
+                    }
+
+                    let block = intern(store, buf)?;
+                    if idx < blocks.len() {
+                        blocks[idx] = block;
+                    } else {
+                        blocks.push(block);
+                    }
+                }
+                *len = new_len;
+            }
+            FileData::Sparse { extents, len } => {
+                if data.is_empty() {
+                    return Ok(());
+                }
+                let end = offset + data.len();
+
+                // Merge with every extent overlapping or touching the range
+                let merged: Vec<usize> = extents
+                    .range(..=end)
+                    .filter(|(start, extent)| *start + extent.len() >= offset)
+                    .map(|(start, _)| *start)
+                    .collect();
+                let new_start = merged.first().map_or(offset, |s| std::cmp::min(*s, offset));
+                let mut buf = Vec::new();
+                for start in merged {
+                    if let Some(extent) = extents.remove(&start) {
+                        let at = start - new_start;
+                        if buf.len() < at + extent.len() {
+                            buf.resize(at + extent.len(), 0);
+                        }
+                        buf[at..at + extent.len()].copy_from_slice(&extent);
+                    }
+                }
+                if buf.len() < end - new_start {
+                    buf.resize(end - new_start, 0);
+                }
+                buf[offset - new_start..end - new_start].copy_from_slice(data);
+                extents.insert(new_start, buf);
+                *len = std::cmp::max(*len, end);
+            }
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, len } => {
+                let mut plain = FileData::from(decompress(bytes));
+                plain.write(offset, data)?;
+                *len = plain.len();
+                *bytes = compress(&plain.to_vec())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy `len` bytes from `src` to `dst`, which may overlap, extending
+    /// the contents if the destination runs past the end.
+    ///
+    /// The source range must lie within the contents. Flat contents are
+    /// moved in place; the other layouts copy through a buffer.
+    pub fn copy_within(&mut self, src: usize, dst: usize, len: usize) -> VfsResult<()> {
+        match self {
+            FileData::Flat { bytes, capacity } => {
+                let mut data = Vec::from(std::mem::take(bytes));
+                if dst + len > data.len() {
+                    data.resize(dst + len, 0);
+                }
+                data.copy_within(src..src + len, dst);
+                *capacity = data.capacity();
+                *bytes = data.into();
+                Ok(())
+            }
+            _ => {
+                let bytes = self.read(src, len);
+                self.write(dst, &bytes)
+            }
+        }
+    }
+
+    /// Make room for `additional` more bytes without changing the contents.
+    ///
+    /// Only flat contents preallocate; the other layouts grow piecewise.
+    pub fn reserve(&mut self, additional: usize) -> VfsResult<()> {
+        if let FileData::Flat { bytes, capacity } = self {
+            let mut data = Vec::from(std::mem::take(bytes));
+            let reserved = data.try_reserve(additional);
+            *capacity = data.capacity();
+            *bytes = data.into();
+            reserved.map_err(|e| VfsError::InvalidArgument(format!("cannot reserve {} bytes: {}", additional, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Release spare buffer capacity, returning how many bytes were freed.
+    ///
+    /// Shared blocks are left alone.
+    pub fn shrink_to_fit(&mut self) -> usize {
+        let before = self.capacity();
+        match self {
+            FileData::Flat { bytes, capacity } => {
+                let mut data = Vec::from(std::mem::take(bytes));
+                data.shrink_to_fit();
+                *capacity = data.capacity();
+                *bytes = data.into();
+            }
+            FileData::Blocks { .. } => {}
+            FileData::Sparse { extents, .. } => extents.values_mut().for_each(Vec::shrink_to_fit),
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, .. } => bytes.shrink_to_fit(),
+        }
+        before.saturating_sub(self.capacity())
+    }
+
+    /// Shrink or zero-extend the contents to `new_len` bytes
+    pub fn truncate(&mut self, new_len: usize) -> VfsResult<()> {
+        match self {
+            FileData::Sparse { extents, len } => {
+                // Growing just makes a longer hole
+                extents.retain(|start, _| *start < new_len);
+                if let Some((start, extent)) = extents.iter_mut().next_back() {
+                    extent.truncate(new_len - start);
+                }
+                *len = new_len;
+            }
+            _ if new_len > self.len() => self.write(new_len - 1, &[0])?,
+            FileData::Flat { bytes, .. } => bytes.truncate(new_len),
+            FileData::Blocks { blocks, len, store } => {
+                blocks.truncate(new_len.div_ceil(BLOCK_SIZE));
+                if let Some(last) = blocks.last_mut() {
+                    let keep = new_len - (new_len - 1) / BLOCK_SIZE * BLOCK_SIZE;
+                    if last.len() > keep {
+                        *last = intern(store, last[..keep].to_vec())?;
+                    }
+                }
+                *len = new_len;
+            }
+            #[cfg(feature = "compression")]
+            FileData::Compressed { bytes, len } => {
+                let mut plain = decompress(bytes);
+                plain.truncate(new_len);
+                *bytes = compress(&plain)?;
+                *len = new_len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read up to `count` bytes at `offset` without copying flat contents.
+    ///
+    /// Flat contents hand out a slice sharing their buffer; the other
+    /// layouts assemble a fresh one.
+    pub fn read_bytes(&self, offset: usize, count: usize) -> Bytes {
+        match self {
+            FileData::Flat { bytes, .. } => {
+                let start = offset.min(bytes.len());
+                bytes.slice(start..start + count.min(bytes.len() - start))
+            }
+            _ => self.read(offset, count).into(),
+        }
+    }
+
+    /// Copy up to `buf.len()` bytes at `offset` into `buf`, returning how
+    /// many were copied; 0 past the end
+    pub fn read_into(&self, offset: usize, buf: &mut [u8]) -> usize {
+        match self {
+            FileData::Flat { bytes, .. } => {
+                let start = offset.min(bytes.len());
+                let n = buf.len().min(bytes.len() - start);
+                buf[..n].copy_from_slice(&bytes[start..start + n]);
+                n
+            }
+            _ => {
+                let data = self.read(offset, buf.len());
+                buf[..data.len()].copy_from_slice(&data);
+                data.len()
+            }
+        }
+    }
+
+    /// Copy the full contents into a contiguous buffer
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.read(0, self.len())
+    }
+}
+
+impl From<Vec<u8>> for FileData {
+    fn from(data: Vec<u8>) -> Self {
+        FileData::Flat {
+            capacity: data.capacity(),
+            bytes: data.into(),
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Incremental snapshots of the in-memory filesystem.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{VfsError, VfsResult};
+use crate::mode::OREAD;
+use crate::types::NodeKind;
+
+use super::fs::{VfsMem, parent_path};
+use super::index::NodeMap;
+use super::journal::Record;
+use super::node::Node;
+
+/// The paths of a filesystem at one moment, as a base for
+/// [`VfsMem::snapshot_since`].
+///
+/// Holds no file contents, only each path's node identity and the change
+/// generation when it was taken, so it is cheap to take and to keep.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VfsSnapshot {
+    generation: u64,
+    /// Creation generation of the node at each path
+    nodes: HashMap<String, u64>,
+}
+
+impl VfsSnapshot {
+    /// Number of paths recorded, the root included
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether no paths are recorded; never true for a real snapshot
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// New state of one path in a [`VfsDelta`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeltaEntry {
+    Dir { mode: u32 },
+    File { mode: u32, data: Vec<u8> },
+}
+
+impl DeltaEntry {
+    /// Kind of node this entry describes
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            DeltaEntry::Dir { .. } => NodeKind::Dir,
+            DeltaEntry::File { .. } => NodeKind::File,
+        }
+    }
+
+    fn of(node: &Node) -> Self {
+        match node.inode() {
+            Some(inode) => DeltaEntry::File {
+                mode: inode.mode,
+                data: inode.data.to_vec(),
+            },
+            None => DeltaEntry::Dir { mode: node.mode() },
+        }
+    }
+}
+
+/// Changes between a [`VfsSnapshot`] and a later state, each list sorted by
+/// path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VfsDelta {
+    /// Paths that did not exist in the base
+    pub added: Vec<(String, DeltaEntry)>,
+    /// Paths whose node was replaced or changed since the base
+    pub modified: Vec<(String, DeltaEntry)>,
+    /// Paths of the base that no longer exist
+    pub removed: Vec<String>,
+}
+
+impl VfsDelta {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl VfsMem {
+    /// Record the current paths as a base for a later
+    /// [`snapshot_since`](Self::snapshot_since)
+    pub fn snapshot(&self) -> VfsResult<VfsSnapshot> {
+        let nodes = self.read_nodes("snapshot", "/")?;
+        let now = self.now();
+        Ok(VfsSnapshot {
+            generation: nodes.generation(),
+            nodes: nodes
+                .iter()
+                .filter(|(_, node)| !node.is_expired(now))
+                .map(|(path, node)| (path.clone(), node.generation()))
+                .collect(),
+        })
+    }
+
+    /// What changed since `base`, with the new contents of every added or
+    /// modified path.
+    ///
+    /// A path counts as modified if its node was replaced or has changed
+    /// since `base` was taken, so unchanged files are never read. Only
+    /// directories whose mode or entries changed are listed. `base` must
+    /// come from this filesystem's own [`snapshot`](Self::snapshot);
+    /// generations from another instance mean nothing here. Timestamps,
+    /// owners, and hard links are not carried over.
+    pub fn snapshot_since(&self, base: &VfsSnapshot) -> VfsResult<VfsDelta> {
+        let nodes = self.read_nodes("snapshot_since", "/")?;
+        let now = self.now();
+
+        let mut delta = VfsDelta::default();
+        for (path, node) in nodes.iter().filter(|(_, node)| !node.is_expired(now)) {
+            match base.nodes.get(path) {
+                None => delta.added.push((path.clone(), DeltaEntry::of(node))),
+                Some(generation)
+                    if *generation != node.generation() || node.changed() > base.generation =>
+                {
+                    delta.modified.push((path.clone(), DeltaEntry::of(node)));
+                }
+                Some(_) => {}
+            }
+        }
+        delta.removed = base
+            .nodes
+            .keys()
+            .filter(|path| self.lookup(&nodes, path).is_none())
+            .cloned()
+            .collect();
+
+        delta.added.sort_by(|a, b| a.0.cmp(&b.0));
+        delta.modified.sort_by(|a, b| a.0.cmp(&b.0));
+        delta.removed.sort();
+        Ok(delta)
+    }
+
+    /// Bring this filesystem up to date with `delta`, under one write lock.
+    ///
+    /// This filesystem should hold the tree `delta`'s base snapshot was
+    /// taken of, as a [`fork`](Self::fork) or restored copy does. The whole
+    /// delta is checked first: an entry whose parent would be missing or a
+    /// file fails with `NotFound` or `NotADirectory`, and a file at `/`
+    /// with `IsADirectory`, before anything changes. Removed paths go with
+    /// everything below them, even entries the base never had. A file's
+    /// contents are replaced with a new node, so any hard links to it here
+    /// keep the old contents. Every change is journaled, so a journaled
+    /// filesystem replays to the same tree.
+    pub fn apply_delta(&self, delta: VfsDelta) -> VfsResult<()> {
+        let mut nodes = self.write_nodes("apply_delta", "/")?;
+
+        let removed = delta
+            .removed
+            .iter()
+            .map(|path| self.resolve_path(path))
+            .collect::<VfsResult<Vec<_>>>()?;
+        let mut upserts = delta
+            .added
+            .into_iter()
+            .chain(delta.modified)
+            .map(|(path, entry)| Ok((self.resolve_path(&path)?, entry)))
+            .collect::<VfsResult<Vec<_>>>()?;
+        // Parents sort before their children
+        upserts.sort_by(|a, b| a.0.cmp(&b.0));
+        self.check_delta(&nodes, &removed, &upserts)?;
+
+        for path in &removed {
+            self.remove_subtree(&mut nodes, path)?;
+        }
+        for (path, entry) in upserts {
+            let existing = nodes.get(&path).map(Node::kind);
+            match entry {
+                DeltaEntry::Dir { mode } if existing == Some(NodeKind::Dir) => {
+                    self.journal(|| Record::Chmod {
+                        path: path.clone(),
+                        mode,
+                    })?;
+                    if let Some(node) = nodes.get_mut(&path) {
+                        node.set_mode(mode)?;
+                    }
+                    nodes.mark_changed(&path);
+                }
+                DeltaEntry::Dir { mode } => {
+                    self.remove_subtree(&mut nodes, &path)?;
+                    self.journal(|| Record::Create {
+                        path: path.clone(),
+                        kind: NodeKind::Dir,
+                        mode: OREAD,
+                        perm: Some(mode),
+                    })?;
+                    let mut node = Node::new_dir(self.now());
+                    node.set_mode(mode)?;
+                    node.set_owner(self.default_owner.clone())?;
+                    nodes.insert(path.clone(), node);
+                    self.touch_dirs(&mut nodes, &path);
+                }
+                DeltaEntry::File { mode, data } => {
+                    self.remove_subtree(&mut nodes, &path)?;
+                    self.journal(|| Record::Create {
+                        path: path.clone(),
+                        kind: NodeKind::File,
+                        mode: OREAD,
+                        perm: Some(mode),
+                    })?;
+                    if !data.is_empty() {
+                        self.journal(|| Record::write(&path, 0, &[&data]))?;
+                    }
+                    self.touch(&path)?;
+                    let mut node = self.new_file_node()?;
+                    if let Node::File { inode, .. } = &node {
+                        inode.write()?.data.write(0, &data)?;
+                    }
+                    node.set_mode(mode)?;
+                    node.set_owner(self.default_owner.clone())?;
+                    nodes.insert(path.clone(), node);
+                    self.touch_dirs(&mut nodes, &path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every entry of a delta can be applied, changing nothing
+    fn check_delta(
+        &self,
+        nodes: &NodeMap,
+        removed: &[String],
+        upserts: &[(String, DeltaEntry)],
+    ) -> VfsResult<()> {
+        if removed.iter().any(|path| path == "/") {
+            return Err(VfsError::PermissionDenied("cannot remove root".into()));
+        }
+        let gone = |path: &str| {
+            removed.iter().any(|r| {
+                path == r
+                    || path
+                        .strip_prefix(r.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+        };
+
+        // Kinds the upserts before the current one leave behind
+        let mut planned: HashMap<&str, NodeKind> = HashMap::new();
+        for (path, entry) in upserts {
+            let kind = entry.kind();
+            if path == "/" {
+                if kind == NodeKind::File {
+                    return Err(VfsError::IsADirectory(path.clone()));
+                }
+                planned.insert(path, kind);
+                continue;
+            }
+            let parent = parent_path(path);
+            let parent_kind = match planned.get(parent) {
+                Some(kind) => Some(*kind),
+                None if gone(parent) => None,
+                None => self.lookup(nodes, parent).map(Node::kind),
+            };
+            match parent_kind {
+                Some(NodeKind::Dir) => {}
+                Some(NodeKind::File) => return Err(VfsError::NotADirectory(parent.to_string())),
+                None => return Err(VfsError::NotFound(parent.to_string())),
+            }
+            planned.insert(path, kind);
+        }
+        Ok(())
+    }
+
+    /// Remove `path` and everything below it, deepest first; a missing
+    /// `path` is fine
+    fn remove_subtree(&self, nodes: &mut NodeMap, path: &str) -> VfsResult<()> {
+        let prefix = format!("{}/", path);
+        let mut doomed: Vec<String> = nodes
+            .keys()
+            .filter(|p| *p == path || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        // A descendant sorts after its ancestor, so reversed it comes first
+        doomed.sort_by(|a, b| b.cmp(a));
+        for path in doomed {
+            self.remove_locked(nodes, &path)?;
+        }
+        Ok(())
+    }
+}
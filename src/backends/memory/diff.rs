@@ -0,0 +1,60 @@
+//! Structural comparison between two in-memory filesystems.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::error::VfsResult;
+
+use super::fs::VfsMem;
+use super::node::Node;
+
+/// A single difference between two filesystem trees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    /// Path exists only in the other tree
+    Added(String),
+    /// Path exists only in this tree
+    Removed(String),
+    /// Path exists in both but differs in kind, mode, or content
+    Modified(String),
+}
+
+/// Check whether two nodes have the same kind, mode, and content
+fn same_node(a: &Node, b: &Node) -> bool {
+    match (a.inode(), b.inode()) {
+        (Some(ia), Some(ib)) => ia.data.to_vec() == ib.data.to_vec() && ia.mode == ib.mode,
+        (None, None) => a.mode() == b.mode(),
+        _ => false,
+    }
+}
+
+impl VfsMem {
+    /// Compare this filesystem against `other`, sorted by path.
+    ///
+    /// Timestamps and versions are ignored; an empty result means both trees
+    /// hold the same paths with the same kinds, modes, and contents.
+    /// Expired files count as absent, as in [`export_tar`](Self::export_tar).
+    pub fn diff(&self, other: &VfsMem) -> VfsResult<Vec<TreeChange>> {
+        if Arc::ptr_eq(&self.nodes, &other.nodes) {
+            return Ok(Vec::new());
+        }
+
+        let ours = self.read_nodes("diff", "/")?;
+        let theirs = other.read_nodes("diff", "/")?;
+
+        let paths: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+        let changes = paths
+            .into_iter()
+            .filter_map(|path| match (self.lookup(&ours, path), other.lookup(&theirs, path)) {
+                (Some(_), None) => Some(TreeChange::Removed(path.clone())),
+                (None, Some(_)) => Some(TreeChange::Added(path.clone())),
+                (Some(a), Some(b)) if !same_node(a, b) => {
+                    Some(TreeChange::Modified(path.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Ok(changes)
+    }
+}
@@ -0,0 +1,40 @@
+//! Handle lifecycle and rename notifications for the in-memory filesystem.
+
+use tokio::sync::broadcast;
+
+use super::fs::VfsMem;
+
+/// Events kept for a subscriber that falls behind before it sees `Lagged`
+pub(super) const EVENT_CAPACITY: usize = 1024;
+
+/// Something that happened to a handle or path, delivered to every
+/// subscriber
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VfsEvent {
+    /// `open` or `create` handed out `fid` for `path`
+    Opened { path: String, fid: u64 },
+    /// `fid` was released by `clunk`
+    Clunked { fid: u64 },
+    /// `rename` moved `from`, and everything below it, to `to`
+    Renamed { from: String, to: String },
+    /// `exchange` swapped the nodes at `a` and `b`, subtrees included
+    Exchanged { a: String, b: String },
+    /// The LRU byte budget evicted the file at `path`
+    Evicted { path: String },
+}
+
+impl VfsMem {
+    /// Receive every event emitted from now on.
+    ///
+    /// Each receiver buffers up to 1024 events; a receiver that falls
+    /// further behind gets `RecvError::Lagged` and skips the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<VfsEvent> {
+        self.events.subscribe()
+    }
+
+    /// Deliver `event` to the current subscribers, if any
+    pub(super) fn emit(&self, event: VfsEvent) {
+        // Sending only fails when nobody is listening
+        let _ = self.events.send(event);
+    }
+}
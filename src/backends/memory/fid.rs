@@ -0,0 +1,197 @@
+//! Registry of live fids for the in-memory filesystem.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{VfsError, VfsResult};
+
+use super::fs::VfsMem;
+use super::node::SharedInode;
+
+/// What a live fid refers to
+#[derive(Debug)]
+pub(super) struct OpenFid {
+    pub path: String,
+    pub mode: u32,
+    pub opened: SystemTime,
+    /// Opened with `ORCLOSE`: remove the path when this fid is clunked
+    pub remove_on_close: bool,
+    /// Opened for appending: every write goes to the end of the file
+    pub append: bool,
+    /// The node this fid was opened on was replaced, so `path` now names
+    /// another node that the fid must not reach
+    pub detached: bool,
+}
+
+/// A live handle, as reported by [`VfsMem::open_handles`](super::VfsMem::open_handles)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenHandleInfo {
+    pub fid: u64,
+    pub path: String,
+    pub mode: u32,
+    pub opened: SystemTime,
+}
+
+/// Allocates fids and tracks which are still open
+#[derive(Debug)]
+pub(super) struct FidTable {
+    next: u64,
+    /// Highest fid handed out before wrapping back to 1
+    last: u64,
+    open: HashMap<u64, OpenFid>,
+    /// Inodes of removed files, kept alive for the fids still open on them
+    orphans: HashMap<u64, SharedInode>,
+}
+
+impl FidTable {
+    pub fn new() -> Self {
+        Self::with_space(u64::MAX)
+    }
+
+    /// Table that only hands out fids `1..=last`
+    pub fn with_space(last: u64) -> Self {
+        Self {
+            next: 1,
+            last,
+            open: HashMap::new(),
+            orphans: HashMap::new(),
+        }
+    }
+
+    /// Register a new fid for `path` opened at `now`, failing once `max_open` are live
+    pub fn allocate(
+        &mut self,
+        path: &str,
+        mode: u32,
+        remove_on_close: bool,
+        max_open: Option<usize>,
+        now: SystemTime,
+    ) -> VfsResult<u64> {
+        if max_open.is_some_and(|max| self.open.len() >= max) {
+            return Err(VfsError::TooManyOpenFiles);
+        }
+        if self.open.len() as u64 >= self.last {
+            return Err(VfsError::TooManyOpenFiles);
+        }
+
+        // Wrap around, skipping fids that are still live
+        let mut fid = self.next;
+        while self.open.contains_key(&fid) {
+            fid = if fid >= self.last { 1 } else { fid + 1 };
+        }
+        self.next = if fid >= self.last { 1 } else { fid + 1 };
+        self.open.insert(
+            fid,
+            OpenFid {
+                path: path.to_string(),
+                mode,
+                opened: now,
+                remove_on_close,
+                append: false,
+                detached: false,
+            },
+        );
+        Ok(fid)
+    }
+
+    /// Forget a fid, returning what it referred to
+    pub fn release(&mut self, fid: u64) -> Option<OpenFid> {
+        self.orphans.remove(&fid);
+        self.open.remove(&fid)
+    }
+
+    /// Path `fid` currently refers to, if it is live
+    pub fn path(&self, fid: u64) -> Option<String> {
+        self.open.get(&fid).map(|open| open.path.clone())
+    }
+
+    /// Whether the node `fid` was opened on has been replaced
+    pub fn detached(&self, fid: u64) -> bool {
+        self.open.get(&fid).is_some_and(|open| open.detached)
+    }
+
+    /// Cut every fid open on `path` off from it, before another node is
+    /// moved there.
+    ///
+    /// `ORCLOSE` is dropped too: the node it would remove is already gone.
+    pub fn detach(&mut self, path: &str) {
+        for open in self.open.values_mut() {
+            if open.path == path {
+                open.detached = true;
+                open.remove_on_close = false;
+            }
+        }
+    }
+
+    /// Make every write through `fid` append
+    pub fn set_append(&mut self, fid: u64) {
+        if let Some(open) = self.open.get_mut(&fid) {
+            open.append = true;
+        }
+    }
+
+    /// Whether writes through `fid` append
+    pub fn appends(&self, fid: u64) -> bool {
+        self.open.get(&fid).is_some_and(|open| open.append)
+    }
+
+    /// Keep `inode` for every fid open on `path`, which was just removed
+    pub fn orphan(&mut self, path: &str, inode: &SharedInode) {
+        for (fid, open) in &self.open {
+            if open.path == path && !self.orphans.contains_key(fid) {
+                self.orphans.insert(*fid, inode.clone());
+            }
+        }
+    }
+
+    /// Inode of the removed file `fid` is still open on, if any
+    pub fn orphaned(&self, fid: u64) -> Option<SharedInode> {
+        self.orphans.get(&fid).cloned()
+    }
+
+    /// Paths with at least one live fid
+    pub fn open_paths(&self) -> HashSet<String> {
+        self.open.values().map(|f| f.path.clone()).collect()
+    }
+
+    /// Every live fid, ordered by fid
+    pub fn handles(&self) -> Vec<OpenHandleInfo> {
+        let mut handles: Vec<OpenHandleInfo> = self
+            .open
+            .iter()
+            .map(|(fid, open)| OpenHandleInfo {
+                fid: *fid,
+                path: open.path.clone(),
+                mode: open.mode,
+                opened: open.opened,
+            })
+            .collect();
+        handles.sort_by_key(|h| h.fid);
+        handles
+    }
+
+    /// Follow `from` (and anything under it) to `to`
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        for fid in self.open.values_mut().filter(|fid| !fid.detached) {
+            if fid.path == from || fid.path.starts_with(&prefix) {
+                fid.path = format!("{}{}", to, &fid.path[from.len()..]);
+            }
+        }
+    }
+}
+
+impl VfsMem {
+    /// Every live handle with its path, open mode, and open time, by fid
+    pub fn open_handles(&self) -> VfsResult<Vec<OpenHandleInfo>> {
+        Ok(self.fids.lock()?.handles())
+    }
+
+    /// Live handles opened more than `older_than` ago, likely leaked
+    pub fn leaked_handles(&self, older_than: Duration) -> VfsResult<Vec<OpenHandleInfo>> {
+        let now = self.now();
+        let mut handles = self.open_handles()?;
+        handles.retain(|h| now.duration_since(h.opened).is_ok_and(|age| age > older_than));
+        Ok(handles)
+    }
+}
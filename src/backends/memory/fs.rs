@@ -2,73 +2,879 @@
 
 use std::collections::{hash_map::DefaultHasher, HashMap};
 use std::hash::{Hash, Hasher};
-use std::sync::{Arc, RwLock};
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+use futures::future::Either;
+use tokio::sync::broadcast;
 
 use crate::backend::VfsBackend;
+use crate::clock::{Clock, SystemClock};
 use crate::error::{VfsError, VfsResult};
-use crate::types::{Dir, File, FileHandle, Qid, ReadOnly, Stat, WalkResult};
+use crate::mode::{AccessMode, OREAD, ORCLOSE, ORDWR, OTRUNC, OWRITE, OpenMode};
+use crate::path::{canonicalize, normalize_path};
+use crate::runtime;
+use crate::types::{
+    Access, Dir, File, FileHandle, NodeKind, NodeType, PutOutcome, Qid, ReadOnly, ReadWrite, Stat,
+    WalkResult, WriteOnly, WriteResult,
+};
 use crate::{CanRead, CanWrite};
 
-use super::node::Node;
+use super::data::{self, BlockStore, FileData};
+use super::events::{EVENT_CAPACITY, VfsEvent};
+use super::fid::FidTable;
+use super::index::NodeMap;
+use super::journal::{Journal, Record};
+use super::lock::{self, LockGuard, LockTable};
+use super::lru::LruState;
+use super::node::{Inode, Node, Owner, SharedInode};
+use super::tasks::Tasks;
+
+/// Default maximum length of a normalized path in bytes
+const DEFAULT_MAX_PATH_LEN: usize = 4096;
+
+/// Default maximum number of components in a path
+const DEFAULT_MAX_COMPONENTS: usize = 256;
+
+/// Default maximum length of a single path component in bytes
+const DEFAULT_MAX_NAME_LEN: usize = 255;
+
+/// How often the expiry reaper runs by default
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Get the parent directory of a normalized path
+pub(super) fn parent_path(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(p, _)| if p.is_empty() { "/" } else { p })
+        .unwrap_or("/")
+}
+
+/// Join a directory path and a child name
+pub(super) fn join_path(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Bump the version and set the mtime to `now` of the directory containing `path`
+pub(super) fn touch_parent(nodes: &mut NodeMap, path: &str, now: SystemTime) {
+    if path == "/" {
+        return;
+    }
+    if let Some(parent) = nodes.get_mut(parent_path(path)) {
+        parent.mark_children_changed(now);
+    }
+}
+
+/// The inode of `node`, or `IsADirectory` naming `path` if it is a directory
+pub(super) fn expect_file<'a>(path: &str, node: &'a Node) -> VfsResult<&'a SharedInode> {
+    match node {
+        Node::File { inode, .. } => Ok(inode),
+        Node::Dir { .. } => Err(VfsError::IsADirectory(path.to_string())),
+    }
+}
+
+/// Fail with `NotADirectory` naming `path` unless `node` is a directory
+pub(super) fn expect_dir(path: &str, node: &Node) -> VfsResult<()> {
+    match node {
+        Node::Dir { .. } => Ok(()),
+        Node::File { .. } => Err(VfsError::NotADirectory(path.to_string())),
+    }
+}
 
 /// In-memory virtual filesystem backend
 #[derive(Debug, Clone)]
 pub struct VfsMem {
-    nodes: Arc<RwLock<HashMap<String, Node>>>,
-    next_fid: Arc<RwLock<u64>>,
+    pub(super) nodes: Arc<RwLock<NodeMap>>,
+    pub(super) fids: Arc<Mutex<FidTable>>,
+    max_open: Option<usize>,
+    max_io: Option<usize>,
+    pub(super) default_owner: Owner,
+    pub(super) journal: Option<Arc<Journal>>,
+    block_store: Option<BlockStore>,
+    #[cfg(feature = "compression")]
+    compressed: bool,
+    pub(super) lru: Option<Arc<Mutex<LruState>>>,
+    clock: Arc<dyn Clock>,
+    pub(super) events: broadcast::Sender<VfsEvent>,
+    locks: Arc<LockTable>,
+    pub(super) tasks: Arc<Tasks>,
+    max_path_len: usize,
+    max_components: usize,
+    max_name_len: usize,
+    strict_offsets: bool,
+    no_gap_writes: bool,
+    strict_trailing_slash: bool,
+    backslash_separators: bool,
+    propagate_mtime: bool,
+    pub(super) unlinked_writes: bool,
+    dir_size_estimate: bool,
+    /// Directory relative paths resolve against, once one is set
+    cwd: Arc<RwLock<Option<String>>>,
+}
+
+/// A [`VfsMem`] that doesn't keep its tree, fids, or background tasks
+/// alive, for holders that must not outlive the filesystem's clones
+#[derive(Debug, Clone)]
+pub(super) struct WeakVfsMem {
+    nodes: Weak<RwLock<NodeMap>>,
+    fids: Weak<Mutex<FidTable>>,
+    tasks: Weak<Tasks>,
+    /// The remaining fields, with unused placeholders for the three above
+    rest: VfsMem,
+}
+
+impl WeakVfsMem {
+    /// The filesystem again, or `None` once its last clone was dropped
+    pub fn upgrade(&self) -> Option<VfsMem> {
+        Some(VfsMem {
+            nodes: self.nodes.upgrade()?,
+            fids: self.fids.upgrade()?,
+            tasks: self.tasks.upgrade()?,
+            ..self.rest.clone()
+        })
+    }
 }
 
 impl VfsMem {
+    /// A reference to this filesystem that doesn't keep it alive
+    pub(super) fn downgrade(&self) -> WeakVfsMem {
+        WeakVfsMem {
+            nodes: Arc::downgrade(&self.nodes),
+            fids: Arc::downgrade(&self.fids),
+            tasks: Arc::downgrade(&self.tasks),
+            rest: VfsMem {
+                nodes: Arc::default(),
+                fids: Arc::new(Mutex::new(FidTable::new())),
+                tasks: Arc::default(),
+                ..self.clone()
+            },
+        }
+    }
+
     /// Create a new in-memory filesystem with a root directory
     pub fn new() -> Self {
-        let mut nodes = HashMap::new();
-        nodes.insert("/".to_string(), Node::new_dir());
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let mut nodes = NodeMap::default();
+        nodes.insert("/".to_string(), Node::new_dir(clock.now()));
 
         Self {
             nodes: Arc::new(RwLock::new(nodes)),
-            next_fid: Arc::new(RwLock::new(1)),
+            fids: Arc::new(Mutex::new(FidTable::new())),
+            max_open: None,
+            max_io: None,
+            default_owner: Owner::default(),
+            journal: None,
+            block_store: None,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            lru: None,
+            clock,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            locks: Arc::new(LockTable::default()),
+            tasks: Arc::new(Tasks::default()),
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            max_components: DEFAULT_MAX_COMPONENTS,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            strict_offsets: false,
+            no_gap_writes: false,
+            strict_trailing_slash: false,
+            backslash_separators: false,
+            unlinked_writes: false,
+            dir_size_estimate: false,
+            cwd: Arc::new(RwLock::new(None)),
+            propagate_mtime: false,
+        }
+    }
+
+    /// Take every timestamp from `clock` instead of the system clock.
+    ///
+    /// Covers mtimes, TTL expiry, and handle open times. The root
+    /// directory's mtime is reset to the clock's current time.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        if let Ok(mut nodes) = self.nodes.write()
+            && let Some(Node::Dir { mtime, .. }) = nodes.get_mut("/")
+        {
+            *mtime = clock.now();
         }
+        Self { clock, ..self }
+    }
+
+    /// Current time according to this filesystem's clock
+    pub(super) fn now(&self) -> SystemTime {
+        self.clock.now()
     }
 
-    /// Generate next unique file ID
-    fn next_fid(&self) -> u64 {
-        let mut fid = self.next_fid.write().unwrap();
-        let id = *fid;
-        *fid += 1;
-        id
+    /// Lock the node map for reading, naming `op` and `path` if it's poisoned
+    pub(super) fn read_nodes(
+        &self,
+        op: &'static str,
+        path: &str,
+    ) -> VfsResult<RwLockReadGuard<'_, NodeMap>> {
+        self.nodes.read().map_err(|_| VfsError::LockPoisonedAt {
+            op,
+            path: path.to_string(),
+        })
     }
 
-    /// Normalize and validate a path
-    pub (super) fn normalize_path(path: &str) -> VfsResult<String> {
-        if path.contains("..") {
-            return Err(VfsError::InvalidPath(".. traversal not allowed".into()));
+    /// Lock the node map for writing, naming `op` and `path` if it's poisoned
+    pub(super) fn write_nodes(
+        &self,
+        op: &'static str,
+        path: &str,
+    ) -> VfsResult<RwLockWriteGuard<'_, NodeMap>> {
+        self.nodes.write().map_err(|_| VfsError::LockPoisonedAt {
+            op,
+            path: path.to_string(),
+        })
+    }
+
+    /// Look up a node, treating files whose TTL has passed as absent
+    pub(super) fn lookup<'a>(&self, nodes: &'a NodeMap, path: &str) -> Option<&'a Node> {
+        let now = self.now();
+        nodes.get(path).filter(|node| !node.is_expired(now))
+    }
+
+    /// Reset to an empty filesystem holding only `/`.
+    ///
+    /// Every node, fid, and bit of LRU history is dropped, so handles opened
+    /// before become invalid. Configuration such as limits, the clock, the
+    /// journal, and the root's mode and owner is kept.
+    pub fn clear(&self) -> VfsResult<()> {
+        let mut nodes = self.write_nodes("clear", "/")?;
+        self.journal(|| Record::Clear)?;
+
+        // The root's mode and owner are configuration too
+        let root = nodes.get("/").map(|root| Node::Dir {
+            mtime: self.now(),
+            mode: root.mode(),
+            version: 0,
+            owner: root.owner(),
+            changed: 0,
+            generation: 0,
+        });
+        nodes.clear();
+        nodes.insert("/".to_string(), root.unwrap_or_else(|| Node::new_dir(self.now())));
+        *self.fids.lock()? = FidTable::new();
+        if let Some(lru) = &self.lru {
+            lru.lock()?.clear();
         }
+        Ok(())
+    }
+
+    /// Consume the filesystem and return every file's contents by path.
+    ///
+    /// Flat contents are moved out rather than copied. Fails with
+    /// `InvalidArgument` while any clone of this `VfsMem` is still alive.
+    /// Expired files are left out.
+    pub fn into_files(self) -> VfsResult<HashMap<String, Vec<u8>>> {
+        let now = self.now();
+        let nodes = Arc::try_unwrap(self.nodes)
+            .map_err(|_| VfsError::InvalidArgument("filesystem is still shared by a clone".into()))?
+            .into_inner()?;
 
-        if path.is_empty() {
-            return Err(VfsError::InvalidPath("empty path".into()));
+        Ok(nodes
+            .into_nodes()
+            .into_iter()
+            .filter(|(_, node)| !node.is_expired(now))
+            .filter_map(|(path, node)| Some((path, node.into_data()?)))
+            .collect())
+    }
+
+    /// Branch off an independent copy of the filesystem.
+    ///
+    /// Flat and block-deduplicated contents are shared with the original
+    /// until either side writes them, at which point the writer gets its
+    /// own copy, so forking costs little more than copying the metadata.
+    /// Sparse and compressed contents are copied up front. Hard links
+    /// between files carry over within the fork.
+    ///
+    /// The fork keeps the configuration but starts with no open fids or
+    /// advisory locks, its own event channel, and no journal, so its
+    /// changes never reach the original's log. A poisoned lock fails the
+    /// fork with `LockPoisonedAt` rather than copying a torn tree.
+    pub fn fork(&self) -> VfsResult<VfsMem> {
+        let nodes = self.read_nodes("fork", "/")?;
+        let mut forked = NodeMap::default();
+        // Inodes already copied, so links keep sharing theirs
+        let mut inodes: HashMap<*const RwLock<Inode>, SharedInode> = HashMap::new();
+
+        for (path, node) in nodes.iter() {
+            let node = match node {
+                Node::File { inode, expires } => {
+                    let copy = match inodes.get(&Arc::as_ptr(inode)) {
+                        Some(copy) => copy.clone(),
+                        None => {
+                            let copy = Arc::new(RwLock::new(inode.read()?.clone()));
+                            inodes.insert(Arc::as_ptr(inode), copy.clone());
+                            copy
+                        }
+                    };
+                    Node::File {
+                        inode: copy,
+                        expires: *expires,
+                    }
+                }
+                dir => dir.clone(),
+            };
+            forked.insert(path.clone(), node);
         }
 
-        // Handle root
-        if path == "/" {
-            return Ok("/".to_string());
+        let lru = match &self.lru {
+            Some(lru) => Some(Arc::new(Mutex::new(lru.lock()?.clone()))),
+            None => None,
+        };
+        let cwd = self.cwd.read()?.clone();
+        Ok(VfsMem {
+            nodes: Arc::new(RwLock::new(forked)),
+            fids: Arc::new(Mutex::new(FidTable::new())),
+            journal: None,
+            lru,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            locks: Arc::new(LockTable::default()),
+            tasks: Arc::new(Tasks::default()),
+            cwd: Arc::new(RwLock::new(cwd)),
+            ..self.clone()
+        })
+    }
+
+    /// Make a trailing slash mean the path must be a directory.
+    ///
+    /// `stat("/a/")` and every other call naming an existing file with a
+    /// trailing slash then fail with `NotADirectory`, and so does creating
+    /// a file that way. By default trailing slashes are ignored.
+    pub fn with_strict_trailing_slash(mut self) -> Self {
+        self.strict_trailing_slash = true;
+        self
+    }
+
+    /// Treat `\` in paths as a separator, like `/`.
+    ///
+    /// Windows-style input such as `\a\b` then names `/a/b`. By default a
+    /// backslash is an ordinary character, so names may contain one.
+    pub fn with_backslash_separators(mut self) -> Self {
+        self.backslash_separators = true;
+        self
+    }
+
+    /// Make `read` past the end of a file fail with `BadOffset`.
+    ///
+    /// Reading exactly at the end still returns an empty buffer (clean EOF).
+    /// By default any offset at or past the end returns an empty buffer.
+    pub fn with_strict_offsets(mut self) -> Self {
+        self.strict_offsets = true;
+        self
+    }
+
+    /// Make `write` past the end of a file fail with `BadOffset`.
+    ///
+    /// Writing exactly at the end still extends the file, so contiguous
+    /// writes work as before. By default a write past the end fills the
+    /// gap with zeros, which can quietly make a huge sparse file from a
+    /// bad offset.
+    pub fn with_no_gap_writes(mut self) -> Self {
+        self.no_gap_writes = true;
+        self
+    }
+
+    /// `BadOffset` if a write at `start` would leave a gap after `len` bytes
+    fn check_gap(&self, start: usize, len: usize) -> VfsResult<()> {
+        if self.no_gap_writes && start > len {
+            return Err(VfsError::BadOffset);
+        }
+        Ok(())
+    }
+
+    /// Keep removed files usable through handles that are still open.
+    ///
+    /// As on Unix, a file removed (or replaced by a rename) while open can
+    /// still be read and written through that handle's `read` and `write`,
+    /// even if a new file appears at the same path. Its data is dropped
+    /// when the last such handle is clunked. By default these calls fail
+    /// with `NotFound` once the path is gone.
+    pub fn with_unlinked_writes(mut self) -> Self {
+        self.unlinked_writes = true;
+        self
+    }
+
+    /// Report a directory's size as the total length of its entries' names.
+    ///
+    /// Some tools expect a directory's apparent size to grow with its
+    /// contents. By default directories report a size of 0.
+    pub fn with_dir_size_estimate(mut self) -> Self {
+        self.dir_size_estimate = true;
+        self
+    }
+
+    /// Mark every ancestor directory changed, not just the parent.
+    ///
+    /// With this on, `write`, `create`, and `remove` bump the mtime and
+    /// version of each directory from the parent up to `/`, so watching a
+    /// directory's version catches changes anywhere below it. By default
+    /// only the parent changes, and only for `create` and `remove`.
+    pub fn with_ancestor_mtime_propagation(mut self) -> Self {
+        self.propagate_mtime = true;
+        self
+    }
+
+    /// Mark the directories above `path` changed: the parent, or with
+    /// ancestor propagation every directory up to `/`
+    pub(super) fn touch_dirs(&self, nodes: &mut NodeMap, path: &str) {
+        let now = self.now();
+        touch_parent(nodes, path, now);
+        if !self.propagate_mtime {
+            return;
+        }
+        let mut dir = parent_path(path);
+        while dir != "/" {
+            touch_parent(nodes, dir, now);
+            dir = parent_path(dir);
+        }
+    }
+
+    /// Limit the length in bytes and the number of components of paths.
+    ///
+    /// Limits apply to the normalized path; defaults are 4096 bytes and
+    /// 256 components.
+    pub fn with_limits(mut self, max_path_len: usize, max_components: usize) -> Self {
+        self.max_path_len = max_path_len;
+        self.max_components = max_components;
+        self
+    }
+
+    /// Limit the length in bytes of each path component; the default is 255
+    pub fn with_max_name_len(mut self, max_name_len: usize) -> Self {
+        self.max_name_len = max_name_len;
+        self
+    }
+
+    /// Take an advisory lock on an existing path, waiting for conflicting holders.
+    ///
+    /// Exclusive locks conflict with any other lock; shared locks coexist.
+    /// The lock is released when the returned guard is dropped. Locks are
+    /// purely advisory and don't restrict other operations.
+    pub async fn lock(&self, path: &str, exclusive: bool) -> VfsResult<LockGuard> {
+        let path = self.lockable_path(path)?;
+        lock::lock(&self.locks, path, exclusive).await
+    }
+
+    /// Like [`lock`](Self::lock), but returns `PermissionDenied` instead of waiting
+    pub fn try_lock(&self, path: &str, exclusive: bool) -> VfsResult<LockGuard> {
+        let path = self.lockable_path(path)?;
+        lock::try_lock(&self.locks, path, exclusive)
+    }
+
+    /// Normalize a lock path and check that it exists
+    fn lockable_path(&self, path: &str) -> VfsResult<String> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("lock", &path)?;
+        match self.lookup(&nodes, &path) {
+            Some(_) => Ok(path),
+            None => Err(VfsError::NotFound(path)),
+        }
+    }
+
+    /// Periodically remove files whose TTL has passed.
+    ///
+    /// Expired files are already invisible before they are reaped; this
+    /// reclaims their memory, removing them as `remove` would, journal
+    /// included. The reaper uses the options set before this call. It stops
+    /// on [`shutdown`](Self::shutdown) or once every clone of this
+    /// filesystem is dropped. Must be called within the runtime selected by
+    /// the `tokio` or `async-std` feature.
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    pub fn with_expiry(self) -> Self {
+        self.with_expiry_every(REAP_INTERVAL)
+    }
+
+    /// Like [`with_expiry`](Self::with_expiry) with a custom reap interval
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    pub fn with_expiry_every(self, interval: Duration) -> Self {
+        let weak = self.downgrade();
+        let mut stop = self.tasks.stop_signal();
+        let task = runtime::spawn(async move {
+            loop {
+                let tick = std::pin::pin!(runtime::sleep(interval));
+                // Either a shutdown or the last clone going away
+                let stopped = std::pin::pin!(stop.changed());
+                if let Either::Right(_) = futures::future::select(tick, stopped).await {
+                    break;
+                }
+                let Some(vfs) = weak.upgrade() else {
+                    break;
+                };
+                if vfs.reap_expired().is_err() {
+                    break;
+                }
+            }
+        });
+        self.tasks.track(task);
+        self
+    }
+
+    /// Remove every file expired by now, through the same path as `remove`
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    fn reap_expired(&self) -> VfsResult<usize> {
+        let mut nodes = self.write_nodes("reap_expired", "/")?;
+        let now = self.now();
+        let expired: Vec<String> = nodes
+            .iter()
+            .filter(|(_, node)| node.is_expired(now))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &expired {
+            self.remove_locked(&mut nodes, path)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Create a file that disappears once `ttl` has elapsed
+    pub async fn create_with_ttl<M, T>(
+        &self,
+        path: &str,
+        mode: u32,
+        ttl: Duration,
+    ) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        if T::KIND != NodeKind::File {
+            return Err(VfsError::InvalidArgument("ttl is only supported for files".into()));
+        }
+
+        let handle = self.create::<M, T>(path, mode).await?;
+        let mut nodes = self.write_nodes("create_with_ttl", &handle.path)?;
+        if let Some(Node::File { expires, .. }) = nodes.get_mut(&handle.path) {
+            *expires = Some(self.now() + ttl);
+        }
+        Ok(handle)
+    }
+
+    /// Bound stored file bytes, evicting least-recently-used files on overflow.
+    ///
+    /// Reads and writes count as uses. When a write pushes the total past
+    /// `bytes`, the least recently used files are removed until it fits again.
+    /// Directories, files with open handles, and the file being written are
+    /// never evicted. Holes in sparse files don't count, and hard links count
+    /// their file's bytes once. Each eviction is journaled as a remove and
+    /// emits [`VfsEvent::Evicted`].
+    pub fn with_lru_budget(mut self, bytes: u64) -> Self {
+        self.lru = Some(Arc::new(Mutex::new(LruState::new(bytes))));
+        self
+    }
+
+    /// Record a use of `path` for LRU eviction
+    pub(super) fn touch(&self, path: &str) -> VfsResult<()> {
+        if let Some(lru) = &self.lru {
+            lru.lock()?.touch(path);
+        }
+        Ok(())
+    }
+
+    /// Store new files as content-addressed blocks shared across files.
+    ///
+    /// Identical `BLOCK_SIZE` blocks, within or across files, are kept once.
+    pub fn with_dedup(mut self) -> Self {
+        self.block_store = Some(Arc::new(RwLock::new(HashMap::new())));
+        self
+    }
+
+    /// Store new files deflate-compressed, inflating them on every access.
+    ///
+    /// Each write recompresses the whole file, so this suits data that is
+    /// written rarely. `stat` still reports uncompressed sizes; `statfs`
+    /// reports the compressed footprint as allocated bytes. Takes precedence
+    /// over [`with_dedup`](Self::with_dedup).
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    /// Number of distinct blocks held by the dedup store (0 without dedup)
+    pub fn block_count(&self) -> VfsResult<usize> {
+        match &self.block_store {
+            Some(store) => data::live_blocks(store),
+            None => Ok(0),
         }
+    }
+
+    /// Create an empty file node using the configured storage
+    pub(super) fn new_file_node(&self) -> VfsResult<Node> {
+        #[cfg(feature = "compression")]
+        if self.compressed {
+            return Ok(Node::new_file_with(FileData::new_compressed()?, self.now()));
+        }
+
+        Ok(match &self.block_store {
+            Some(store) => Node::new_file_with(FileData::new_blocks(store.clone()), self.now()),
+            None => Node::new_file(self.now()),
+        })
+    }
+
+    /// Cap the number of simultaneously live handles.
+    ///
+    /// Once `n` handles are open, `open` and `create` fail with
+    /// `TooManyOpenFiles` until one is clunked.
+    pub fn with_max_open(mut self, n: usize) -> Self {
+        self.max_open = Some(n);
+        self
+    }
+
+    /// Cap the bytes moved by a single `read` or `write`, such as a 9P msize.
+    ///
+    /// Reads asking for more return at most `max_io` bytes; writes of more
+    /// fail with `InvalidArgument` and change nothing.
+    pub fn with_io_limit(mut self, max_io: usize) -> Self {
+        self.max_io = Some(max_io);
+        self
+    }
+
+    /// `count` clamped to the I/O limit, if one is set
+    fn io_count(&self, count: usize) -> usize {
+        self.max_io.map_or(count, |max_io| count.min(max_io))
+    }
+
+    /// Refuse a write of `len` bytes over the I/O limit
+    fn check_io_limit(&self, len: usize) -> VfsResult<()> {
+        match self.max_io {
+            Some(max_io) if len > max_io => Err(VfsError::InvalidArgument(format!(
+                "write of {} bytes exceeds the {} byte I/O limit",
+                len, max_io
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Set the owner stamped on newly created files and directories
+    pub fn with_default_owner(mut self, uid: impl Into<String>, gid: impl Into<String>) -> Self {
+        self.default_owner = Owner {
+            uid: uid.into(),
+            gid: gid.into(),
+        };
+        self
+    }
+
+    /// Change the owner of the file or directory at `path`
+    pub async fn chown(&self, path: &str, uid: &str, gid: &str) -> VfsResult<()> {
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("chown", &path)?;
+
+        let node = nodes
+            .get_mut(&path)
+            .filter(|node| !node.is_expired(self.now()))
+            .ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        self.journal(|| Record::Chown {
+            path: path.clone(),
+            uid: uid.into(),
+            gid: gid.into(),
+        })?;
+        node.set_owner(Owner {
+            uid: uid.into(),
+            gid: gid.into(),
+        })?;
+        nodes.mark_changed(&path);
+        Ok(())
+    }
+
+    /// Change the permission bits of the file or directory at `path`.
+    ///
+    /// Only the low 12 bits (`0o7777`) are kept.
+    pub async fn chmod(&self, path: &str, mode: u32) -> VfsResult<()> {
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("chmod", &path)?;
+
+        let node = nodes
+            .get_mut(&path)
+            .filter(|node| !node.is_expired(self.now()))
+            .ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        self.journal(|| Record::Chmod {
+            path: path.clone(),
+            mode: mode & 0o7777,
+        })?;
+        node.set_mode(mode & 0o7777)?;
+        nodes.mark_changed(&path);
+        Ok(())
+    }
+
+    /// Whether the user `uid` in group `gid` has `want` on the node at `path`.
+    ///
+    /// Evaluates the node's owner, group, and other permission bits the way
+    /// Unix does; see [`AccessMode::granted_by`]. Nothing else, such as
+    /// the permissions of parent directories, is taken into account.
+    pub async fn access(
+        &self,
+        path: &str,
+        uid: &str,
+        gid: &str,
+        want: AccessMode,
+    ) -> VfsResult<bool> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("access", &path)?;
+
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        let owner = node.owner();
+        Ok(want.granted_by(node.mode(), owner.uid == uid, owner.gid == gid))
+    }
+
+    /// Current path of the node `handle` was opened on.
+    ///
+    /// Fids follow renames, so this can differ from `handle.path`. Call it
+    /// with the node lock held, so no rename can slip in before the lookup.
+    pub(super) fn handle_path<T, M>(&self, handle: &FileHandle<T, M>) -> VfsResult<String> {
+        let fids = self.fids.lock()?;
+        let path = fids.path(handle.fid).unwrap_or_else(|| handle.path.clone());
+        // Whatever is at the path now is not the node the handle was opened on
+        if fids.detached(handle.fid) {
+            return Err(VfsError::NotFound(path));
+        }
+        Ok(path)
+    }
+
+    /// Register a new fid for `path` opened with `mode`
+    fn next_fid(&self, path: &str, mode: u32, remove_on_close: bool) -> VfsResult<u64> {
+        self.fids
+            .lock()?
+            .allocate(path, mode, remove_on_close, self.max_open, self.now())
+    }
+
+    /// Empty the file at `path` for an `OTRUNC` open, with the write lock held
+    fn truncate_on_open(&self, nodes: &NodeMap, path: &str, inode: &SharedInode) -> VfsResult<()> {
+        self.journal(|| Record::Truncate { path: path.to_string() })?;
+        let mut inode = inode.write()?;
+        inode.data.truncate(0)?;
+        inode.mtime = self.now();
+        inode.version += 1;
+        inode.changed = nodes.next_generation();
+        Ok(())
+    }
+
+    /// Normalize and validate a path, see [`normalize_path`]
+    pub(super) fn normalize_path(path: &str) -> VfsResult<String> {
+        normalize_path(path)
+    }
+
+    /// Resolve relative paths against the directory at `path` from now on.
+    ///
+    /// `path` must name a directory and may itself be relative. Relative
+    /// paths are then joined to it and canonicalized, so `..` works as long
+    /// as it doesn't climb above the root; absolute paths are unaffected.
+    /// Until this is called, relative paths are taken from `/` and may not
+    /// contain `..`. Every clone shares the working directory, like threads
+    /// of a process; a [`fork`](Self::fork) gets its own copy.
+    pub fn set_cwd(&self, path: &str) -> VfsResult<()> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("set_cwd", &path)?;
+        match self.lookup(&nodes, &path) {
+            None => Err(VfsError::NotFound(path)),
+            Some(node) if node.is_file() => Err(VfsError::NotADirectory(path)),
+            Some(_) => {
+                *self.cwd.write()? = Some(path);
+                Ok(())
+            }
+        }
+    }
+
+    /// The directory relative paths resolve against
+    pub fn cwd(&self) -> VfsResult<String> {
+        Ok(self.cwd.read()?.clone().unwrap_or_else(|| "/".to_string()))
+    }
+
+    /// Turn `path` into a normalized absolute path, joining relative paths
+    /// to the working directory if one is set
+    fn absolute_path(&self, path: &str) -> VfsResult<String> {
+        let cwd = self.cwd.read()?;
+        match cwd.as_deref() {
+            Some(cwd) if !path.is_empty() && !path.starts_with('/') => {
+                if path.contains('\0') {
+                    return Err(VfsError::InvalidPath("NUL byte in path".into()));
+                }
+                canonicalize(&join_path(cwd, path))
+            }
+            _ => Self::normalize_path(path),
+        }
+    }
+
+    /// Normalize a path and enforce this filesystem's path limits and, if
+    /// enabled, strict trailing slashes
+    pub(super) fn resolve_path(&self, path: &str) -> VfsResult<String> {
+        let resolved = self.resolve_name(path)?;
+        if self.wants_dir(path) {
+            let nodes = self.read_nodes("resolve_path", &resolved)?;
+            self.check_trailing_slash(&nodes, &resolved)?;
+        }
+        Ok(resolved)
+    }
+
+    /// [`resolve_path`](Self::resolve_path) with the node lock already held
+    pub(super) fn resolve_path_in(&self, nodes: &NodeMap, path: &str) -> VfsResult<String> {
+        let resolved = self.resolve_name(path)?;
+        if self.wants_dir(path) {
+            self.check_trailing_slash(nodes, &resolved)?;
+        }
+        Ok(resolved)
+    }
 
-        // Remove leading/trailing slashes and normalize
-        let clean = path.trim_matches('/');
-        if clean.is_empty() {
-            return Ok("/".to_string());
+    /// Whether `path` ends in a slash that, with strict trailing slashes,
+    /// demands a directory
+    fn wants_dir(&self, path: &str) -> bool {
+        let trailing = path.ends_with('/') || (self.backslash_separators && path.ends_with('\\'));
+        self.strict_trailing_slash && trailing
+    }
+
+    /// `NotADirectory` if `path` names a file
+    fn check_trailing_slash(&self, nodes: &NodeMap, path: &str) -> VfsResult<()> {
+        match self.lookup(nodes, path) {
+            Some(node) if node.is_file() => Err(VfsError::NotADirectory(path.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Normalize `path` and check it against the configured limits
+    fn resolve_name(&self, path: &str) -> VfsResult<String> {
+        let path = match self.backslash_separators {
+            true => self.absolute_path(&path.replace('\\', "/"))?,
+            false => self.absolute_path(path)?,
+        };
+
+        if path.len() > self.max_path_len {
+            return Err(VfsError::InvalidPath(format!(
+                "path is {} bytes, limit is {}",
+                path.len(),
+                self.max_path_len
+            )));
         }
 
-        // Check for empty components (e.g., "//")
-        if clean.split('/').any(|s| s.is_empty()) {
-            return Err(VfsError::InvalidPath("empty path component".into()));
+        let components = path.split('/').filter(|c| !c.is_empty()).count();
+        if components > self.max_components {
+            return Err(VfsError::InvalidPath(format!(
+                "path has {} components, limit is {}",
+                components, self.max_components
+            )));
         }
 
-        Ok(format!("/{}", clean))
+        if let Some(name) = path.split('/').find(|c| c.len() > self.max_name_len) {
+            return Err(VfsError::InvalidPath(format!(
+                "component is {} bytes, limit is {}",
+                name.len(),
+                self.max_name_len
+            )));
+        }
+
+        Ok(path)
     }
 
-    /// Generate a unique Qid path from a string path
+    /// Generate a unique Qid path from a string path.
+    ///
+    /// Derived from the path alone, with no counter, so a path keeps its
+    /// qid across remove and recreate and across `export_tar`/`import_tar`.
     fn path_to_qid_path(&self, path: &str) -> u64 {
         let mut hasher = DefaultHasher::new();
         path.hash(&mut hasher);
@@ -76,66 +882,780 @@ impl VfsMem {
     }
 
     /// Get immediate children of a directory
-    fn get_dir_children(&self, dir_path: &str, nodes: &HashMap<String, Node>) -> Vec<String> {
-        let prefix = if dir_path == "/" {
-            "/"
-        } else {
-            &format!("{}/", dir_path)
-        };
-
-        nodes
-            .keys()
-            .filter(|p| p.starts_with(prefix) && *p != dir_path)
-            .filter_map(|p| {
-                let rest = &p[prefix.len()..];
-                // Only immediate children (no nested paths)
-                if !rest.contains('/') {
-                    Some(rest.to_string())
-                } else {
-                    None
-                }
-            })
-            .collect()
+    pub(super) fn get_dir_children(&self, dir_path: &str, nodes: &NodeMap) -> Vec<String> {
+        nodes.children(dir_path).cloned().collect()
     }
 
-    /// Convert a Node to a Stat
-    fn node_to_stat(&self, path: &str, node: &Node) -> Stat {
+    /// Build the Qid of a node
+    fn node_qid(&self, path: &str, node: &Node) -> Qid {
         let qid_path = self.path_to_qid_path(path);
-        let name = path.split('/').last().unwrap_or(path).to_string();
+        match node.kind() {
+            NodeKind::File => Qid::new_file(qid_path, node.version()).into(),
+            NodeKind::Dir => Qid::new_dir(qid_path, node.version()).into(),
+        }
+    }
 
-        let qid = if node.is_file() {
-            Qid::new_file(qid_path, node.version())
-        } else {
-            Qid::new_dir(qid_path, 0)
+    /// Build the Qid of a node already known to be of kind `T`
+    fn typed_qid<T: NodeType>(&self, path: &str, node: &Node) -> Qid<T> {
+        debug_assert_eq!(node.kind(), T::KIND);
+        Qid::new(self.path_to_qid_path(path), node.version())
+    }
+
+    /// Convert a Node to a Stat
+    fn node_to_stat(&self, nodes: &NodeMap, path: &str, node: &Node) -> Stat {
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+        let owner = node.owner();
+        let (nlink, size) = match node {
+            Node::File { .. } => (node.nlink(), node.size()),
+            Node::Dir { .. } => {
+                let children: Vec<&String> = nodes
+                    .children(path)
+                    .filter(|child| self.lookup(nodes, &join_path(path, child)).is_some())
+                    .collect();
+                let size = match self.dir_size_estimate {
+                    true => children.iter().map(|child| child.len() as u64).sum(),
+                    false => 0,
+                };
+                (children.len(), size)
+            }
         };
 
         Stat {
-            qid,
+            qid: self.node_qid(path, node),
             name,
-            size: node.size(),
-            mode: if node.is_dir() { 0o755 } else { 0o644 },
+            size,
+            mode: node.mode(),
             atime: node.mtime(),
             mtime: node.mtime(),
-            uid: "user".into(),
-            gid: "group".into(),
+            uid: owner.uid,
+            gid: owner.gid,
+            nlink: nlink as u64,
+            generation: node.generation(),
+        }
+    }
+
+    /// Write `bufs` back to back at `offset` under a single write lock.
+    ///
+    /// The node's version and mtime change once for the whole call. With
+    /// `expected_version`, nothing is written unless the version matches.
+    fn write_bufs<M>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        bufs: &[&[u8]],
+        expected_version: Option<u32>,
+    ) -> VfsResult<WriteResult> {
+        let mut nodes = self.write_nodes("write", &handle.path)?;
+        let path = self.handle_path(handle)?;
+        self.write_locked(&mut nodes, &path, offset, bufs, expected_version)
+    }
+
+    /// [`write_bufs`](Self::write_bufs) with the write lock already held
+    fn write_locked(
+        &self,
+        nodes: &mut NodeMap,
+        path: &str,
+        offset: u64,
+        bufs: &[&[u8]],
+        expected_version: Option<u32>,
+    ) -> VfsResult<WriteResult> {
+        // An empty write changes nothing, whatever the offset
+        let empty = bufs.iter().all(|buf| buf.is_empty());
+        // Reject writes whose end can't be addressed before touching any data
+        let start = match empty {
+            true => 0,
+            false => usize::try_from(offset).map_err(|_| VfsError::BadOffset)?,
+        };
+        let total = bufs.iter().try_fold(0usize, |acc, buf| acc.checked_add(buf.len()));
+        total
+            .and_then(|total| start.checked_add(total))
+            .ok_or(VfsError::BadOffset)?;
+
+        let node = self.lookup(nodes, path).ok_or_else(|| VfsError::NotFound(path.to_string()))?;
+
+        let written = {
+            let mut inode = expect_file(path, node)?.write()?;
+            if expected_version.is_some_and(|v| v != inode.version) {
+                return Err(VfsError::Stale(path.to_string()));
+            }
+            if empty {
+                return Ok(WriteResult {
+                    written: 0,
+                    qid: Qid::new_file(self.path_to_qid_path(path), inode.version),
+                });
+            }
+            self.check_gap(start, inode.data.len())?;
+            self.journal(|| Record::write(path, offset, bufs))?;
+            let mut pos = start;
+            for buf in bufs {
+                inode.data.write(pos, buf)?;
+                pos += buf.len();
+            }
+            inode.mtime = self.now();
+            inode.version += 1;
+            inode.changed = nodes.next_generation();
+            WriteResult {
+                written: pos - start,
+                qid: Qid::new_file(self.path_to_qid_path(path), inode.version),
+            }
+        };
+        if self.propagate_mtime {
+            self.touch_dirs(nodes, path);
+        }
+
+        if let Some(lru) = &self.lru {
+            let mut pinned = self.fids.lock()?.open_paths();
+            pinned.insert(path.to_string());
+
+            let evicted = {
+                let mut lru = lru.lock()?;
+                lru.touch(path);
+                lru.enforce(nodes, &pinned, self.now())
+            };
+            for path in evicted {
+                self.journal(|| Record::Remove { path: path.clone() })?;
+                self.emit(VfsEvent::Evicted { path });
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Inode of the removed file `fid` is open on, with unlinked writes on
+    fn orphaned(&self, fid: u64) -> VfsResult<Option<SharedInode>> {
+        if !self.unlinked_writes {
+            return Ok(None);
         }
+        Ok(self.fids.lock()?.orphaned(fid))
     }
 
-    /// Ensure parent directory exists
-    fn ensure_parent_exists(&self, path: &str, nodes: &HashMap<String, Node>) -> VfsResult<()> {
+    /// Check a read `offset` into `data`, failing past the end with strict offsets
+    fn read_offset(&self, data: &FileData, offset: u64) -> VfsResult<usize> {
+        if self.strict_offsets && offset > data.len() as u64 {
+            return Err(VfsError::BadOffset);
+        }
+        usize::try_from(offset)
+            .map_err(|_| VfsError::InvalidArgument(format!("offset {} overflows usize", offset)))
+    }
+
+    /// Run `read` on the contents `handle` refers to, holding the locks a
+    /// read needs and recording the access
+    fn read_with<M, R>(
+        &self,
+        handle: &FileHandle<File, M>,
+        read: impl FnOnce(&FileData) -> VfsResult<R>,
+    ) -> VfsResult<R> {
+        if let Some(inode) = self.orphaned(handle.fid)? {
+            return read(&inode.read()?.data);
+        }
+
+        let nodes = self.read_nodes("read", &handle.path)?;
+        let path = self.handle_path(handle)?;
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+
+        let result = read(&expect_file(&path, node)?.read()?.data)?;
+        self.touch(&path)?;
+        Ok(result)
+    }
+
+    /// Like `read`, but return a refcounted slice instead of a copy.
+    ///
+    /// For flat (default) storage the result shares the file's buffer, so
+    /// serving the same bytes to many readers costs no copies. A later
+    /// write to the file copies the buffer instead of changing what was
+    /// already returned.
+    pub async fn read_bytes<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Bytes> {
+        let count = self.io_count(count);
+        self.read_with(handle, |data| Ok(data.read_bytes(self.read_offset(data, offset)?, count)))
+    }
+
+    /// Read several `(offset, count)` ranges of one file under a single lock.
+    ///
+    /// Each range behaves like its own `read`: one running past the end
+    /// comes back short, and the I/O limit applies to each.
+    pub async fn read_ranges<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        ranges: &[(u64, usize)],
+    ) -> VfsResult<Vec<Vec<u8>>> {
+        self.read_with(handle, |data| {
+            ranges
+                .iter()
+                .map(|&(offset, count)| {
+                    Ok(data.read(self.read_offset(data, offset)?, self.io_count(count)))
+                })
+                .collect()
+        })
+    }
+
+    /// Like `read`, but also report whether the read reached the end of
+    /// the file, saving the empty read that would otherwise confirm it.
+    pub async fn read_at_most<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<(Vec<u8>, bool)> {
+        let count = self.io_count(count);
+        self.read_with(handle, |data| {
+            let start = self.read_offset(data, offset)?;
+            let chunk = data.read(start, count);
+            let eof = start.saturating_add(chunk.len()) >= data.len();
+            Ok((chunk, eof))
+        })
+    }
+
+    /// Append `data` to the file at `path`, creating it if needed.
+    ///
+    /// Finding the end of the file and writing there happen under one write
+    /// lock, so concurrent appends never overwrite each other. A created file
+    /// gets the default mode and owner. Returns the number of bytes written.
+    pub async fn append_to(&self, path: &str, data: &[u8]) -> VfsResult<usize> {
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("append", &path)?;
+
+        if self.lookup(&nodes, &path).is_none() {
+            self.create_file_locked(&mut nodes, &path)?;
+        }
+
+        Ok(self.append_locked(&mut nodes, &path, data)?.written)
+    }
+
+    /// Replace the contents of the file at `path`, creating it if needed.
+    ///
+    /// Happens under one write lock and reports whether the file was new.
+    /// An existing file is rewritten in place, so its hard links see the
+    /// new contents, and its version moves once.
+    pub async fn put(&self, path: &str, data: &[u8]) -> VfsResult<PutOutcome> {
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("put", &path)?;
+
+        let outcome = match self.lookup(&nodes, &path) {
+            Some(node) => {
+                let mut inode = expect_file(&path, node)?.write()?;
+                self.journal(|| Record::Truncate { path: path.clone() })?;
+                inode.data.truncate(0)?;
+                if data.is_empty() {
+                    // The write below is a no-op, so record the change here
+                    inode.mtime = self.now();
+                    inode.version += 1;
+                    inode.changed = nodes.next_generation();
+                }
+                PutOutcome::Overwritten
+            }
+            None => {
+                self.create_file_locked(&mut nodes, &path)?;
+                PutOutcome::Created
+            }
+        };
+        self.write_locked(&mut nodes, &path, 0, &[data], None)?;
+        Ok(outcome)
+    }
+
+    /// Replace the contents of the file at `path` in `chunk`-byte pieces,
+    /// creating it if needed.
+    ///
+    /// The write lock is released between pieces, so other operations can
+    /// run in the middle of a huge write instead of waiting for all of it.
+    /// This trades atomicity for fairness: a concurrent reader may see the
+    /// file truncated or partly written. Use [`put`](Self::put) to replace
+    /// contents in one step. `chunk` is capped at the I/O limit.
+    pub async fn write_all_chunked(&self, path: &str, data: &[u8], chunk: usize) -> VfsResult<()> {
+        if chunk == 0 {
+            return Err(VfsError::InvalidArgument("chunk size must be non-zero".into()));
+        }
+        let chunk = self.io_count(chunk);
+        let handle = match self.open::<WriteOnly, File>(path, OWRITE | OTRUNC).await {
+            Err(VfsError::NotFound(_)) => self.create::<WriteOnly, File>(path, 0o644).await?,
+            opened => opened?,
+        };
+
+        let mut result = Ok(());
+        for (i, piece) in data.chunks(chunk).enumerate() {
+            if let Err(e) = self.write(&handle, (i * chunk) as u64, piece).await {
+                result = Err(e);
+                break;
+            }
+            runtime::yield_now().await;
+        }
+        self.clunk(handle.fid).await?;
+        result
+    }
+
+    /// Add an empty file at `path` with the default mode and owner, with
+    /// the write lock held
+    fn create_file_locked(&self, nodes: &mut NodeMap, path: &str) -> VfsResult<()> {
+        self.ensure_parent_exists(path, nodes)?;
+        self.journal(|| Record::Create {
+            path: path.to_string(),
+            kind: NodeKind::File,
+            mode: 0o644,
+            perm: None,
+        })?;
+        let mut node = self.new_file_node()?;
+        node.set_owner(self.default_owner.clone())?;
+        nodes.insert(path.to_string(), node);
+        self.touch_dirs(nodes, path);
+        Ok(())
+    }
+
+    /// Write `data` at the end of the file at `path` with the write lock held
+    fn append_locked(&self, nodes: &mut NodeMap, path: &str, data: &[u8]) -> VfsResult<WriteResult> {
+        let node = self.lookup(nodes, path).ok_or_else(|| VfsError::NotFound(path.to_string()))?;
+        let end = expect_file(path, node)?.read()?.data.len() as u64;
+        self.write_locked(nodes, path, end, &[data], None)
+    }
+
+    /// Open the file at `path` for reading and appending, like `fopen`'s
+    /// `"a+"`.
+    ///
+    /// `read` through the handle honors its offset, while `write` ignores
+    /// the offset and always writes at the current end of the file. Other
+    /// writing methods such as `write_vectored` still use their offset.
+    pub async fn open_append(&self, path: &str) -> VfsResult<FileHandle<File, ReadWrite>> {
+        let handle = self.open::<ReadWrite, File>(path, ORDWR).await?;
+        self.fids.lock()?.set_append(handle.fid);
+        Ok(handle)
+    }
+
+    /// Create the entry `name` inside the directory `dir` is open on.
+    ///
+    /// `name` must be a single component: empty names, `.`, `..`, and names
+    /// containing `/` or NUL are `InvalidPath`. The directory is found
+    /// through the handle, so it still works after the directory is renamed.
+    pub async fn create_in<M, T>(
+        &self,
+        dir: &FileHandle<Dir, ReadOnly>,
+        name: &str,
+        mode: u32,
+    ) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+            return Err(VfsError::InvalidPath(format!("invalid name: {}", name)));
+        }
+        let parent = self.handle_path(dir)?;
+        self.create(&join_path(&parent, name), mode).await
+    }
+
+    /// Write several buffers consecutively starting at `offset`.
+    ///
+    /// All buffers are applied atomically; returns the total bytes written.
+    pub async fn write_vectored<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        bufs: &[&[u8]],
+    ) -> VfsResult<usize> {
+        self.check_io_limit(bufs.iter().map(|buf| buf.len()).sum())?;
+        Ok(self.write_bufs(handle, offset, bufs, None)?.written)
+    }
+
+    /// Copy `len` bytes from `src_offset` to `dst_offset` within one file.
+    ///
+    /// The ranges may overlap, as with `memmove`. A destination running
+    /// past the end extends the file; a source running past it is
+    /// `BadOffset`. The version and mtime change once.
+    pub async fn copy_within<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        src_offset: u64,
+        dst_offset: u64,
+        len: usize,
+    ) -> VfsResult<()> {
+        let src = usize::try_from(src_offset).map_err(|_| VfsError::BadOffset)?;
+        let dst = usize::try_from(dst_offset).map_err(|_| VfsError::BadOffset)?;
+        let src_end = src.checked_add(len).ok_or(VfsError::BadOffset)?;
+        dst.checked_add(len).ok_or(VfsError::BadOffset)?;
+
+        let mut nodes = self.write_nodes("copy_within", &handle.path)?;
+        let path = &self.handle_path(handle)?;
+        let node = self.lookup(&nodes, path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        {
+            let mut inode = expect_file(path, node)?.write()?;
+            if src_end > inode.data.len() {
+                return Err(VfsError::BadOffset);
+            }
+            self.journal(|| Record::write(path, dst_offset, &[&inode.data.read(src, len)]))?;
+            inode.data.copy_within(src, dst, len)?;
+            inode.mtime = self.now();
+            inode.version += 1;
+            inode.changed = nodes.next_generation();
+        }
+        if self.propagate_mtime {
+            self.touch_dirs(&mut nodes, path);
+        }
+        Ok(())
+    }
+
+    /// Like `write`, but also return the file's qid after the write.
+    ///
+    /// The version in a handle's qid goes stale once the file is written;
+    /// callers can store the returned qid back into their handle.
+    pub async fn write_returning_qid<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<WriteResult> {
+        self.write_bufs(handle, offset, &[data], None)
+    }
+
+    /// Write only if the file's version still equals `expected_version`.
+    ///
+    /// Fails with `Stale` if the file changed since that version was
+    /// observed, e.g. from a `stat` or a previous write; the check and the
+    /// write happen under one lock.
+    pub async fn write_cas<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+        expected_version: u32,
+    ) -> VfsResult<usize> {
+        Ok(self.write_bufs(handle, offset, &[data], Some(expected_version))?.written)
+    }
+
+    /// Whether `path` is a directory with no entries.
+    ///
+    /// Fails with `NotADirectory` for a file and `NotFound` for a missing
+    /// path. Expired files don't count as entries.
+    pub async fn is_empty_dir(&self, path: &str) -> VfsResult<bool> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("is_empty_dir", &path)?;
+
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        expect_dir(&path, node)?;
+        Ok(!nodes
+            .children(&path)
+            .any(|name| self.lookup(&nodes, &join_path(&path, name)).is_some()))
+    }
+
+    /// Time since `path` was last modified, by this filesystem's clock.
+    ///
+    /// An mtime in the future (from clock skew) gives an age of zero rather
+    /// than an error.
+    pub async fn age(&self, path: &str) -> VfsResult<Duration> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("age", &path)?;
+
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        Ok(self.now().duration_since(node.mtime()).unwrap_or_default())
+    }
+
+    /// Stat every descendant of the directory at `path`, sorted by path
+    ///
+    /// Descendants are found by scanning paths rather than by recursing into
+    /// each directory, so an arbitrarily deep tree is safe to list.
+    pub async fn readdir_recursive(&self, path: &str) -> VfsResult<Vec<(String, Stat)>> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("readdir_recursive", &path)?;
+
+        let dir = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        expect_dir(&path, dir)?;
+
+        let prefix = if path == "/" { "/".to_string() } else { format!("{}/", path) };
+        let now = self.now();
+        let mut entries: Vec<(String, Stat)> = nodes
+            .iter()
+            .filter(|(p, node)| p.starts_with(&prefix) && **p != path && !node.is_expired(now))
+            .map(|(p, node)| (p.clone(), self.node_to_stat(&nodes, p, node)))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+
+    /// Call `visitor` on every descendant of the directory at `root`.
+    ///
+    /// Entries are visited in pre-order with siblings sorted by name, so a
+    /// directory comes right before its contents. Only one directory's
+    /// listing is held at a time and no lock is held while `visitor` runs.
+    /// An error from `visitor` stops the walk and is returned. Entries
+    /// removed during the walk are skipped.
+    pub async fn walk_tree<F, Fut>(&self, root: &str, mut visitor: F) -> VfsResult<()>
+    where
+        F: FnMut(&str, &Stat) -> Fut,
+        Fut: Future<Output = VfsResult<()>>,
+    {
+        let root = self.resolve_path(root)?;
+        // Listings are pushed reversed so they pop in name order
+        let mut pending = self.child_stats(&root)?;
+        pending.reverse();
+
+        while let Some((path, stat)) = pending.pop() {
+            visitor(&path, &stat).await?;
+            if stat.is_file() {
+                continue;
+            }
+            match self.child_stats(&path) {
+                Ok(mut children) => {
+                    children.reverse();
+                    pending.extend(children);
+                }
+                Err(VfsError::NotFound(_) | VfsError::NotADirectory(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Path and stat of each entry of the directory at `dir`, sorted by name
+    fn child_stats(&self, dir: &str) -> VfsResult<Vec<(String, Stat)>> {
+        let nodes = self.read_nodes("walk_tree", dir)?;
+        let node = self.lookup(&nodes, dir).ok_or_else(|| VfsError::NotFound(dir.to_string()))?;
+        expect_dir(dir, node)?;
+
+        Ok(self
+            .get_dir_children(dir, &nodes)
+            .into_iter()
+            .filter_map(|name| {
+                let path = join_path(dir, &name);
+                let node = self.lookup(&nodes, &path)?;
+                let stat = self.node_to_stat(&nodes, &path, node);
+                Some((path, stat))
+            })
+            .collect())
+    }
+
+    /// Open the node at `path`, creating it if nothing is there.
+    ///
+    /// The non-exclusive counterpart to `create`. An existing node of the
+    /// other kind fails as `open` would, with `IsADirectory` or
+    /// `NotADirectory`. If the node is created or removed between the open
+    /// and the create, the attempt is repeated.
+    pub async fn open_or_create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        loop {
+            match self.open::<M, T>(path, mode).await {
+                Err(VfsError::NotFound(_)) => {}
+                result => return result,
+            }
+            match self.create::<M, T>(path, mode).await {
+                Err(VfsError::AlreadyExists(_)) => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// Open an existing file for writing and empty it first.
+    ///
+    /// Shorthand for `open` with `OTRUNC` and the write mode matching `M`.
+    /// A plain write-mode `open` leaves the contents alone, so writing fewer
+    /// bytes than the file holds keeps the old tail.
+    pub async fn open_truncating<M>(&self, path: &str) -> VfsResult<FileHandle<File, M>>
+    where
+        M: Access + CanWrite,
+    {
+        let access = if M::READ { ORDWR } else { OWRITE };
+        self.open::<M, File>(path, access | OTRUNC).await
+    }
+
+    /// Every path, sorted, whose `(path, stat)` satisfies `predicate`
+    pub fn find<F: Fn(&str, &Stat) -> bool>(&self, predicate: F) -> VfsResult<Vec<String>> {
+        let nodes = self.read_nodes("find", "/")?;
+        let now = self.now();
+
+        let mut paths: Vec<String> = nodes
+            .iter()
+            .filter(|(_, node)| !node.is_expired(now))
+            .filter(|(path, node)| predicate(path, &self.node_to_stat(&nodes, path, node)))
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Remove a file or empty directory, returning its `Stat` from just before.
+    ///
+    /// The stat and the removal happen under one write lock, so the result
+    /// describes exactly what was removed.
+    pub async fn remove_stat<T>(&self, path: &str) -> VfsResult<Stat> {
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("remove_stat", &path)?;
+        let stat = self
+            .lookup(&nodes, &path)
+            .map(|node| self.node_to_stat(&nodes, &path, node));
+        self.remove_locked(&mut nodes, &path)?;
+        stat.ok_or(VfsError::NotFound(path))
+    }
+
+    /// Remove each of `paths` under a single write lock.
+    ///
+    /// Each path gets its own result, so a missing path or a non-empty
+    /// directory fails on its own without aborting the rest. Paths are
+    /// removed in order, so a directory listed after its contents goes too.
+    pub async fn remove_many(&self, paths: &[String]) -> VfsResult<Vec<(String, VfsResult<()>)>> {
+        let mut nodes = self.write_nodes("remove_many", "/")?;
+        Ok(paths
+            .iter()
+            .map(|path| {
+                let result = self
+                    .resolve_path_in(&nodes, path)
+                    .and_then(|resolved| self.remove_locked(&mut nodes, &resolved))
+                    .map(|_| ());
+                (path.clone(), result)
+            })
+            .collect())
+    }
+
+    /// Stat each of `paths` under a single read lock.
+    ///
+    /// Each path gets its own result, so a missing or invalid path fails on
+    /// its own without affecting the rest.
+    pub async fn stat_many(&self, paths: &[String]) -> VfsResult<Vec<(String, VfsResult<Stat>)>> {
+        let nodes = self.read_nodes("stat_many", "/")?;
+        Ok(paths
+            .iter()
+            .map(|path| {
+                let result = self.resolve_path_in(&nodes, path).and_then(|resolved| {
+                    self.lookup(&nodes, &resolved)
+                        .map(|node| self.node_to_stat(&nodes, &resolved, node))
+                        .ok_or(VfsError::NotFound(resolved))
+                });
+                (path.clone(), result)
+            })
+            .collect())
+    }
+
+    /// Create every `(path, kind, mode)` in `specs` under a single write lock.
+    ///
+    /// `mode` holds permission bits, of which the low 12 (`0o7777`) are
+    /// kept; ancestors created along the way get `0o755`.
+    ///
+    /// Specs may come in any order: they are applied shallowest first, and
+    /// missing ancestor directories are created along the way. Paths that
+    /// already exist with the same kind are left alone. If any path or
+    /// ancestor exists with a different kind, the call fails with
+    /// `NotADirectory` or `IsADirectory` naming it before anything is
+    /// created.
+    pub async fn create_many(&self, specs: &[(String, NodeKind, u32)]) -> VfsResult<()> {
+        let mut specs = specs
+            .iter()
+            .map(|(path, kind, mode)| Ok((self.resolve_path(path)?, *kind, *mode)))
+            .collect::<VfsResult<Vec<_>>>()?;
+        specs.sort_by_key(|(path, _, _)| path.matches('/').count());
+
+        let mut nodes = self.write_nodes("create_many", "/")?;
+
+        // Plan every creation first so a conflict leaves the tree untouched
+        let mut planned: HashMap<String, NodeKind> = HashMap::new();
+        let mut order = Vec::new();
+        for (path, kind, mode) in specs {
+            let ancestors = path
+                .match_indices('/')
+                .skip(1)
+                .map(|(i, _)| (path[..i].to_string(), NodeKind::Dir, 0o755));
+            for (target, kind, mode) in ancestors.chain(std::iter::once((path.clone(), kind, mode))) {
+                let existing = planned
+                    .get(&target)
+                    .copied()
+                    .or_else(|| self.lookup(&nodes, &target).map(Node::kind));
+                match existing {
+                    Some(found) if found == kind => {}
+                    Some(NodeKind::File) => return Err(VfsError::NotADirectory(target)),
+                    Some(NodeKind::Dir) => return Err(VfsError::IsADirectory(target)),
+                    None => {
+                        planned.insert(target.clone(), kind);
+                        order.push((target, kind, mode));
+                    }
+                }
+            }
+        }
+
+        for (path, kind, mode) in order {
+            let perm = mode & 0o7777;
+            self.journal(|| Record::Create {
+                path: path.clone(),
+                kind,
+                mode: OREAD,
+                perm: Some(perm),
+            })?;
+            let mut node = match kind {
+                NodeKind::File => {
+                    self.touch(&path)?;
+                    self.new_file_node()?
+                }
+                NodeKind::Dir => Node::new_dir(self.now()),
+            };
+            node.set_mode(perm)?;
+            node.set_owner(self.default_owner.clone())?;
+            nodes.insert(path.clone(), node);
+            self.touch_dirs(&mut nodes, &path);
+        }
+        Ok(())
+    }
+
+    /// Remove the node at the normalized `path`, returning it
+    pub(super) fn remove_locked(&self, nodes: &mut NodeMap, path: &str) -> VfsResult<Node> {
+        // Can't remove root
+        if path == "/" {
+            return Err(VfsError::PermissionDenied("cannot remove root".into()));
+        }
+
+        // Check if it's a directory with children
+        if let Some(node) = nodes.get(path)
+            && node.is_dir()
+            && !self.get_dir_children(path, nodes).is_empty()
+        {
+            return Err(VfsError::DirectoryNotEmpty(path.to_string()));
+        }
+
+        if !nodes.contains_key(path) {
+            return Err(VfsError::NotFound(path.to_string()));
+        }
+        self.journal(|| Record::Remove { path: path.to_string() })?;
+
+        let node = nodes.remove(path).ok_or_else(|| VfsError::NotFound(path.to_string()))?;
+        if self.unlinked_writes
+            && let Node::File { inode, .. } = &node
+        {
+            self.fids.lock()?.orphan(path, inode);
+        }
+        self.touch_dirs(nodes, path);
+        if let Some(lru) = &self.lru {
+            lru.lock()?.forget(path);
+        }
+
+        Ok(node)
+    }
+
+    /// Ensure parent directory exists.
+    ///
+    /// Fails with `NotADirectory` naming the first file found among the
+    /// ancestors, or `NotFound("parent directory: ...")` if the parent is
+    /// simply missing.
+    pub(super) fn ensure_parent_exists(&self, path: &str, nodes: &NodeMap) -> VfsResult<()> {
         if path == "/" {
             return Ok(());
         }
 
-        let parent = path
-            .rsplit_once('/')
-            .map(|(p, _)| if p.is_empty() { "/" } else { p })
-            .unwrap_or("/");
+        let parent = parent_path(path);
 
-        match nodes.get(parent) {
+        match self.lookup(nodes, parent) {
             Some(node) if node.is_dir() => Ok(()),
             Some(_) => Err(VfsError::NotADirectory(parent.to_string())),
-            None => Err(VfsError::NotFound(format!("parent directory: {}", parent))),
+            None => {
+                // A file further up is the real problem, so name it instead
+                let blocking = parent
+                    .match_indices('/')
+                    .skip(1)
+                    .map(|(i, _)| &parent[..i])
+                    .find(|ancestor| self.lookup(nodes, ancestor).is_some_and(Node::is_file));
+                match blocking {
+                    Some(file) => Err(VfsError::NotADirectory(file.to_string())),
+                    None => Err(VfsError::NotFound(format!("parent directory: {}", parent))),
+                }
+            }
         }
     }
 }
@@ -149,12 +1669,16 @@ impl Default for VfsMem {
 #[async_trait::async_trait]
 impl VfsBackend for VfsMem {
     async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
-        let start = Self::normalize_path(start)?;
-        let nodes = self.nodes.read().unwrap();
+        let start = self.resolve_path(start)?;
+        let nodes = self.read_nodes("walk", &start)?;
 
-        // Verify start exists
-        if !nodes.contains_key(&start) {
-            return Err(VfsError::NotFound(start));
+        // Verify start exists, and is a directory if there is anything to walk
+        match self.lookup(&nodes, &start) {
+            None => return Err(VfsError::NotFound(start)),
+            Some(node) if node.is_file() && !names.is_empty() => {
+                return Err(VfsError::NotADirectory(start));
+            }
+            Some(_) => {}
         }
 
         let mut current = start;
@@ -162,11 +1686,20 @@ impl VfsBackend for VfsMem {
 
         // Walk each component
         for name in names {
-            // Validate name (no slashes, no ..)
-            if name.contains('/') || name == ".." {
+            // Validate name (no slashes, no NUL, no ..)
+            if name.contains(['/', '\0']) || name == ".." {
                 return Err(VfsError::InvalidPath(format!("invalid name: {}", name)));
             }
 
+            // "." stays put but still answers with a qid, so every name in a
+            // successful walk has one
+            if name == "." {
+                let node = self.lookup(&nodes, &current)
+                    .ok_or_else(|| VfsError::NotFound(current.clone()))?;
+                qids.push(self.node_qid(&current, node));
+                continue;
+            }
+
             // Build next path
             let next = if current == "/" {
                 format!("/{}", name)
@@ -175,14 +1708,8 @@ impl VfsBackend for VfsMem {
             };
 
             // Check if it exists
-            if let Some(node) = nodes.get(&next) {
-                let qid_path = self.path_to_qid_path(&next);
-                let qid = if node.is_file() {
-                    Qid::new_file(qid_path, node.version())
-                } else {
-                    Qid::new_dir(qid_path, 0)
-                };
-                qids.push(qid);
+            if let Some(node) = self.lookup(&nodes, &next) {
+                qids.push(self.node_qid(&next, node));
                 current = next;
             } else {
                 // Partial walk is OK in 9P - return what we have
@@ -194,79 +1721,128 @@ impl VfsBackend for VfsMem {
     }
 
     async fn stat(&self, path: &str) -> VfsResult<Stat> {
-        let path = Self::normalize_path(path)?;
-        let nodes = self.nodes.read().unwrap();
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("stat", &path)?;
 
-        let node = nodes
-            .get(&path)
-            .ok_or_else(|| VfsError::NotFound(path.clone()))?;
-        Ok(self.node_to_stat(&path, node))
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        Ok(self.node_to_stat(&nodes, &path, node))
+    }
+
+    async fn kind(&self, path: &str) -> VfsResult<NodeKind> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("kind", &path)?;
+
+        self.lookup(&nodes, &path)
+            .map(Node::kind)
+            .ok_or(VfsError::NotFound(path))
     }
 
     async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
     where
-        M: Send + Sync + 'static,
-        T: Send + Sync + 'static,
+        M: Access,
+        T: NodeType,
     {
-        let path = Self::normalize_path(path)?;
-        let nodes = self.nodes.read().unwrap();
-
-        let node = nodes
-            .get(&path)
-            .ok_or_else(|| VfsError::NotFound(path.clone()))?;
-
-        // Type check based on T
-        let type_name = std::any::type_name::<T>();
-        let (qid, is_valid) = if node.is_file() {
-            let qid = Qid::new_file(self.path_to_qid_path(&path), node.version());
-            (qid, type_name.contains("File"))
+        let path = self.resolve_path(path)?;
+        // The root is always a directory
+        if path == "/" && T::KIND == NodeKind::File {
+            return Err(VfsError::IsADirectory(path));
+        }
+        let open_mode = OpenMode::parse(mode)?;
+        open_mode.check::<M>(&path)?;
+        // Truncating changes the file, so it needs the write lock
+        let (read_guard, write_guard);
+        let nodes: &NodeMap = if open_mode.truncate {
+            write_guard = self.write_nodes("open", &path)?;
+            &write_guard
         } else {
-            let qid = Qid::new_dir(self.path_to_qid_path(&path), 0);
-            (qid, type_name.contains("Dir"))
+            read_guard = self.read_nodes("open", &path)?;
+            &read_guard
         };
 
-        if !is_valid {
-            return if node.is_file() {
-                Err(VfsError::NotADirectory(path))
-            } else {
-                Err(VfsError::IsADirectory(path))
-            };
+        let node = self.lookup(nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+
+        match T::KIND {
+            NodeKind::File => expect_file(&path, node).map(|_| ())?,
+            NodeKind::Dir => expect_dir(&path, node)?,
+        }
+        if node.is_dir() && open_mode.wants_write() {
+            return Err(VfsError::IsADirectory(path));
+        }
+
+        // Take the fid first so hitting the open-handle cap changes nothing
+        let fid = self.next_fid(&path, mode, open_mode.remove_on_close)?;
+        if let Node::File { inode, .. } = node
+            && open_mode.truncate
+            && let Err(e) = self.truncate_on_open(nodes, &path, inode)
+        {
+            self.fids.lock()?.release(fid);
+            return Err(e);
         }
 
-        let fid = self.next_fid();
+        let qid = self.typed_qid(&path, node);
+        self.emit(VfsEvent::Opened { path: path.clone(), fid });
         Ok(FileHandle::new(fid, qid, path, mode))
     }
 
     async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
     where
         M: Send + Sync + 'static,
-        T: Send + Sync + 'static,
+        T: NodeType,
     {
-        let path = Self::normalize_path(path)?;
+        let raw = path;
+        let path = self.resolve_path(path)?;
+        // The root always exists, whatever kind is asked for
+        if path == "/" {
+            return Err(VfsError::AlreadyExists(path));
+        }
+        // The existence check and the insert below share this one lock, so
+        // concurrent creates of the same path have exactly one winner
+        let mut nodes = self.write_nodes("create", &path)?;
 
-        {
-            let mut nodes = self.nodes.write().unwrap();
+        // Check if already exists (an expired file is simply replaced). Like
+        // `O_EXCL`, this wins over any mismatch with the requested kind.
+        if self.lookup(&nodes, &path).is_some() {
+            return Err(VfsError::AlreadyExists(path));
+        }
+        if T::KIND == NodeKind::File && self.wants_dir(raw) {
+            return Err(VfsError::NotADirectory(path));
+        }
 
-            // Check if already exists
-            if nodes.contains_key(&path) {
-                return Err(VfsError::AlreadyExists(path));
-            }
+        // Ensure parent directory exists
+        self.ensure_parent_exists(&path, &nodes)?;
 
-            // Ensure parent directory exists
-            self.ensure_parent_exists(&path, &nodes)?;
+        // A mode of open flags alone may ask for ORCLOSE; permission bits
+        // such as 0o755 are not open flags, however they overlap
+        let remove_on_close = OpenMode::parse(mode).is_ok_and(|mode| mode.remove_on_close);
 
-            // Create based on type T
-            let type_name = std::any::type_name::<T>();
-            if type_name.contains("File") {
-                nodes.insert(path.clone(), Node::new_file());
-            } else if type_name.contains("Dir") {
-                nodes.insert(path.clone(), Node::new_dir());
-            } else {
-                return Err(VfsError::InvalidArgument("unknown type".into()));
-            }
+        // Take the fid first so hitting the open-handle cap creates nothing
+        let fid = self.next_fid(&path, mode, remove_on_close)?;
+        // The removal on clunk is journaled when it happens
+        let logged = self.journal(|| Record::Create {
+            path: path.clone(),
+            kind: T::KIND,
+            mode: mode & !ORCLOSE,
+            perm: None,
+        });
+        if let Err(e) = logged {
+            self.fids.lock()?.release(fid);
+            return Err(e);
         }
 
-        self.open::<M, T>(&path, mode).await
+        let mut node = match T::KIND {
+            NodeKind::File => {
+                self.touch(&path)?;
+                self.new_file_node()?
+            }
+            NodeKind::Dir => Node::new_dir(self.now()),
+        };
+        node.set_owner(self.default_owner.clone())?;
+        let qid = self.typed_qid(&path, &node);
+        nodes.insert(path.clone(), node);
+        self.touch_dirs(&mut nodes, &path);
+        self.emit(VfsEvent::Opened { path: path.clone(), fid });
+
+        Ok(FileHandle::new(fid, qid, path, mode))
     }
 
     async fn read<M: CanRead>(
@@ -275,22 +1851,18 @@ impl VfsBackend for VfsMem {
         offset: u64,
         count: usize,
     ) -> VfsResult<Vec<u8>> {
-        let nodes = self.nodes.read().unwrap();
-        let node = nodes
-            .get(&handle.path)
-            .ok_or_else(|| VfsError::NotFound(handle.path.clone()))?;
-
-        match node {
-            Node::File { data, .. } => {
-                let start = offset as usize;
-                if start > data.len() {
-                    return Ok(Vec::new());
-                }
-                let end = std::cmp::min(start + count, data.len());
-                Ok(data[start..end].to_vec())
-            }
-            Node::Dir { .. } => Err(VfsError::IsADirectory(handle.path.clone())),
-        }
+        Ok(self.read_bytes(handle, offset, count).await?.into())
+    }
+
+    async fn read_into<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let count = self.io_count(buf.len());
+        let buf = &mut buf[..count];
+        self.read_with(handle, |data| Ok(data.read_into(self.read_offset(data, offset)?, buf)))
     }
 
     async fn write<M: CanWrite>(
@@ -299,87 +1871,118 @@ impl VfsBackend for VfsMem {
         offset: u64,
         data: &[u8],
     ) -> VfsResult<usize> {
-        let mut nodes = self.nodes.write().unwrap();
-        let node = nodes
-            .get_mut(&handle.path)
-            .ok_or_else(|| VfsError::NotFound(handle.path.clone()))?;
-
-        match node {
-            Node::File {
-                data: file_data,
-                mtime,
-                version,
-            } => {
-                let start = offset as usize;
-
-                // Extend file if necessary
-                if start + data.len() > file_data.len() {
-                    file_data.resize(start + data.len(), 0);
-                }
+        self.check_io_limit(data.len())?;
+        let append = self.fids.lock()?.appends(handle.fid);
+        if let Some(inode) = self.orphaned(handle.fid)? {
+            if data.is_empty() {
+                return Ok(0);
+            }
+            let mut inode = inode.write()?;
+            let start = match append {
+                true => inode.data.len(),
+                false => usize::try_from(offset).map_err(|_| VfsError::BadOffset)?,
+            };
+            start.checked_add(data.len()).ok_or(VfsError::BadOffset)?;
+            self.check_gap(start, inode.data.len())?;
 
-                file_data[start..start + data.len()].copy_from_slice(data);
-                *mtime = SystemTime::now();
-                *version += 1;
+            inode.data.write(start, data)?;
+            inode.mtime = self.now();
+            inode.version += 1;
+            return Ok(data.len());
+        }
 
-                Ok(data.len())
-            }
-            Node::Dir { .. } => Err(VfsError::IsADirectory(handle.path.clone())),
+        if append {
+            let mut nodes = self.write_nodes("write", &handle.path)?;
+            let path = self.handle_path(handle)?;
+            return Ok(self.append_locked(&mut nodes, &path, data)?.written);
         }
+        Ok(self.write_bufs(handle, offset, &[data], None)?.written)
+    }
+
+    async fn reserve<M: CanWrite>(&self, handle: &FileHandle<File, M>, additional: u64) -> VfsResult<()> {
+        let additional = usize::try_from(additional)
+            .map_err(|_| VfsError::InvalidArgument(format!("cannot reserve {} bytes", additional)))?;
+        let nodes = self.read_nodes("reserve", &handle.path)?;
+        let path = self.handle_path(handle)?;
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+
+        expect_file(&path, node)?.write()?.data.reserve(additional)
     }
 
     async fn remove<T>(&self, path: &str) -> VfsResult<()> {
-        let path = Self::normalize_path(path)?;
+        let path = self.resolve_path(path)?;
+        let mut nodes = self.write_nodes("remove", &path)?;
+        self.remove_locked(&mut nodes, &path)?;
+        Ok(())
+    }
 
-        // Can't remove root
-        if path == "/" {
-            return Err(VfsError::PermissionDenied("cannot remove root".into()));
-        }
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.rename_opts(from, to, false).await
+    }
 
-        let mut nodes = self.nodes.write().unwrap();
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        let nodes = self.read_nodes("readdir", &handle.path)?;
+        let path = self.handle_path(handle)?;
+        let node = nodes.get(&path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
 
-        // Check if it's a directory with children
-        if let Some(node) = nodes.get(&path) {
-            if node.is_dir() {
-                let children = self.get_dir_children(&path, &nodes);
-                if !children.is_empty() {
-                    return Err(VfsError::InvalidArgument("directory not empty".into()));
-                }
+        expect_dir(&path, node)?;
+
+        let children = self.get_dir_children(&path, &nodes);
+        let mut stats = Vec::new();
+
+        for child_name in children {
+            let child_path = join_path(&path, &child_name);
+
+            if let Some(child_node) = self.lookup(&nodes, &child_path) {
+                stats.push(self.node_to_stat(&nodes, &child_path, child_node));
             }
         }
 
-        nodes
-            .remove(&path)
-            .ok_or_else(|| VfsError::NotFound(path))?;
+        Ok(stats)
+    }
 
-        Ok(())
+    async fn walk_from<M: Send + Sync + 'static>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        names: &[String],
+    ) -> VfsResult<WalkResult> {
+        // Walk from where the directory is now, which a rename may have changed
+        self.walk(&self.handle_path(handle)?, names).await
     }
 
-    async fn readdir(&self, handle: &FileHandle<Dir, ReadOnly>) -> VfsResult<Vec<Stat>> {
-        let nodes = self.nodes.read().unwrap();
-        let node = nodes
-            .get(&handle.path)
-            .ok_or_else(|| VfsError::NotFound(handle.path.clone()))?;
+    async fn reopen<M2, M>(&self, handle: &FileHandle<File, M>, mode: u32) -> VfsResult<FileHandle<File, M2>>
+    where
+        M2: Access,
+        M: Send + Sync + 'static,
+    {
+        self.open::<M2, File>(&self.handle_path(handle)?, mode).await
+    }
 
-        match node {
-            Node::Dir { .. } => {
-                let children = self.get_dir_children(&handle.path, &nodes);
-                let mut stats = Vec::new();
-
-                for child_name in children {
-                    let child_path = if handle.path == "/" {
-                        format!("/{}", child_name)
-                    } else {
-                        format!("{}/{}", handle.path, child_name)
-                    };
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.clunk_fid(fid)
+    }
+}
 
-                    if let Some(child_node) = nodes.get(&child_path) {
-                        stats.push(self.node_to_stat(&child_path, child_node));
-                    }
-                }
+impl VfsMem {
+    /// Release `fid`, removing its file if it was opened with `ORCLOSE`.
+    ///
+    /// Never waits, so it can run from `Drop`.
+    pub(super) fn clunk_fid(&self, fid: u64) -> VfsResult<()> {
+        let open = self
+            .fids
+            .lock()?
+            .release(fid)
+            .ok_or_else(|| VfsError::InvalidArgument(format!("unknown fid {}", fid)))?;
+        self.emit(VfsEvent::Clunked { fid });
 
-                Ok(stats)
+        // The fid is released even if the ORCLOSE removal then fails
+        if open.remove_on_close {
+            let mut nodes = self.write_nodes("clunk", &open.path)?;
+            match self.remove_locked(&mut nodes, &open.path) {
+                Ok(_) | Err(VfsError::NotFound(_)) => {}
+                Err(e) => return Err(e),
             }
-            Node::File { .. } => Err(VfsError::NotADirectory(handle.path.clone())),
         }
+        Ok(())
     }
 }
@@ -0,0 +1,178 @@
+//! Node storage with a per-directory index of children.
+
+use std::collections::{BTreeSet, HashMap, hash_map};
+#[cfg(test)]
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::fs::parent_path;
+use super::node::Node;
+
+/// Every node keyed by normalized path, plus the names of each directory's
+/// immediate children.
+///
+/// The flat map is the source of truth; `insert`, `remove`, and `retain`
+/// keep the index in step with it, so listing a directory costs
+/// O(children) instead of a scan over every node.
+///
+/// A generation counter advances on every change; `insert` stamps the new
+/// node and `remove` and `retain` stamp the parent of what they drop.
+/// A node inserted for the first time also keeps that generation as its
+/// creation generation, which moving it to another path preserves.
+/// Content changes stamp their node with [`NodeMap::next_generation`].
+///
+/// Every file node going in or out also adjusts its inode's link count, so
+/// `nlink` counts exactly the paths in the map sharing that inode.
+#[derive(Debug, Default)]
+pub(super) struct NodeMap {
+    nodes: HashMap<String, Node>,
+    children: HashMap<String, BTreeSet<String>>,
+    generation: AtomicU64,
+    /// Number of full scans, so tests can check listings avoid them
+    #[cfg(test)]
+    pub scans: AtomicUsize,
+}
+
+/// Split a non-root path into its parent and final name
+fn split(path: &str) -> Option<(&str, &str)> {
+    if path == "/" {
+        return None;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    Some((parent_path(path), name))
+}
+
+impl NodeMap {
+    pub fn get(&self, path: &str) -> Option<&Node> {
+        self.nodes.get(path)
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Node> {
+        self.nodes.get_mut(path)
+    }
+
+    pub fn contains_key(&self, path: &str) -> bool {
+        self.nodes.contains_key(path)
+    }
+
+    pub fn insert(&mut self, path: String, mut node: Node) -> Option<Node> {
+        if let Some((parent, name)) = split(&path) {
+            self.children
+                .entry(parent.to_string())
+                .or_default()
+                .insert(name.to_string());
+        }
+        // Never hand out a generation a moved-in node already carries
+        self.generation.fetch_max(node.generation(), Ordering::Relaxed);
+        let generation = self.next_generation();
+        if node.generation() == 0 {
+            node.set_generation(generation);
+        }
+        node.mark_changed(generation);
+        node.add_link();
+        let replaced = self.nodes.insert(path, node);
+        if let Some(old) = &replaced {
+            old.drop_link();
+        }
+        replaced
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Node> {
+        let node = self.nodes.remove(path)?;
+        node.drop_link();
+        self.unindex(path);
+        Some(node)
+    }
+
+    /// Drop every node, keeping the generation moving forward
+    pub fn clear(&mut self) {
+        for node in self.nodes.values() {
+            node.drop_link();
+        }
+        self.nodes.clear();
+        self.children.clear();
+        self.next_generation();
+    }
+
+    /// Advance the generation and return the new value
+    pub fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// The generation of the most recent change
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Stamp the node at `path` with a new generation
+    pub fn mark_changed(&mut self, path: &str) {
+        let generation = self.next_generation();
+        if let Some(node) = self.nodes.get_mut(path) {
+            node.mark_changed(generation);
+        }
+    }
+
+    /// Keep only the nodes for which `keep` returns true
+    pub fn retain(&mut self, mut keep: impl FnMut(&String, &mut Node) -> bool) {
+        self.count_scan();
+        let mut dropped = Vec::new();
+        self.nodes.retain(|path, node| {
+            let kept = keep(path, node);
+            if !kept {
+                node.drop_link();
+                dropped.push(path.clone());
+            }
+            kept
+        });
+        for path in dropped {
+            self.unindex(&path);
+        }
+    }
+
+    fn unindex(&mut self, path: &str) {
+        if let Some((parent, _)) = split(path) {
+            self.mark_changed(parent);
+        }
+        if let Some((parent, name)) = split(path)
+            && let Some(names) = self.children.get_mut(parent)
+        {
+            names.remove(name);
+            if names.is_empty() {
+                self.children.remove(parent);
+            }
+        }
+    }
+
+    /// Names of the immediate children of `dir`, sorted
+    pub fn children(&self, dir: &str) -> impl Iterator<Item = &String> {
+        self.children.get(dir).into_iter().flatten()
+    }
+
+    /// Take every node, dropping the index
+    pub fn into_nodes(self) -> HashMap<String, Node> {
+        self.nodes
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, String, Node> {
+        self.count_scan();
+        self.nodes.iter()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, String, Node> {
+        self.count_scan();
+        self.nodes.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, String, Node> {
+        self.count_scan();
+        self.nodes.values()
+    }
+
+    #[cfg(test)]
+    fn count_scan(&self) {
+        self.scans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(not(test))]
+    fn count_scan(&self) {}
+}
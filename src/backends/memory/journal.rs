@@ -0,0 +1,193 @@
+//! Write-ahead journal of mutations for rebuilding a filesystem.
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::VfsBackend;
+use crate::error::{VfsError, VfsResult};
+use crate::mode::{OTRUNC, OWRITE};
+use crate::types::{Dir, File, NodeKind, ReadOnly, WriteOnly};
+
+use super::fs::VfsMem;
+
+/// One journaled mutation, stored as a line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(super) enum Record {
+    /// `mode` is the open mode passed to `create`; `perm` is set when the
+    /// node was given permission bits other than the default
+    Create {
+        path: String,
+        kind: NodeKind,
+        mode: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        perm: Option<u32>,
+    },
+    Write { path: String, offset: u64, data: String },
+    Remove { path: String },
+    Rename { from: String, to: String, overwrite: bool },
+    Exchange { a: String, b: String },
+    Truncate { path: String },
+    Chmod { path: String, mode: u32 },
+    Chown { path: String, uid: String, gid: String },
+    Link { existing: String, path: String },
+    CloneFile { from: String, to: String },
+    Clear,
+}
+
+impl Record {
+    pub fn write(path: &str, offset: u64, bufs: &[&[u8]]) -> Self {
+        Record::Write {
+            path: path.to_string(),
+            offset,
+            data: base64::engine::general_purpose::STANDARD.encode(bufs.concat()),
+        }
+    }
+}
+
+/// Append-only log file, or a transaction's records held back from one
+#[derive(Debug)]
+pub(super) enum Journal {
+    File(Mutex<std::fs::File>),
+    /// Lines kept until [`commit`](Journal::commit) hands them to `parent`
+    Buffer {
+        parent: Arc<Journal>,
+        lines: Mutex<Vec<u8>>,
+    },
+}
+
+impl Journal {
+    /// A journal that holds records back from `parent` until committed
+    pub fn buffered(parent: Arc<Journal>) -> Self {
+        Journal::Buffer {
+            parent,
+            lines: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Append `record` and flush it to the file
+    pub fn append(&self, record: &Record) -> VfsResult<()> {
+        let mut line = serde_json::to_vec(record).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+        self.append_lines(&line)
+    }
+
+    /// Pass every held-back record on to the parent journal
+    pub fn commit(&self) -> VfsResult<()> {
+        match self {
+            Journal::File(_) => Ok(()),
+            Journal::Buffer { parent, lines } => {
+                let lines = std::mem::take(&mut *lines.lock()?);
+                parent.append_lines(&lines)
+            }
+        }
+    }
+
+    fn append_lines(&self, bytes: &[u8]) -> VfsResult<()> {
+        match self {
+            Journal::File(file) => {
+                let mut file = file.lock()?;
+                file.write_all(bytes)?;
+                file.flush()?;
+            }
+            Journal::Buffer { lines, .. } => lines.lock()?.extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+}
+
+impl VfsMem {
+    /// Journal every `create`, `write`, `remove`, `rename`, `exchange`,
+    /// `chmod`, `chown`, `link`, `clone_file`, `clear`, `apply_delta`, tar
+    /// import, and `OTRUNC` open to the file at `path` before applying it.
+    /// LRU evictions and files reaped after their TTL are journaled as
+    /// removes once they happen.
+    ///
+    /// Records are appended to any existing log, and those of a
+    /// [`transaction`](Self::transaction) only once it commits. TTLs
+    /// themselves are not journaled, so a replayed file never expires.
+    pub fn with_journal(mut self, path: PathBuf) -> VfsResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.journal = Some(Arc::new(Journal::File(Mutex::new(file))));
+        Ok(self)
+    }
+
+    /// Rebuild a filesystem by replaying the journal at `path`.
+    ///
+    /// Replay stops at the first record that can't be parsed, such as one
+    /// cut short by a crash. Only operations that passed their checks are
+    /// journaled, so a record that fails to apply means the log doesn't
+    /// match the tree it came from; that fails with `ReplayFailed`.
+    pub async fn replay(path: impl AsRef<Path>) -> VfsResult<Self> {
+        let vfs = VfsMem::new();
+        let reader = BufReader::new(std::fs::File::open(path)?);
+
+        for (index, line) in reader.lines().enumerate() {
+            let Ok(record) = serde_json::from_str::<Record>(&line?) else {
+                break;
+            };
+            vfs.apply(record).await.map_err(|e| VfsError::ReplayFailed {
+                index,
+                source: Box::new(e),
+            })?;
+        }
+        Ok(vfs)
+    }
+
+    /// Log `record` if journaling is enabled
+    pub(super) fn journal(&self, record: impl FnOnce() -> Record) -> VfsResult<()> {
+        match &self.journal {
+            Some(journal) => journal.append(&record()),
+            None => Ok(()),
+        }
+    }
+
+    async fn apply(&self, record: Record) -> VfsResult<()> {
+        match record {
+            Record::Create { path, kind, mode, perm } => {
+                let fid = match kind {
+                    NodeKind::File => self.create::<ReadOnly, File>(&path, mode).await?.fid,
+                    NodeKind::Dir => self.create::<ReadOnly, Dir>(&path, mode).await?.fid,
+                };
+                self.clunk(fid).await?;
+                match perm {
+                    Some(perm) => self.chmod(&path, perm).await,
+                    None => Ok(()),
+                }
+            }
+            Record::Chmod { path, mode } => self.chmod(&path, mode).await,
+            Record::Chown { path, uid, gid } => self.chown(&path, &uid, &gid).await,
+            Record::Link { existing, path } => self.link(&existing, &path).await,
+            Record::CloneFile { from, to } => self.clone_file(&from, &to).await,
+            Record::Write { path, offset, data } => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| VfsError::InvalidArgument(e.to_string()))?;
+                let handle = self.open::<WriteOnly, File>(&path, 0).await?;
+                let result = self.write(&handle, offset, &data).await;
+                self.clunk(handle.fid).await?;
+                result.map(|_| ())
+            }
+            Record::Remove { path } => match self.kind(&path).await? {
+                NodeKind::File => self.remove::<File>(&path).await,
+                NodeKind::Dir => self.remove::<Dir>(&path).await,
+            },
+            Record::Rename {
+                from,
+                to,
+                overwrite,
+            } => self.rename_opts(&from, &to, overwrite).await,
+            Record::Exchange { a, b } => self.exchange(&a, &b).await,
+            Record::Truncate { path } => {
+                let handle = self.open::<WriteOnly, File>(&path, OWRITE | OTRUNC).await?;
+                self.clunk(handle.fid).await
+            }
+            Record::Clear => self.clear(),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Line-by-line streaming reads.
+
+use futures::stream::{self, Stream};
+
+use crate::backend::VfsBackend;
+use crate::error::{VfsError, VfsResult};
+use crate::types::{File, FileHandle, ReadOnly};
+
+use super::fs::VfsMem;
+
+/// Bytes fetched per read while splitting lines
+const LINE_CHUNK: usize = 8 * 1024;
+
+/// Progress of a line stream
+struct LineReader {
+    vfs: VfsMem,
+    path: String,
+    handle: Option<FileHandle<File, ReadOnly>>,
+    offset: u64,
+    buf: Vec<u8>,
+    eof: bool,
+    chunk: usize,
+}
+
+impl LineReader {
+    /// Produce the next line, reading more chunks as needed
+    async fn next_line(&mut self) -> Option<VfsResult<String>> {
+        loop {
+            if let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+                let mut line: Vec<u8> = self.buf.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Some(decode(line));
+            }
+
+            if self.eof {
+                if self.buf.is_empty() {
+                    return None;
+                }
+                return Some(decode(std::mem::take(&mut self.buf)));
+            }
+
+            if let Err(e) = self.fill().await {
+                self.eof = true;
+                self.buf.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Append the next chunk of the file to the buffer
+    async fn fill(&mut self) -> VfsResult<()> {
+        let handle = match &self.handle {
+            Some(handle) => handle,
+            None => self
+                .handle
+                .insert(self.vfs.open::<ReadOnly, File>(&self.path, 0).await?),
+        };
+
+        let chunk = self.vfs.read(handle, self.offset, self.chunk).await?;
+        if chunk.is_empty() {
+            self.eof = true;
+            self.vfs.clunk(handle.fid).await?;
+        }
+        self.offset += chunk.len() as u64;
+        self.buf.extend_from_slice(&chunk);
+        Ok(())
+    }
+}
+
+fn decode(line: Vec<u8>) -> VfsResult<String> {
+    String::from_utf8(line).map_err(|_| VfsError::InvalidArgument("invalid utf-8".into()))
+}
+
+impl VfsMem {
+    /// Stream the lines of a file without loading it whole.
+    ///
+    /// Lines are split on `\n` (a trailing `\r` is dropped). A final line
+    /// without a newline is still yielded. Errors end the stream.
+    pub fn read_lines(&self, path: &str) -> impl Stream<Item = VfsResult<String>> + use<> {
+        self.read_lines_chunked(path, LINE_CHUNK)
+    }
+
+    pub(super) fn read_lines_chunked(
+        &self,
+        path: &str,
+        chunk: usize,
+    ) -> impl Stream<Item = VfsResult<String>> + use<> {
+        let reader = LineReader {
+            vfs: self.clone(),
+            path: path.to_string(),
+            handle: None,
+            offset: 0,
+            buf: Vec::new(),
+            eof: false,
+            chunk,
+        };
+
+        stream::unfold(reader, |mut reader| async move {
+            reader.next_line().await.map(|line| (line, reader))
+        })
+    }
+}
@@ -0,0 +1,81 @@
+//! Hard links and copy-on-write clones for the in-memory filesystem.
+
+use crate::error::{VfsError, VfsResult};
+
+use super::fs::{VfsMem, expect_file, touch_parent};
+use super::journal::Record;
+use super::node::Node;
+
+impl VfsMem {
+    /// Make `new_path` another name for the file at `existing`.
+    ///
+    /// Both paths share contents, mode, mtime, and version, so a write
+    /// through either is visible through the other. The contents are freed
+    /// once the last link is removed. Directories can't be linked.
+    pub async fn link(&self, existing: &str, new_path: &str) -> VfsResult<()> {
+        let existing = self.resolve_path(existing)?;
+        let new_path = self.resolve_path(new_path)?;
+
+        let mut nodes = self.write_nodes("link", &new_path)?;
+
+        let node = self.lookup(&nodes, &existing).ok_or_else(|| VfsError::NotFound(existing.clone()))?;
+        let link = Node::link(expect_file(&existing, node)?);
+
+        if self.lookup(&nodes, &new_path).is_some() {
+            return Err(VfsError::AlreadyExists(new_path));
+        }
+        self.ensure_parent_exists(&new_path, &nodes)?;
+        self.journal(|| Record::Link {
+            existing: existing.clone(),
+            path: new_path.clone(),
+        })?;
+
+        nodes.insert(new_path.clone(), link);
+        touch_parent(&mut nodes, &new_path, self.now());
+        self.touch(&new_path)?;
+        Ok(())
+    }
+
+    /// Create `to` as a copy-on-write clone of the file at `from`.
+    ///
+    /// Unlike a link, the clone is a separate file that diverges on the
+    /// first write to either side. With block dedup enabled the two files
+    /// share their blocks until then; flat contents are copied eagerly.
+    pub async fn clone_file(&self, from: &str, to: &str) -> VfsResult<()> {
+        let from = self.resolve_path(from)?;
+        let to = self.resolve_path(to)?;
+
+        let mut nodes = self.write_nodes("clone_file", &to)?;
+
+        let source = self.lookup(&nodes, &from).ok_or_else(|| VfsError::NotFound(from.clone()))?;
+        let mut inode = expect_file(&from, source)?.read()?.clone();
+
+        if self.lookup(&nodes, &to).is_some() {
+            return Err(VfsError::AlreadyExists(to));
+        }
+        self.ensure_parent_exists(&to, &nodes)?;
+        self.journal(|| Record::CloneFile {
+            from: from.clone(),
+            to: to.clone(),
+        })?;
+
+        inode.mtime = self.now();
+        inode.version = 0;
+        inode.generation = 0;
+        inode.owner = self.default_owner.clone();
+        nodes.insert(to.clone(), Node::from_inode(inode));
+        touch_parent(&mut nodes, &to, self.now());
+        self.touch(&to)?;
+        Ok(())
+    }
+
+    /// Number of paths linked to the file at `path` (1 for directories)
+    pub async fn link_count(&self, path: &str) -> VfsResult<usize> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("link_count", &path)?;
+
+        self.lookup(&nodes, &path)
+            .map(|node| node.nlink())
+            .ok_or(VfsError::NotFound(path))
+    }
+}
@@ -0,0 +1,125 @@
+//! Advisory (flock-style) path locks for the in-memory filesystem.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+use crate::error::{VfsError, VfsResult};
+
+/// Lock holders of a single path
+#[derive(Debug, Default)]
+struct Holders {
+    shared: usize,
+    exclusive: bool,
+}
+
+/// Per-path advisory lock state
+#[derive(Debug, Default)]
+pub(super) struct LockTable {
+    held: Mutex<HashMap<String, Holders>>,
+    released: Notify,
+}
+
+impl LockTable {
+    /// Take the lock if it doesn't conflict with current holders
+    fn try_acquire(&self, path: &str, exclusive: bool) -> VfsResult<bool> {
+        let mut held = self.held.lock()?;
+        let holders = held.entry(path.to_string()).or_default();
+
+        let conflict = holders.exclusive || (exclusive && holders.shared > 0);
+        if conflict {
+            return Ok(false);
+        }
+
+        if exclusive {
+            holders.exclusive = true;
+        } else {
+            holders.shared += 1;
+        }
+        Ok(true)
+    }
+
+    fn release(&self, path: &str, exclusive: bool) {
+        // Releasing must not panic in `Drop`, so tolerate a poisoned lock
+        let mut held = match self.held.lock() {
+            Ok(held) => held,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(holders) = held.get_mut(path) {
+            if exclusive {
+                holders.exclusive = false;
+            } else {
+                holders.shared = holders.shared.saturating_sub(1);
+            }
+            if !holders.exclusive && holders.shared == 0 {
+                held.remove(path);
+            }
+        }
+        drop(held);
+        self.released.notify_waiters();
+    }
+}
+
+/// Held advisory lock, released when dropped
+#[derive(Debug)]
+pub struct LockGuard {
+    table: Arc<LockTable>,
+    path: String,
+    exclusive: bool,
+}
+
+impl LockGuard {
+    /// Path this lock is held on
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether this is an exclusive lock
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.table.release(&self.path, self.exclusive);
+    }
+}
+
+/// Wait until the lock on `path` can be taken
+pub(super) async fn lock(
+    table: &Arc<LockTable>,
+    path: String,
+    exclusive: bool,
+) -> VfsResult<LockGuard> {
+    loop {
+        // Register for wakeups before checking, so a release in between isn't missed
+        let released = table.released.notified();
+        if table.try_acquire(&path, exclusive)? {
+            return Ok(LockGuard {
+                table: table.clone(),
+                path,
+                exclusive,
+            });
+        }
+        released.await;
+    }
+}
+
+/// Take the lock on `path` or fail immediately on conflict
+pub(super) fn try_lock(
+    table: &Arc<LockTable>,
+    path: String,
+    exclusive: bool,
+) -> VfsResult<LockGuard> {
+    if !table.try_acquire(&path, exclusive)? {
+        return Err(VfsError::PermissionDenied(format!("{} is locked", path)));
+    }
+    Ok(LockGuard {
+        table: table.clone(),
+        path,
+        exclusive,
+    })
+}
@@ -0,0 +1,109 @@
+//! Least-recently-used eviction for a bounded in-memory filesystem.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::fs::touch_parent;
+use super::index::NodeMap;
+use super::node::Node;
+
+/// Access tracking for LRU eviction
+#[derive(Debug, Clone)]
+pub(super) struct LruState {
+    budget: u64,
+    tick: u64,
+    last_access: HashMap<String, u64>,
+}
+
+impl LruState {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            tick: 0,
+            last_access: HashMap::new(),
+        }
+    }
+
+    /// Mark `path` as the most recently used
+    pub fn touch(&mut self, path: &str) {
+        self.tick += 1;
+        self.last_access.insert(path.to_string(), self.tick);
+    }
+
+    /// Stop tracking a removed path
+    pub fn forget(&mut self, path: &str) {
+        self.last_access.remove(path);
+    }
+
+    /// Forget all access history
+    pub fn clear(&mut self) {
+        self.tick = 0;
+        self.last_access.clear();
+    }
+
+    /// Carry recency over when `from` (and anything under it) moves to `to`
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<String> = self
+            .last_access
+            .keys()
+            .filter(|p| *p == from || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for old in moved {
+            if let Some(tick) = self.last_access.remove(&old) {
+                self.last_access.insert(format!("{}{}", to, &old[from.len()..]), tick);
+            }
+        }
+    }
+
+    /// Evict least-recently-used files until stored file bytes fit the budget.
+    ///
+    /// Hard links share their file's bytes, which count once and are freed
+    /// with the last link. Directories and `pinned` paths are never evicted.
+    /// Parent directories are stamped with `now`. Returns the evicted paths.
+    pub fn enforce(
+        &mut self,
+        nodes: &mut NodeMap,
+        pinned: &HashSet<String>,
+        now: SystemTime,
+    ) -> Vec<String> {
+        let mut inodes = HashSet::new();
+        let mut used: u64 = nodes
+            .values()
+            .filter(|node| match node {
+                Node::File { inode, .. } => inodes.insert(Arc::as_ptr(inode)),
+                Node::Dir { .. } => false,
+            })
+            .map(Node::allocated)
+            .sum();
+        if used <= self.budget {
+            return Vec::new();
+        }
+
+        // Files never touched count as oldest
+        let mut candidates: Vec<(u64, String)> = nodes
+            .iter()
+            .filter(|(path, node)| node.is_file() && !pinned.contains(*path))
+            .map(|(path, _)| (self.last_access.get(path).copied().unwrap_or(0), path.clone()))
+            .collect();
+        candidates.sort();
+
+        let mut evicted = Vec::new();
+        for (_, path) in candidates {
+            if used <= self.budget {
+                break;
+            }
+            if let Some(node) = nodes.remove(&path) {
+                if node.nlink() == 0 {
+                    used = used.saturating_sub(node.allocated());
+                }
+                touch_parent(nodes, &path, now);
+                self.last_access.remove(&path);
+                evicted.push(path);
+            }
+        }
+        evicted
+    }
+}
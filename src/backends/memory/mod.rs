@@ -1,9 +1,40 @@
 
+#[cfg(feature = "tar")]
+mod archive;
+mod builder;
+mod changes;
+mod data;
+mod delta;
+mod diff;
+mod events;
+mod fid;
 mod fs;
+mod index;
+mod journal;
+mod lines;
+mod link;
+mod lock;
+mod lru;
 mod node;
+mod owned;
+mod rename;
+mod statfs;
+mod tasks;
+mod transaction;
+mod tree;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "tar")]
+pub use archive::{ImportPolicy, ImportReport};
+pub use builder::VfsMemBuilder;
+pub use delta::{DeltaEntry, VfsDelta, VfsSnapshot};
+pub use diff::TreeChange;
+pub use events::VfsEvent;
+pub use fid::OpenHandleInfo;
 pub use fs::VfsMem;
-
+pub use lock::LockGuard;
+pub use owned::OpenFile;
+pub use statfs::StatFs;
+pub use transaction::TxContext;
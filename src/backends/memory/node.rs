@@ -1,34 +1,165 @@
 //! Internal node representation for the in-memory filesystem.
 
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
 use std::time::SystemTime;
 
-/// Internal filesystem node - either a file or directory
+use crate::error::VfsResult;
+use crate::types::NodeKind;
+
+use super::data::FileData;
+
+/// User and group owning a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Owner {
+    pub uid: String,
+    pub gid: String,
+}
+
+impl Default for Owner {
+    fn default() -> Self {
+        Self {
+            uid: "user".into(),
+            gid: "group".into(),
+        }
+    }
+}
+
+/// File contents and metadata, shared by every hard link to the file
 #[derive(Debug, Clone)]
+pub(super) struct Inode {
+    pub data: FileData,
+    pub mtime: SystemTime,
+    pub version: u32,
+    pub mode: u32,
+    pub owner: Owner,
+    /// Generation of the last change to the contents or metadata
+    pub changed: u64,
+    /// Generation the file was created in, set when first inserted
+    pub generation: u64,
+    /// Paths linking the file, kept by [`NodeMap`](super::index::NodeMap)
+    pub links: LinkCount,
+}
+
+/// Number of paths linking an inode.
+///
+/// A copy of an inode is a new file that no path links yet, so cloning
+/// the count gives zero rather than the original's links.
+#[derive(Debug, Default)]
+pub(super) struct LinkCount(usize);
+
+impl Clone for LinkCount {
+    fn clone(&self) -> Self {
+        LinkCount(0)
+    }
+}
+
+/// Handle to an inode; each path linking the file holds one, as do open
+/// handles on a removed file with unlinked writes on
+pub(super) type SharedInode = Arc<RwLock<Inode>>;
+
+/// Lock an inode for reading.
+///
+/// Inode updates can't be left half-done, so a poisoned lock is still usable.
+fn read_inode(inode: &SharedInode) -> RwLockReadGuard<'_, Inode> {
+    inode.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Internal filesystem node - either a file or directory
+#[derive(Debug)]
 pub(super) enum Node {
     File {
-        data: Vec<u8>,
-        mtime: SystemTime,
-        version: u32,
+        inode: SharedInode,
+        expires: Option<SystemTime>,
     },
     Dir {
         mtime: SystemTime,
+        mode: u32,
+        version: u32,
+        owner: Owner,
+        changed: u64,
+        generation: u64,
     },
 }
 
 impl Node {
-    /// Create a new empty file
-    pub fn new_file() -> Self {
-        Node::File {
-            data: Vec::new(),
-            mtime: SystemTime::now(),
+    /// Create a new empty file modified at `now`
+    pub fn new_file(now: SystemTime) -> Self {
+        Self::new_file_with(FileData::from(Vec::new()), now)
+    }
+
+    /// Create a new file with the given contents, modified at `now`
+    pub fn new_file_with(data: FileData, now: SystemTime) -> Self {
+        Self::from_inode(Inode {
+            data,
+            mtime: now,
             version: 0,
+            mode: 0o644,
+            owner: Owner::default(),
+            changed: 0,
+            generation: 0,
+            links: LinkCount::default(),
+        })
+    }
+
+    /// Create a file with its own, unshared inode
+    pub fn from_inode(inode: Inode) -> Self {
+        Node::File {
+            inode: Arc::new(RwLock::new(inode)),
+            expires: None,
+        }
+    }
+
+    /// Create another link to the file owning `inode`
+    pub fn link(inode: &SharedInode) -> Self {
+        Node::File {
+            inode: inode.clone(),
+            expires: None,
         }
     }
 
-    /// Create a new directory
-    pub fn new_dir() -> Self {
+    /// Number of paths linking this file's inode (1 for directories)
+    pub fn nlink(&self) -> usize {
+        match self {
+            Node::File { inode, .. } => read_inode(inode).links.0,
+            Node::Dir { .. } => 1,
+        }
+    }
+
+    /// Count one more path linking this file's inode
+    pub fn add_link(&self) {
+        if let Node::File { inode, .. } = self {
+            inode.write().unwrap_or_else(PoisonError::into_inner).links.0 += 1;
+        }
+    }
+
+    /// Count one path fewer linking this file's inode
+    pub fn drop_link(&self) {
+        if let Node::File { inode, .. } = self {
+            let links = &mut inode.write().unwrap_or_else(PoisonError::into_inner).links.0;
+            *links = links.saturating_sub(1);
+        }
+    }
+
+    /// Lock the inode of a file for reading
+    pub fn inode(&self) -> Option<RwLockReadGuard<'_, Inode>> {
+        match self {
+            Node::File { inode, .. } => Some(read_inode(inode)),
+            Node::Dir { .. } => None,
+        }
+    }
+
+    /// Create a new directory modified at `now`.
+    ///
+    /// Its version starts at 0 and only child changes bump it; opening it
+    /// never does.
+    pub fn new_dir(now: SystemTime) -> Self {
         Node::Dir {
-            mtime: SystemTime::now(),
+            mtime: now,
+            mode: 0o755,
+            version: 0,
+            owner: Owner::default(),
+            changed: 0,
+            generation: 0,
         }
     }
 
@@ -42,26 +173,170 @@ impl Node {
         matches!(self, Node::Dir { .. })
     }
 
+    /// Get the node kind
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            Node::File { .. } => NodeKind::File,
+            Node::Dir { .. } => NodeKind::Dir,
+        }
+    }
+
     /// Get the modification time
     pub fn mtime(&self) -> SystemTime {
         match self {
-            Node::File { mtime, .. } | Node::Dir { mtime } => *mtime,
+            Node::File { inode, .. } => read_inode(inode).mtime,
+            Node::Dir { mtime, .. } => *mtime,
+        }
+    }
+
+    /// Get the permission bits
+    pub fn mode(&self) -> u32 {
+        match self {
+            Node::File { inode, .. } => read_inode(inode).mode,
+            Node::Dir { mode, .. } => *mode,
+        }
+    }
+
+    /// Change the permission bits
+    pub fn set_mode(&mut self, new_mode: u32) -> VfsResult<()> {
+        match self {
+            Node::File { inode, .. } => inode.write()?.mode = new_mode,
+            Node::Dir { mode, .. } => *mode = new_mode,
+        }
+        Ok(())
+    }
+
+    /// Get the owning user and group
+    pub fn owner(&self) -> Owner {
+        match self {
+            Node::File { inode, .. } => read_inode(inode).owner.clone(),
+            Node::Dir { owner, .. } => owner.clone(),
+        }
+    }
+
+    /// Change the owning user and group
+    pub fn set_owner(&mut self, new_owner: Owner) -> VfsResult<()> {
+        match self {
+            Node::File { inode, .. } => inode.write()?.owner = new_owner,
+            Node::Dir { owner, .. } => *owner = new_owner,
+        }
+        Ok(())
+    }
+
+    /// Check if this is a file whose TTL has passed at `now`
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        match self {
+            Node::File {
+                expires: Some(expires),
+                ..
+            } => *expires <= now,
+            _ => false,
+        }
+    }
+
+    /// Take a file's contents, copying only if another link still shares them
+    pub fn into_data(self) -> Option<Vec<u8>> {
+        let Node::File { inode, .. } = self else {
+            return None;
+        };
+        match Arc::try_unwrap(inode) {
+            Ok(inode) => match inode.into_inner().unwrap_or_else(PoisonError::into_inner).data {
+                FileData::Flat { bytes, .. } => Some(Vec::from(bytes)),
+                data => Some(data.to_vec()),
+            },
+            Err(shared) => Some(read_inode(&shared).data.to_vec()),
         }
     }
 
     /// Get file size (0 for directories)
     pub fn size(&self) -> u64 {
+        self.inode().map_or(0, |i| i.data.len() as u64)
+    }
+
+    /// Get the bytes actually stored for a file (0 for directories)
+    pub fn allocated(&self) -> u64 {
+        self.inode().map_or(0, |i| i.data.allocated() as u64)
+    }
+
+    /// Get the version, bumped on every content or child change
+    pub fn version(&self) -> u32 {
         match self {
-            Node::File { data, .. } => data.len() as u64,
-            Node::Dir { .. } => 0,
+            Node::File { inode, .. } => read_inode(inode).version,
+            Node::Dir { version, .. } => *version,
         }
     }
 
-    /// Get file version (0 for directories)
-    pub fn version(&self) -> u32 {
+    /// Generation of the last change to this node
+    pub fn changed(&self) -> u64 {
+        match self {
+            Node::File { inode, .. } => read_inode(inode).changed,
+            Node::Dir { changed, .. } => *changed,
+        }
+    }
+
+    /// Record that this node changed in `generation`
+    pub fn mark_changed(&mut self, generation: u64) {
+        match self {
+            Node::File { inode, .. } => {
+                inode.write().unwrap_or_else(PoisonError::into_inner).changed = generation;
+            }
+            Node::Dir { changed, .. } => *changed = generation,
+        }
+    }
+
+    /// Generation this node was created in (0 until first inserted)
+    pub fn generation(&self) -> u64 {
+        match self {
+            Node::File { inode, .. } => read_inode(inode).generation,
+            Node::Dir { generation, .. } => *generation,
+        }
+    }
+
+    /// Record that this node was created in `generation`
+    pub fn set_generation(&mut self, new_generation: u64) {
+        match self {
+            Node::File { inode, .. } => {
+                inode.write().unwrap_or_else(PoisonError::into_inner).generation = new_generation;
+            }
+            Node::Dir { generation, .. } => *generation = new_generation,
+        }
+    }
+
+    /// Record that a directory's children changed at `now`
+    pub fn mark_children_changed(&mut self, now: SystemTime) {
+        if let Node::Dir { mtime, version, .. } = self {
+            *mtime = now;
+            *version = version.wrapping_add(1);
+        }
+    }
+}
+
+/// Cloning copies a file's inode, so the clone is independent of the
+/// original; use [`Node::link`] to share it.
+impl Clone for Node {
+    fn clone(&self) -> Self {
         match self {
-            Node::File { version, .. } => *version,
-            Node::Dir { .. } => 0,
+            Node::File { inode, expires } => {
+                Node::File {
+                    inode: Arc::new(RwLock::new(read_inode(inode).clone())),
+                    expires: *expires,
+                }
+            }
+            Node::Dir {
+                mtime,
+                mode,
+                version,
+                owner,
+                changed,
+                generation,
+            } => Node::Dir {
+                mtime: *mtime,
+                mode: *mode,
+                version: *version,
+                owner: owner.clone(),
+                changed: *changed,
+                generation: *generation,
+            },
         }
     }
 }
@@ -0,0 +1,97 @@
+//! Handles that clunk themselves when dropped.
+
+use std::ops::Deref;
+
+use crate::backend::VfsBackend;
+use crate::error::{VfsError, VfsResult};
+use crate::types::{Access, FileHandle, NodeType};
+
+use super::fs::{VfsMem, WeakVfsMem};
+
+/// A handle that releases its fid when dropped.
+///
+/// Derefs to the [`FileHandle`], so it can be passed anywhere a handle
+/// is borrowed. `Drop` can't be async, but clunking a `VfsMem` fid never
+/// waits on anything, so the fid is released on the spot rather than
+/// queued for later. Errors from that clunk, such as a failed `ORCLOSE`
+/// removal, are lost; call [`OpenFile::close`] to see them.
+///
+/// The handle doesn't keep its filesystem alive, so
+/// [`into_files`](VfsMem::into_files) works while it is open. Once every
+/// clone of the filesystem is dropped, `close` fails and dropping the
+/// handle does nothing.
+#[derive(Debug)]
+pub struct OpenFile<T = (), M = ()> {
+    vfs: WeakVfsMem,
+    /// Only `None` once ownership has been given up
+    handle: Option<FileHandle<T, M>>,
+}
+
+impl<T, M> OpenFile<T, M> {
+    /// Clunk the fid now, reporting any error
+    pub fn close(mut self) -> VfsResult<()> {
+        let handle = self.take();
+        let vfs = self.vfs.upgrade().ok_or_else(|| {
+            VfsError::InvalidArgument(format!("fid {}: its filesystem was dropped", handle.fid))
+        })?;
+        vfs.clunk_fid(handle.fid)
+    }
+
+    /// Give up ownership of the fid, leaving it for the caller to clunk
+    pub fn into_handle(mut self) -> FileHandle<T, M> {
+        self.take()
+    }
+
+    fn take(&mut self) -> FileHandle<T, M> {
+        self.handle.take().expect("handle is only taken by consuming self")
+    }
+}
+
+impl<T, M> Deref for OpenFile<T, M> {
+    type Target = FileHandle<T, M>;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.as_ref().expect("handle is only taken by consuming self")
+    }
+}
+
+impl<T, M> Drop for OpenFile<T, M> {
+    fn drop(&mut self) {
+        // Best effort: an unknown fid was already clunked by hand, and a
+        // dropped filesystem took its fids with it
+        if let Some(handle) = &self.handle
+            && let Some(vfs) = self.vfs.upgrade()
+        {
+            let _ = vfs.clunk_fid(handle.fid);
+        }
+    }
+}
+
+impl VfsMem {
+    /// Like `open`, but the handle clunks itself when dropped
+    pub async fn open_owned<M, T>(&self, path: &str, mode: u32) -> VfsResult<OpenFile<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        let handle = self.open(path, mode).await?;
+        Ok(self.owned(handle))
+    }
+
+    /// Like `create`, but the handle clunks itself when dropped
+    pub async fn create_owned<M, T>(&self, path: &str, mode: u32) -> VfsResult<OpenFile<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        let handle = self.create(path, mode).await?;
+        Ok(self.owned(handle))
+    }
+
+    fn owned<T, M>(&self, handle: FileHandle<T, M>) -> OpenFile<T, M> {
+        OpenFile {
+            vfs: self.downgrade(),
+            handle: Some(handle),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Rename support for the in-memory filesystem.
+
+use crate::error::{VfsError, VfsResult};
+
+use super::events::VfsEvent;
+use super::fs::{VfsMem, touch_parent};
+use super::index::NodeMap;
+use super::journal::Record;
+use super::node::Node;
+
+/// Move `from` and every path below it to `to`
+pub(super) fn move_subtree(nodes: &mut NodeMap, from: &str, to: &str) {
+    let prefix = format!("{}/", from);
+    let moved: Vec<String> = nodes
+        .keys()
+        .filter(|p| *p == from || p.starts_with(&prefix))
+        .cloned()
+        .collect();
+
+    // Take everything out first so no new key collides with a pending old one
+    let taken: Vec<(String, Node)> = moved
+        .into_iter()
+        .filter_map(|old| nodes.remove(&old).map(|node| (old, node)))
+        .collect();
+    for (old, node) in taken {
+        nodes.insert(format!("{}{}", to, &old[from.len()..]), node);
+    }
+}
+
+/// Parks one side of an exchange; no real path can contain a NUL byte
+const EXCHANGE_SLOT: &str = "\0exchange";
+
+impl VfsMem {
+    /// Rename `from` to `to`, failing if `to` already exists.
+    ///
+    /// Directories are moved together with their contents.
+    pub async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.rename_opts(from, to, false).await
+    }
+
+    /// Rename `from` to `to`, optionally replacing an existing target.
+    ///
+    /// With `overwrite`, an existing target of the same kind is replaced, like
+    /// `rename(2)`; a non-empty target directory is still refused. Replacing a
+    /// directory with a file fails with `IsADirectory`, and a file with a
+    /// directory with `NotADirectory`. Moving a directory onto itself or into
+    /// one of its descendants is an `InvalidArgument` error. The whole move
+    /// happens under a single write lock, so readers never observe a
+    /// half-renamed tree.
+    ///
+    /// Open handles follow the node, as on Unix: reads and writes through a
+    /// handle opened before the rename reach the file at its new path.
+    /// Handles open on a replaced target fail with `NotFound` from then on,
+    /// unless [`with_unlinked_writes`](Self::with_unlinked_writes) keeps
+    /// the replaced file usable through them.
+    pub async fn rename_opts(&self, from: &str, to: &str, overwrite: bool) -> VfsResult<()> {
+        let from = self.resolve_path(from)?;
+        let to = self.resolve_path(to)?;
+
+        if from == "/" {
+            return Err(VfsError::PermissionDenied("cannot rename root".into()));
+        }
+
+        let mut nodes = self.write_nodes("rename", &from)?;
+
+        let source_is_dir = self.lookup(&nodes, &from)
+            .ok_or_else(|| VfsError::NotFound(from.clone()))?
+            .is_dir();
+        if source_is_dir && (to == from || to.starts_with(&format!("{}/", from))) {
+            return Err(VfsError::InvalidArgument("cannot move a directory into itself".into()));
+        }
+        if from == to {
+            return Ok(());
+        }
+
+        self.ensure_parent_exists(&to, &nodes)?;
+
+        if let Some(target) = self.lookup(&nodes, &to) {
+            if !overwrite {
+                return Err(VfsError::AlreadyExists(to));
+            }
+            match (source_is_dir, target.is_dir()) {
+                (false, true) => return Err(VfsError::IsADirectory(to)),
+                (true, false) => return Err(VfsError::NotADirectory(to)),
+                (true, true) if !self.get_dir_children(&to, &nodes).is_empty() => {
+                    return Err(VfsError::DirectoryNotEmpty(to));
+                }
+                _ => {}
+            }
+        }
+        self.journal(|| Record::Rename {
+            from: from.clone(),
+            to: to.clone(),
+            overwrite,
+        })?;
+
+        // Handles on the replaced target must not reach the node moving in;
+        // with unlinked writes they keep the replaced file instead
+        {
+            let mut fids = self.fids.lock()?;
+            if self.unlinked_writes
+                && let Some(Node::File { inode, .. }) = nodes.get(&to)
+            {
+                fids.orphan(&to, inode);
+            }
+            fids.detach(&to);
+        }
+        // Also drops an expired file lingering at the target
+        nodes.remove(&to);
+
+        move_subtree(&mut nodes, &from, &to);
+        touch_parent(&mut nodes, &from, self.now());
+        touch_parent(&mut nodes, &to, self.now());
+        if let Some(lru) = &self.lru {
+            let mut lru = lru.lock()?;
+            lru.forget(&to);
+            lru.rename(&from, &to);
+        }
+        self.fids.lock()?.rename(&from, &to);
+        self.emit(VfsEvent::Renamed { from, to });
+
+        Ok(())
+    }
+
+    /// Swap the nodes at `a` and `b`, like `renameat2` with
+    /// `RENAME_EXCHANGE`.
+    ///
+    /// Both paths must exist. Directories are swapped with their whole
+    /// subtrees, and a file may be swapped with a directory. Swapping a path
+    /// with one of its own ancestors or descendants is an `InvalidArgument`
+    /// error. The swap happens under a single write lock, so readers see
+    /// either both old paths or both new ones. Open handles follow their
+    /// nodes, as with [`rename`](Self::rename), and subscribers get a single
+    /// [`VfsEvent::Exchanged`].
+    pub async fn exchange(&self, a: &str, b: &str) -> VfsResult<()> {
+        let a = self.resolve_path(a)?;
+        let b = self.resolve_path(b)?;
+
+        if a == "/" || b == "/" {
+            return Err(VfsError::PermissionDenied("cannot exchange root".into()));
+        }
+
+        let mut nodes = self.write_nodes("exchange", &a)?;
+
+        for path in [&a, &b] {
+            if self.lookup(&nodes, path).is_none() {
+                return Err(VfsError::NotFound(path.clone()));
+            }
+        }
+        if a == b {
+            return Ok(());
+        }
+        if a.starts_with(&format!("{}/", b)) || b.starts_with(&format!("{}/", a)) {
+            return Err(VfsError::InvalidArgument(
+                "cannot exchange a path with its own ancestor".into(),
+            ));
+        }
+        self.journal(|| Record::Exchange {
+            a: a.clone(),
+            b: b.clone(),
+        })?;
+
+        let moves = [(&*a, EXCHANGE_SLOT), (&*b, &*a), (EXCHANGE_SLOT, &*b)];
+        for (from, to) in moves {
+            move_subtree(&mut nodes, from, to);
+        }
+        touch_parent(&mut nodes, &a, self.now());
+        touch_parent(&mut nodes, &b, self.now());
+        if let Some(lru) = &self.lru {
+            let mut lru = lru.lock()?;
+            for (from, to) in moves {
+                lru.rename(from, to);
+            }
+        }
+        {
+            let mut fids = self.fids.lock()?;
+            for (from, to) in moves {
+                fids.rename(from, to);
+            }
+        }
+        self.emit(VfsEvent::Exchanged { a, b });
+
+        Ok(())
+    }
+
+    /// Move `src` into the directory `dest_dir`, keeping its name.
+    ///
+    /// Returns the new path. Fails with `NotADirectory` if `dest_dir` is not
+    /// a directory and `AlreadyExists` if the target name is taken.
+    pub async fn move_into(&self, src: &str, dest_dir: &str) -> VfsResult<String> {
+        let src = self.resolve_path(src)?;
+        let dest_dir = self.resolve_path(dest_dir)?;
+
+        if src == "/" {
+            return Err(VfsError::PermissionDenied("cannot rename root".into()));
+        }
+
+        {
+            let nodes = self.read_nodes("move_into", &src)?;
+            let dest = self.lookup(&nodes, &dest_dir)
+                .ok_or_else(|| VfsError::NotFound(dest_dir.clone()))?;
+            if !dest.is_dir() {
+                return Err(VfsError::NotADirectory(dest_dir));
+            }
+        }
+
+        let name = &src[src.rfind('/').map_or(0, |i| i + 1)..];
+        let target = if dest_dir == "/" {
+            format!("/{}", name)
+        } else {
+            format!("{}/{}", dest_dir, name)
+        };
+
+        self.rename(&src, &target).await?;
+        Ok(target)
+    }
+}
@@ -0,0 +1,103 @@
+//! Filesystem-wide usage statistics.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::error::{VfsError, VfsResult};
+
+use super::data::FileData;
+use super::fs::{VfsMem, expect_file};
+use super::node::Node;
+
+/// Usage summary of a whole filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatFs {
+    pub files: usize,
+    pub dirs: usize,
+    /// Sum of file sizes as reported by `stat`
+    pub logical_bytes: u64,
+    /// Bytes actually stored, excluding holes in sparse files
+    pub allocated_bytes: u64,
+}
+
+impl VfsMem {
+    /// Summarize node counts and space usage
+    pub async fn statfs(&self) -> VfsResult<StatFs> {
+        let nodes = self.read_nodes("statfs", "/")?;
+        let now = self.now();
+
+        let mut stats = StatFs {
+            files: 0,
+            dirs: 0,
+            logical_bytes: 0,
+            allocated_bytes: 0,
+        };
+        for node in nodes.values().filter(|node| !node.is_expired(now)) {
+            if node.is_dir() {
+                stats.dirs += 1;
+            } else {
+                stats.files += 1;
+                stats.logical_bytes += node.size();
+                stats.allocated_bytes += node.allocated();
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Heap bytes held by file contents, spare buffer capacity included.
+    ///
+    /// Unlike [`StatFs::allocated_bytes`], which counts the bytes stored,
+    /// this counts what the buffers reserved; the gap to
+    /// [`logical_bytes`](Self::logical_bytes) is over-allocation from
+    /// growth and `reserve`. Each file counts once however many links it
+    /// has, and expired files not yet reaped still count.
+    pub fn allocated_bytes(&self) -> VfsResult<u64> {
+        self.sum_files("allocated_bytes", FileData::capacity)
+    }
+
+    /// Sum of file lengths, counted the same way as
+    /// [`allocated_bytes`](Self::allocated_bytes)
+    pub fn logical_bytes(&self) -> VfsResult<u64> {
+        self.sum_files("logical_bytes", FileData::len)
+    }
+
+    /// Release the spare capacity of every file's buffer, returning the
+    /// bytes freed.
+    ///
+    /// Runs under the write lock, so it is safe alongside any other
+    /// operation but blocks them while it runs.
+    pub fn compact(&self) -> VfsResult<u64> {
+        let nodes = self.write_nodes("compact", "/")?;
+        let mut freed = 0;
+        for node in nodes.values() {
+            if let Node::File { inode, .. } = node {
+                freed += inode.write()?.data.shrink_to_fit() as u64;
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Add up `size` over the contents of every distinct inode
+    fn sum_files(&self, op: &'static str, size: impl Fn(&FileData) -> usize) -> VfsResult<u64> {
+        let nodes = self.read_nodes(op, "/")?;
+        let mut seen = HashSet::new();
+        let mut total = 0;
+        for node in nodes.values() {
+            if let Node::File { inode, .. } = node
+                && seen.insert(Arc::as_ptr(inode))
+            {
+                total += size(&inode.read()?.data) as u64;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Whether the file at `path` has unallocated holes
+    pub async fn is_sparse(&self, path: &str) -> VfsResult<bool> {
+        let path = self.resolve_path(path)?;
+        let nodes = self.read_nodes("is_sparse", &path)?;
+
+        let node = self.lookup(&nodes, &path).ok_or_else(|| VfsError::NotFound(path.clone()))?;
+        Ok(expect_file(&path, node)?.read()?.data.is_sparse())
+    }
+}
@@ -0,0 +1,67 @@
+//! Background tasks spawned on behalf of the in-memory filesystem.
+
+use std::sync::{Mutex, PoisonError};
+
+use tokio::sync::watch;
+
+use crate::runtime::Task;
+
+use super::fs::VfsMem;
+
+/// Running background tasks and the signal that stops them.
+///
+/// Dropping this drops the sender, which stops every task as well, so
+/// tasks end as soon as the last clone of their filesystem goes away.
+#[derive(Debug)]
+pub(super) struct Tasks {
+    stop: watch::Sender<bool>,
+    pub running: Mutex<Vec<Task>>,
+}
+
+impl Default for Tasks {
+    fn default() -> Self {
+        Self {
+            stop: watch::channel(false).0,
+            running: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Tasks {
+    /// A receiver that changes, or closes, when tasks should stop
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    pub fn stop_signal(&self) -> watch::Receiver<bool> {
+        self.stop.subscribe()
+    }
+
+    /// Remember `task` so `shutdown` can wait for it
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    pub fn track(&self, task: Task) {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        running.retain(|task| !task.is_finished());
+        running.push(task);
+    }
+
+    /// Signal every task to stop, then wait until they have
+    async fn shutdown(&self) {
+        self.stop.send_replace(true);
+        let running = {
+            let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::take(&mut *running)
+        };
+        for task in running {
+            task.join().await;
+        }
+    }
+}
+
+impl VfsMem {
+    /// Stop background tasks, such as the expiry reaper, and wait for them
+    /// to finish.
+    ///
+    /// This stops them for every clone of this filesystem. Dropping every
+    /// clone also stops them, without waiting.
+    pub async fn shutdown(&self) {
+        self.tasks.shutdown().await;
+    }
+}
@@ -1,163 +1,3751 @@
 //! Tests for the in-memory VFS backend.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::types::{File, Dir, ReadWrite, WriteOnly, ReadOnly};
-    use crate::backends::VfsMem;
-    use crate::backend::VfsBackend;
-    use crate::VfsError;
-   
-    #[tokio::test]
-    async fn test_normalize_path() {
-        assert_eq!(VfsMem::normalize_path("/").unwrap(), "/");
-        assert_eq!(VfsMem::normalize_path("/foo").unwrap(), "/foo");
-        assert_eq!(VfsMem::normalize_path("foo").unwrap(), "/foo");
-        assert_eq!(VfsMem::normalize_path("/foo/bar/").unwrap(), "/foo/bar");
-
-        assert!(VfsMem::normalize_path("..").is_err());
-        assert!(VfsMem::normalize_path("/foo/../bar").is_err());
-        assert!(VfsMem::normalize_path("").is_err());
-    }
-
-    #[tokio::test]
-    async fn test_create_and_stat() {
-        let vfs = VfsMem::new();
+use crate::types::{ChangeToken, File, FileHandle, Dir, NodeKind, OpenResult, PutOutcome, Qid, ReadWrite, WriteOnly, ReadOnly};
+use crate::mode::{AccessMode, ORCLOSE, ORDWR, OTRUNC, OWRITE};
+use crate::backends::VfsMem;
+use crate::backends::memory::{DeltaEntry, TreeChange, VfsDelta, VfsEvent};
+use super::fid::FidTable;
+use super::node::Node;
+use crate::backend::{VfsBackend, VfsBackendExt};
+use crate::{VfsError, VfsResult};
+use crate::clock::ManualClock;
+use crate::wire;
 
-        // Create a file
-        let _handle = vfs
-            .create::<WriteOnly, File>("/test.txt", 0o644)
-            .await
-            .unwrap();
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-        // Stat it
-        let stat = vfs.stat("/test.txt").await.unwrap();
-        assert_eq!(stat.name, "test.txt");
-        assert_eq!(stat.size, 0);
+use futures::StreamExt;
+
+#[tokio::test]
+async fn test_normalize_path() {
+    assert_eq!(VfsMem::normalize_path("/").unwrap(), "/");
+    assert_eq!(VfsMem::normalize_path("/foo").unwrap(), "/foo");
+    assert_eq!(VfsMem::normalize_path("foo").unwrap(), "/foo");
+    assert_eq!(VfsMem::normalize_path("/foo/bar/").unwrap(), "/foo/bar");
+
+    assert!(VfsMem::normalize_path("..").is_err());
+    assert!(VfsMem::normalize_path("/foo/../bar").is_err());
+    assert!(VfsMem::normalize_path("").is_err());
+
+    let vfs = VfsMem::new();
+    for root in ["///", "/.", "/./"] {
+        assert!(vfs.stat(root).await.unwrap().is_dir());
     }
+}
 
-    #[tokio::test]
-    async fn test_write_and_read() {
-        let vfs = VfsMem::new();
+#[tokio::test]
+async fn test_builder_root_mode_and_owner() {
+    let vfs = VfsMem::builder()
+        .root_mode(0o700)
+        .root_owner("root", "wheel")
+        .build()
+        .with_clock(Arc::new(ManualClock::default()));
 
-        let handle = vfs
-            .create::<ReadWrite, File>("/test.txt", 0o644)
-            .await
-            .unwrap();
+    let root = vfs.stat("/").await.unwrap();
+    assert_eq!(root.mode, 0o700);
+    assert_eq!((root.uid.as_str(), root.gid.as_str()), ("root", "wheel"));
+    assert_eq!(root.mtime, SystemTime::UNIX_EPOCH);
+
+    vfs.create_dir_all("/a").await.unwrap();
+    vfs.clear().unwrap();
+    let root = vfs.stat("/").await.unwrap();
+    assert_eq!(root.mode, 0o700);
+    assert_eq!(root.uid, "root");
+
+    assert_eq!(VfsMem::builder().build().stat("/").await.unwrap().mode, 0o755);
+}
+
+#[tokio::test]
+async fn test_clear() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/f", b"data").await;
+    let stale = vfs.open::<ReadOnly, File>("/a/b/f", 0).await.unwrap();
+
+    vfs.clear().unwrap();
+
+    assert_eq!(vfs.find(|_, _| true).unwrap(), ["/"]);
+    assert!(vfs.open_handles().unwrap().is_empty());
+    assert!(matches!(vfs.clunk(stale.fid).await, Err(VfsError::InvalidArgument(_))));
+
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    assert!(vfs.readdir(&root).await.unwrap().is_empty());
+    assert_eq!(root.fid, 1);
+
+    write_file(&vfs, "/a", b"again").await;
+    assert_eq!(read_file(&vfs, "/a").await, b"again");
+    assert_eq!(vfs.stat("/").await.unwrap().nlink, 1);
+}
+
+#[tokio::test]
+async fn test_create_and_stat() {
+    let vfs = VfsMem::new();
+
+    // Create a file
+    let _handle = vfs
+        .create::<WriteOnly, File>("/test.txt", 0o644)
+        .await
+        .unwrap();
+
+    // Stat it
+    let stat = vfs.stat("/test.txt").await.unwrap();
+    assert_eq!(stat.name, "test.txt");
+    assert_eq!(stat.size, 0);
+}
+
+#[tokio::test]
+async fn test_write_and_read() {
+    let vfs = VfsMem::new();
+
+    let handle = vfs
+        .create::<ReadWrite, File>("/test.txt", 0o644)
+        .await
+        .unwrap();
+
+    // Write data
+    let written = vfs.write(&handle, 0, b"Hello, World!").await.unwrap();
+    assert_eq!(written, 13);
+
+    // Read it back
+    let data = vfs.read(&handle, 0, 100).await.unwrap();
+    assert_eq!(data, b"Hello, World!");
+}
+
+#[tokio::test]
+async fn test_partial_write() {
+    let vfs = VfsMem::new();
+    let handle = vfs
+        .create::<ReadWrite, File>("/test.txt", 0o644)
+        .await
+        .unwrap();
+
+    // Write at different offsets
+    vfs.write(&handle, 0, b"Hello").await.unwrap();
+    vfs.write(&handle, 7, b"World").await.unwrap();
+
+    let data = vfs.read(&handle, 0, 100).await.unwrap();
+    // Note: gap at offset 5-6 will be filled with zeros
+    assert_eq!(data.len(), 12);
+}
+
+#[tokio::test]
+async fn test_directory_operations() {
+    let vfs = VfsMem::new();
 
-        // Write data
-        let written = vfs.write(&handle, 0, b"Hello, World!").await.unwrap();
-        assert_eq!(written, 13);
+    // Create directories
+    vfs.create::<ReadOnly, Dir>("/dir1", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/dir1/dir2", 0o755)
+        .await
+        .unwrap();
 
-        // Read it back
-        let data = vfs.read(&handle, 0, 100).await.unwrap();
-        assert_eq!(data, b"Hello, World!");
+    // Create file in nested dir
+    vfs.create::<WriteOnly, File>("/dir1/dir2/file.txt", 0o644)
+        .await
+        .unwrap();
+
+    // List root
+    let root_handle = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    let entries = vfs.readdir(&root_handle).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "dir1");
+
+    // List nested dir
+    let dir2_handle = vfs.open::<ReadOnly, Dir>("/dir1/dir2", 0).await.unwrap();
+    let entries = vfs.readdir(&dir2_handle).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "file.txt");
+}
+
+#[tokio::test]
+async fn test_walk() {
+    let vfs = VfsMem::new();
+
+    vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/b", 0o755).await.unwrap();
+    vfs.create::<WriteOnly, File>("/a/b/c.txt", 0o644)
+        .await
+        .unwrap();
+
+    let result = vfs
+        .walk("/", &["a".into(), "b".into(), "c.txt".into()])
+        .await
+        .unwrap();
+    assert_eq!(result.qids.len(), 3);
+}
+
+#[tokio::test]
+async fn test_walk_dot_stays_put() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+
+    let plain = vfs.walk("/", &["a".into(), "b".into()]).await.unwrap();
+    let dotted = vfs.walk("/", &["a".into(), ".".into(), "b".into()]).await.unwrap();
+
+    // The "." repeats the qid of "a", so the count still matches the names
+    assert_eq!(dotted.qids.len(), 3);
+    assert_eq!(dotted.qids[1], dotted.qids[0]);
+    assert_eq!(dotted.qids[2], plain.qids[1]);
+    assert_eq!(vfs.walk("/", &[".".into()]).await.unwrap().qids.len(), 1);
+    assert!(vfs.walk("/", &["a".into(), "..".into()]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_walk_from_file() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file.txt", b"data").await;
+
+    let result = vfs.walk("/file.txt", &["child".into()]).await;
+    assert!(matches!(result, Err(VfsError::NotADirectory(p)) if p == "/file.txt"));
+    assert!(vfs.walk("/file.txt", &[]).await.unwrap().qids.is_empty());
+}
+
+#[tokio::test]
+async fn test_remove() {
+    let vfs = VfsMem::new();
+
+    vfs.create::<WriteOnly, File>("/test.txt", 0o644)
+        .await
+        .unwrap();
+    assert!(vfs.stat("/test.txt").await.is_ok());
+
+    vfs.remove::<File>("/test.txt").await.unwrap();
+    assert!(vfs.stat("/test.txt").await.is_err());
+}
+
+#[tokio::test]
+async fn test_create_under_file_names_the_file() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"file").await;
+
+    for path in ["/a/b", "/a/b/c", "/a/b/c/d"] {
+        assert!(matches!(
+            vfs.create::<WriteOnly, File>(path, 0o644).await,
+            Err(VfsError::NotADirectory(p)) if p == "/a"
+        ));
+        assert!(matches!(
+            vfs.create::<ReadOnly, Dir>(path, 0o755).await,
+            Err(VfsError::NotADirectory(p)) if p == "/a"
+        ));
+        assert!(matches!(
+            vfs.create_dir_all(path).await,
+            Err(VfsError::NotADirectory(p)) if p == "/a"
+        ));
     }
 
-    #[tokio::test]
-    async fn test_partial_write() {
-        let vfs = VfsMem::new();
-        let handle = vfs
-            .create::<ReadWrite, File>("/test.txt", 0o644)
-            .await
-            .unwrap();
+    vfs.create_dir_all("/d/e").await.unwrap();
+    write_file(&vfs, "/d/e/f", b"x").await;
+    assert!(matches!(
+        vfs.create_dir_all("/d/e/f/g/h").await,
+        Err(VfsError::NotADirectory(p)) if p == "/d/e/f"
+    ));
+}
 
-        // Write at different offsets
-        vfs.write(&handle, 0, b"Hello").await.unwrap();
-        vfs.write(&handle, 7, b"World").await.unwrap();
+#[tokio::test]
+async fn test_create_rejects_normalized_collisions() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/foo", b"file").await;
 
-        let data = vfs.read(&handle, 0, 100).await.unwrap();
-        // Note: gap at offset 5-6 will be filled with zeros
-        assert_eq!(data.len(), 12);
+    for spelling in ["/foo", "foo", "/foo/", "//foo//"] {
+        assert!(matches!(
+            vfs.create::<ReadOnly, Dir>(spelling, 0o755).await,
+            Err(VfsError::AlreadyExists(p)) if p == "/foo"
+        ));
+        assert!(matches!(
+            vfs.create::<WriteOnly, File>(spelling, 0o644).await,
+            Err(VfsError::AlreadyExists(p)) if p == "/foo"
+        ));
     }
 
-    #[tokio::test]
-    async fn test_directory_operations() {
-        let vfs = VfsMem::new();
+    // Names are case-sensitive, so this is a different entry
+    vfs.create::<ReadOnly, Dir>("/Foo", 0o755).await.unwrap();
 
-        // Create directories
-        vfs.create::<ReadOnly, Dir>("/dir1", 0o755).await.unwrap();
-        vfs.create::<ReadOnly, Dir>("/dir1/dir2", 0o755)
-            .await
-            .unwrap();
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    let mut names: Vec<String> = vfs.readdir(&root).await.unwrap().into_iter().map(|s| s.name).collect();
+    names.sort();
+    assert_eq!(names, ["Foo", "foo"]);
+}
 
-        // Create file in nested dir
-        vfs.create::<WriteOnly, File>("/dir1/dir2/file.txt", 0o644)
-            .await
-            .unwrap();
+#[tokio::test]
+async fn test_create_with_missing_parent() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a").await.unwrap();
+
+    assert!(matches!(
+        vfs.create::<WriteOnly, File>("/a/b/c", 0o644).await,
+        Err(VfsError::NotFound(p)) if p == "parent directory: /a/b"
+    ));
+    assert!(matches!(
+        vfs.create::<ReadOnly, Dir>("/x/y/z", 0o755).await,
+        Err(VfsError::NotFound(p)) if p == "parent directory: /x/y"
+    ));
+
+    // create_dir_all fills in the missing parents instead
+    vfs.create_dir_all("/a/b/c").await.unwrap();
+    assert_eq!(vfs.kind("/a/b/c").await.unwrap(), NodeKind::Dir);
+}
+
+#[tokio::test]
+async fn test_stat_nlink() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir/sub").await.unwrap();
+    write_file(&vfs, "/dir/a", b"a").await;
+    write_file(&vfs, "/dir/b", b"b").await;
+
+    assert_eq!(vfs.stat("/dir").await.unwrap().nlink, 3);
+    assert_eq!(vfs.stat("/dir/sub").await.unwrap().nlink, 0);
+    assert_eq!(vfs.stat("/dir/a").await.unwrap().nlink, 1);
 
-        // List root
-        let root_handle = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
-        let entries = vfs.readdir(&root_handle).await.unwrap();
-        assert_eq!(entries.len(), 1);
-        assert_eq!(entries[0].name, "dir1");
+    vfs.link("/dir/a", "/dir/sub/a").await.unwrap();
+    assert_eq!(vfs.stat("/dir/a").await.unwrap().nlink, 2);
+    assert_eq!(vfs.stat("/dir/sub").await.unwrap().nlink, 1);
 
-        // List nested dir
-        let dir2_handle = vfs.open::<ReadOnly, Dir>("/dir1/dir2", 0).await.unwrap();
-        let entries = vfs.readdir(&dir2_handle).await.unwrap();
-        assert_eq!(entries.len(), 1);
-        assert_eq!(entries[0].name, "file.txt");
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    let listed = vfs.readdir(&root).await.unwrap();
+    assert_eq!(listed[0].nlink, 3);
+}
+
+#[tokio::test]
+async fn test_find() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/logs/old").await.unwrap();
+    write_file(&vfs, "/logs/small.log", b"tiny").await;
+    write_file(&vfs, "/logs/big.log", &[0u8; 2048]).await;
+    write_file(&vfs, "/logs/old/huge.log", &[0u8; 4096]).await;
+    write_file(&vfs, "/big.bin", &[0u8; 1025]).await;
+
+    let large = vfs.find(|_, stat| stat.size > 1024).unwrap();
+    assert_eq!(large, ["/big.bin", "/logs/big.log", "/logs/old/huge.log"]);
+
+    let dirs = vfs.find(|path, stat| stat.is_dir() && path != "/").unwrap();
+    assert_eq!(dirs, ["/logs", "/logs/old"]);
+}
+
+#[tokio::test]
+async fn test_readdir_uses_child_index() {
+    use std::sync::atomic::Ordering;
+
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/big").await.unwrap();
+    vfs.create_dir_all("/small").await.unwrap();
+    for i in 0..10_000 {
+        let h = vfs.create::<WriteOnly, File>(&format!("/big/{}", i), 0o644).await.unwrap();
+        vfs.clunk(h.fid).await.unwrap();
+    }
+    for i in 0..10 {
+        write_file(&vfs, &format!("/small/{}", i), b"x").await;
     }
 
-    #[tokio::test]
-    async fn test_walk() {
-        let vfs = VfsMem::new();
+    vfs.nodes.read().unwrap().scans.store(0, Ordering::Relaxed);
+    let dir = vfs.open::<ReadOnly, Dir>("/small", 0).await.unwrap();
+    let entries = vfs.readdir(&dir).await.unwrap();
+    assert_eq!(entries.len(), 10);
+    assert_eq!(vfs.nodes.read().unwrap().scans.load(Ordering::Relaxed), 0);
 
-        vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
-        vfs.create::<ReadOnly, Dir>("/a/b", 0o755).await.unwrap();
-        vfs.create::<WriteOnly, File>("/a/b/c.txt", 0o644)
-            .await
-            .unwrap();
+    // The index follows removes and renames
+    vfs.remove::<File>("/small/0").await.unwrap();
+    vfs.rename("/small/1", "/big/moved").await.unwrap();
+    assert_eq!(vfs.readdir(&dir).await.unwrap().len(), 8);
+    let big = vfs.open::<ReadOnly, Dir>("/big", 0).await.unwrap();
+    assert_eq!(vfs.readdir(&big).await.unwrap().len(), 10_001);
+}
 
-        let result = vfs
-            .walk("/", &["a".into(), "b".into(), "c.txt".into()])
-            .await
-            .unwrap();
-        assert_eq!(result.qids.len(), 3);
+#[tokio::test]
+async fn test_remove_stat() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/report.csv", b"a,b,c\n").await;
+
+    let before = vfs.stat("/report.csv").await.unwrap();
+    let removed = vfs.remove_stat::<File>("/report.csv").await.unwrap();
+    assert_eq!(removed, before);
+    assert_eq!(removed.name, "report.csv");
+    assert_eq!(removed.size, 6);
+    assert!(!vfs.exists("/report.csv").await.unwrap());
+
+    assert!(matches!(vfs.remove_stat::<File>("/report.csv").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_remove_many() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/one", b"1").await;
+    write_file(&vfs, "/two", b"2").await;
+    vfs.create_dir_all("/full").await.unwrap();
+    write_file(&vfs, "/full/child", b"c").await;
+
+    let paths: Vec<String> = ["/one", "/missing", "/full", "/two"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let results = vfs.remove_many(&paths).await.unwrap();
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].0, "/one");
+    assert!(results[0].1.is_ok());
+    assert!(matches!(results[1].1, Err(VfsError::NotFound(_))));
+    assert!(matches!(results[2].1, Err(VfsError::DirectoryNotEmpty(_))));
+    assert!(results[3].1.is_ok());
+
+    assert!(!vfs.exists("/one").await.unwrap());
+    assert!(!vfs.exists("/two").await.unwrap());
+    assert!(vfs.exists("/full/child").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_wrong_kind_errors_name_the_path() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/file", b"data").await;
+
+    // Handles whose marker disagrees with what is at the path
+    let dir_as_file: FileHandle<File, ReadWrite> =
+        FileHandle::new(0, Qid::new_file(0, 0), "/dir".into(), ORDWR);
+    let file_as_dir: FileHandle<Dir, ReadOnly> =
+        FileHandle::new(0, Qid::new_dir(0, 0), "/file".into(), 0);
+
+    let is_dir = |r: VfsResult<()>| matches!(r, Err(VfsError::IsADirectory(p)) if p == "/dir");
+    let not_dir = |r: VfsResult<()>| matches!(r, Err(VfsError::NotADirectory(p)) if p == "/file");
+
+    assert!(is_dir(vfs.read(&dir_as_file, 0, 1).await.map(|_| ())));
+    assert!(is_dir(vfs.write(&dir_as_file, 0, b"x").await.map(|_| ())));
+    assert!(is_dir(vfs.reserve(&dir_as_file, 16).await));
+    assert!(is_dir(vfs.open::<ReadWrite, File>("/dir", ORDWR | OTRUNC).await.map(|_| ())));
+    assert!(is_dir(vfs.is_sparse("/dir").await.map(|_| ())));
+    assert!(is_dir(vfs.link("/dir", "/link").await));
+    assert!(is_dir(vfs.clone_file("/dir", "/clone").await));
+
+    assert!(not_dir(vfs.readdir(&file_as_dir).await.map(|_| ())));
+    assert!(not_dir(vfs.open::<ReadOnly, Dir>("/file", 0).await.map(|_| ())));
+    assert!(not_dir(vfs.readdir_recursive("/file").await.map(|_| ())));
+    assert!(not_dir(vfs.walk_tree("/file", |_, _| async { Ok(()) }).await));
+}
+
+#[tokio::test]
+async fn test_append_to() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/logs").await.unwrap();
+
+    assert_eq!(vfs.append_to("/logs/app.log", b"first\n").await.unwrap(), 6);
+    assert_eq!(vfs.append_to("/logs/app.log", b"second\n").await.unwrap(), 7);
+    assert_eq!(read_file(&vfs, "/logs/app.log").await, b"first\nsecond\n");
+
+    assert!(matches!(
+        vfs.append_to("/logs", b"x").await,
+        Err(VfsError::IsADirectory(p)) if p == "/logs"
+    ));
+    assert!(matches!(
+        vfs.append_to("/missing/app.log", b"x").await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_appends_do_not_overlap() {
+    let vfs = VfsMem::new();
+    let tasks: Vec<_> = (0..8u8)
+        .map(|i| {
+            let vfs = vfs.clone();
+            tokio::spawn(async move {
+                for _ in 0..50 {
+                    vfs.append_to("/log", &[i; 4]).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
     }
 
-    #[tokio::test]
-    async fn test_remove() {
-        let vfs = VfsMem::new();
+    let data = read_file(&vfs, "/log").await;
+    assert_eq!(data.len(), 8 * 50 * 4);
+    assert!(data.chunks(4).all(|c| c.iter().all(|b| *b == c[0])));
+}
 
-        vfs.create::<WriteOnly, File>("/test.txt", 0o644)
-            .await
-            .unwrap();
-        assert!(vfs.stat("/test.txt").await.is_ok());
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_create_has_one_winner() {
+    let vfs = VfsMem::new();
+    let tasks: Vec<_> = (0..16)
+        .map(|_| {
+            let vfs = vfs.clone();
+            tokio::spawn(async move { vfs.create::<ReadWrite, File>("/race", 0o644).await })
+        })
+        .collect();
 
-        vfs.remove::<File>("/test.txt").await.unwrap();
-        assert!(vfs.stat("/test.txt").await.is_err());
+    let mut created = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => created += 1,
+            Err(VfsError::AlreadyExists(p)) => assert_eq!(p, "/race"),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
     }
+    assert_eq!(created, 1);
+}
 
-    #[tokio::test]
-    async fn test_cannot_remove_nonempty_dir() {
-        let vfs = VfsMem::new();
+#[tokio::test]
+async fn test_io_limit() {
+    let vfs = VfsMem::new().with_io_limit(4);
+    let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
 
-        vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
-        vfs.create::<WriteOnly, File>("/dir/file.txt", 0o644)
-            .await
-            .unwrap();
+    assert!(matches!(
+        vfs.write(&handle, 0, b"too long").await,
+        Err(VfsError::InvalidArgument(_))
+    ));
+    assert_eq!(vfs.stat("/f").await.unwrap().size, 0);
+
+    vfs.write(&handle, 0, b"abcd").await.unwrap();
+    vfs.write(&handle, 4, b"efgh").await.unwrap();
+    assert_eq!(vfs.read(&handle, 0, 100).await.unwrap(), b"abcd");
+    assert_eq!(vfs.read(&handle, 6, 100).await.unwrap(), b"gh");
+
+    let mut buf = [0u8; 8];
+    assert_eq!(vfs.read_into(&handle, 2, &mut buf).await.unwrap(), 4);
+    assert_eq!(&buf[..4], b"cdef");
+}
+
+#[tokio::test]
+async fn test_changed_since() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/dir/f", b"one").await;
+    write_file(&vfs, "/other", b"x").await;
+    assert!(vfs.changed_since("/", ChangeToken::default()).await.unwrap().0);
+
+    let before = vfs.change_token().unwrap();
+    let handle = vfs.open::<WriteOnly, File>("/dir/f", OWRITE).await.unwrap();
+    vfs.write(&handle, 0, b"two").await.unwrap();
+    let after = vfs.change_token().unwrap();
+    assert!(after > before);
+
+    let (changed, token) = vfs.changed_since("/dir", before).await.unwrap();
+    assert!(changed);
+    assert_eq!(vfs.changed_since("/dir", token).await.unwrap(), (false, token));
+
+    // Changes outside the subtree don't count, removals inside do
+    vfs.put("/other", b"y").await.unwrap();
+    assert!(!vfs.changed_since("/dir", token).await.unwrap().0);
+    assert!(vfs.changed_since("/", token).await.unwrap().0);
+    vfs.remove::<File>("/dir/f").await.unwrap();
+    assert!(vfs.changed_since("/dir", token).await.unwrap().0);
+    assert!(matches!(
+        vfs.changed_since("/dir/f", token).await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_open_file_clunks_on_drop() {
+    let vfs = VfsMem::new();
+    let file = vfs.create_owned::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    vfs.write(&file, 0, b"data").await.unwrap();
+    assert_eq!(vfs.open_handles().unwrap().len(), 1);
+    drop(file);
+    assert!(vfs.open_handles().unwrap().is_empty());
+
+    // close reports errors, and a handle given away is left open
+    let file = vfs.open_owned::<ReadOnly, File>("/f", 0).await.unwrap();
+    assert_eq!(vfs.read(&file, 0, 10).await.unwrap(), b"data");
+    file.close().unwrap();
+    let handle = vfs.open_owned::<ReadOnly, File>("/f", 0).await.unwrap().into_handle();
+    assert_eq!(vfs.open_handles().unwrap().len(), 1);
+    vfs.clunk(handle.fid).await.unwrap();
+}
 
-        let result = vfs.remove::<Dir>("/dir").await;
-        assert!(result.is_err());
+#[tokio::test]
+async fn test_open_file_outlived_by_handle() {
+    let vfs = VfsMem::new();
+    let file = vfs.create_owned::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    vfs.write(&file, 0, b"data").await.unwrap();
+
+    // The handle doesn't hold the filesystem, so it can be taken apart
+    let files = vfs.into_files().unwrap();
+    assert_eq!(files["/f"], b"data");
+    assert!(matches!(file.close(), Err(VfsError::InvalidArgument(_))));
+}
+
+#[tokio::test]
+async fn test_handles_follow_rename() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/old").await.unwrap();
+    write_file(&vfs, "/old/f", b"before").await;
+
+    let file = vfs.open::<ReadWrite, File>("/old/f", ORDWR).await.unwrap();
+    let dir = vfs.open::<ReadOnly, Dir>("/old", 0).await.unwrap();
+    vfs.rename("/old", "/new").await.unwrap();
+
+    assert_eq!(vfs.read(&file, 0, 100).await.unwrap(), b"before");
+    vfs.write(&file, 0, b"after!").await.unwrap();
+    assert_eq!(read_file(&vfs, "/new/f").await, b"after!");
+    assert_eq!(vfs.readdir(&dir).await.unwrap()[0].name, "f");
+}
+
+#[tokio::test]
+async fn test_rename_overwrite_detaches_target_handles() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"source").await;
+    write_file(&vfs, "/b", b"target").await;
+
+    let target = vfs.open::<ReadWrite, File>("/b", ORDWR).await.unwrap();
+    let source = vfs.open::<ReadOnly, File>("/a", 0).await.unwrap();
+    vfs.rename_opts("/a", "/b", true).await.unwrap();
+
+    // The old target's handle never reaches the file renamed over it
+    assert!(matches!(vfs.read(&target, 0, 100).await, Err(VfsError::NotFound(_))));
+    assert!(matches!(vfs.write(&target, 0, b"x").await, Err(VfsError::NotFound(_))));
+    assert_eq!(vfs.read(&source, 0, 100).await.unwrap(), b"source");
+    assert_eq!(read_file(&vfs, "/b").await, b"source");
+    vfs.clunk(target.fid).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_read_ranges() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"0123456789abcdef").await;
+    let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+    let ranges = [(0, 3), (8, 4), (14, 10)];
+    let slices = vfs.read_ranges(&handle, &ranges).await.unwrap();
+    assert_eq!(slices, [&b"012"[..], b"89ab", b"ef"]);
+    for ((offset, count), slice) in ranges.iter().zip(&slices) {
+        assert_eq!(&vfs.read(&handle, *offset, *count).await.unwrap(), slice);
     }
+}
 
-    #[tokio::test]
-    async fn test_type_mismatch() {
-        let vfs = VfsMem::new();
+#[tokio::test]
+async fn test_strict_trailing_slash() {
+    let vfs = VfsMem::new().with_strict_trailing_slash();
+    write_file(&vfs, "/file", b"x").await;
+    vfs.create_dir_all("/dir").await.unwrap();
+
+    assert!(matches!(vfs.stat("/file/").await, Err(VfsError::NotADirectory(p)) if p == "/file"));
+    assert!(vfs.stat("/file").await.is_ok());
+    assert!(vfs.stat("/dir/").await.unwrap().is_dir());
+    assert!(matches!(
+        vfs.create::<WriteOnly, File>("/new/", 0o644).await,
+        Err(VfsError::NotADirectory(_))
+    ));
+    vfs.create::<ReadOnly, Dir>("/newdir/", 0o755).await.unwrap();
+
+    // The default ignores trailing slashes
+    let lax = VfsMem::new();
+    write_file(&lax, "/file", b"x").await;
+    assert!(lax.stat("/file/").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_backslash_separators() {
+    let vfs = VfsMem::new().with_backslash_separators();
+    vfs.create_dir_all("/a").await.unwrap();
+    write_file(&vfs, "\\a\\b", b"x").await;
+    assert_eq!(read_file(&vfs, "/a/b").await, b"x");
+    assert!(vfs.stat("a\\b\\").await.unwrap().is_file());
+
+    // By default a backslash is part of the name
+    let literal = VfsMem::new();
+    write_file(&literal, "\\a\\b", b"x").await;
+    let root = literal.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    let entries = literal.readdir(&root).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "\\a\\b");
+    assert!(literal.stat("/a/b").await.is_err());
+}
+
+#[tokio::test]
+async fn test_empty_write_is_a_no_op() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"data").await;
+    let handle = vfs.open::<WriteOnly, File>("/f", OWRITE).await.unwrap();
+    let before = vfs.stat("/f").await.unwrap();
+
+    assert_eq!(vfs.write(&handle, 1 << 40, b"").await.unwrap(), 0);
+    assert_eq!(vfs.write(&handle, u64::MAX, b"").await.unwrap(), 0);
+    assert_eq!(vfs.write_vectored(&handle, 1 << 40, &[b"", b""]).await.unwrap(), 0);
+
+    let after = vfs.stat("/f").await.unwrap();
+    assert_eq!(after.size, 4);
+    assert_eq!(after.qid.version, before.qid.version);
+
+    // Emptying a file with put still counts as a change
+    vfs.put("/f", b"").await.unwrap();
+    assert!(vfs.stat("/f").await.unwrap().qid.version > before.qid.version);
+}
+
+#[tokio::test]
+async fn test_create_in() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a").await.unwrap();
+    let dir = vfs.open::<ReadOnly, Dir>("/a", 0).await.unwrap();
 
-        vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    let file = vfs.create_in::<WriteOnly, File>(&dir, "b.txt", 0o644).await.unwrap();
+    assert_eq!(file.path, "/a/b.txt");
+    assert!(vfs.stat("/a/b.txt").await.unwrap().is_file());
 
-        // Try to open directory as file
-        let result = vfs.open::<ReadOnly, File>("/dir", 0).await;
-        assert!(matches!(result, Err(VfsError::IsADirectory(_))));
+    for name in ["", ".", "..", "x/y", "nul\0"] {
+        assert!(matches!(
+            vfs.create_in::<WriteOnly, File>(&dir, name, 0o644).await,
+            Err(VfsError::InvalidPath(_))
+        ));
     }
+}
+
+#[tokio::test]
+async fn test_read_at_most_reports_eof() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"0123456789").await;
+    let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+    assert_eq!(vfs.read_at_most(&handle, 2, 3).await.unwrap(), (b"234".to_vec(), false));
+    assert_eq!(vfs.read_at_most(&handle, 6, 100).await.unwrap(), (b"6789".to_vec(), true));
+    assert_eq!(vfs.read_at_most(&handle, 6, 4).await.unwrap(), (b"6789".to_vec(), true));
+    assert_eq!(vfs.read_at_most(&handle, 50, 4).await.unwrap(), (Vec::new(), true));
+}
+
+#[tokio::test]
+async fn test_readdir_read_write_handle() {
+    let vfs = VfsMem::new();
+    let dir = vfs.create::<ReadWrite, Dir>("/d", 0o755).await.unwrap();
+    write_file(&vfs, "/d/f", b"x").await;
+
+    let entries = vfs.readdir(&dir).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "f");
+}
 
-    #[tokio::test]
-    async fn test_path_traversal_blocked() {
+#[test]
+fn test_core_needs_no_runtime() {
+    // Driven by a bare executor, as under any runtime feature or none
+    futures::executor::block_on(async {
         let vfs = VfsMem::new();
+        let mut events = vfs.subscribe();
+        write_file(&vfs, "/plain", b"no runtime").await;
+        assert_eq!(read_file(&vfs, "/plain").await, b"no runtime");
+        assert!(events.try_recv().is_ok());
+
+        let _guard = vfs.lock("/plain", true).await.unwrap();
+        assert!(vfs.try_lock("/plain", false).is_err());
+    });
+}
+
+#[tokio::test]
+async fn test_generation_changes_on_recreate() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/reused", b"first").await;
+    let first = vfs.stat("/reused").await.unwrap();
+
+    vfs.put("/reused", b"first, edited").await.unwrap();
+    assert_eq!(vfs.stat("/reused").await.unwrap().generation, first.generation);
+
+    vfs.remove::<File>("/reused").await.unwrap();
+    write_file(&vfs, "/reused", b"second").await;
+    let second = vfs.stat("/reused").await.unwrap();
+
+    assert_eq!(second.qid.path, first.qid.path);
+    assert_ne!(second.generation, first.generation);
+
+    // A rename moves the same file, so it keeps its generation
+    vfs.rename("/reused", "/moved").await.unwrap();
+    assert_eq!(vfs.stat("/moved").await.unwrap().generation, second.generation);
+}
+
+#[tokio::test]
+async fn test_dir_version_starts_at_zero() {
+    let vfs = VfsMem::new();
+    let created = vfs.create::<ReadOnly, Dir>("/d", 0o755).await.unwrap();
+    assert_eq!(created.qid.version, 0);
+    assert_eq!(vfs.stat("/d").await.unwrap().qid.version, 0);
+
+    for _ in 0..2 {
+        let opened = vfs.open::<ReadOnly, Dir>("/d", 0).await.unwrap();
+        assert_eq!(opened.qid.version, 0);
+        vfs.clunk(opened.fid).await.unwrap();
+    }
+    assert_eq!(vfs.stat("/d").await.unwrap().qid.version, 0);
+
+    write_file(&vfs, "/d/child", b"").await;
+    assert_eq!(vfs.stat("/d").await.unwrap().qid.version, 1);
+}
+
+#[tokio::test]
+async fn test_exchange_files() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/blue.conf", b"blue").await;
+    write_file(&vfs, "/green.conf", b"green").await;
+    let handle = vfs.open::<ReadOnly, File>("/blue.conf", 0).await.unwrap();
+
+    vfs.exchange("/blue.conf", "/green.conf").await.unwrap();
+    assert_eq!(read_file(&vfs, "/blue.conf").await, b"green");
+    assert_eq!(read_file(&vfs, "/green.conf").await, b"blue");
+    // The handle followed its file
+    assert_eq!(vfs.read(&handle, 0, 16).await.unwrap(), b"blue");
+
+    assert!(matches!(
+        vfs.exchange("/blue.conf", "/missing").await,
+        Err(VfsError::NotFound(p)) if p == "/missing"
+    ));
+}
+
+#[tokio::test]
+async fn test_exchange_dirs() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/sub").await.unwrap();
+    write_file(&vfs, "/a/sub/one", b"1").await;
+    vfs.create_dir_all("/b").await.unwrap();
+    write_file(&vfs, "/b/two", b"2").await;
+
+    vfs.exchange("/a", "/b").await.unwrap();
+    assert_eq!(read_file(&vfs, "/a/two").await, b"2");
+    assert_eq!(read_file(&vfs, "/b/sub/one").await, b"1");
+    assert!(vfs.stat("/a/sub").await.is_err());
+    assert!(vfs.stat("/b/two").await.is_err());
+
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    let mut names: Vec<String> = vfs.readdir(&root).await.unwrap().into_iter().map(|s| s.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b"]);
+    assert!(matches!(vfs.exchange("/b", "/b/sub").await, Err(VfsError::InvalidArgument(_))));
+}
+
+#[tokio::test]
+async fn test_access() {
+    let vfs = VfsMem::new().with_default_owner("alice", "staff");
+    vfs.create::<WriteOnly, File>("/report", 0o640).await.unwrap();
+    vfs.chmod("/report", 0o640).await.unwrap();
+    let rw = AccessMode::READ | AccessMode::WRITE;
+
+    // Owner: rw-
+    assert!(vfs.access("/report", "alice", "other", rw).await.unwrap());
+    assert!(!vfs.access("/report", "alice", "staff", AccessMode::EXEC).await.unwrap());
+    // Group: r--
+    assert!(vfs.access("/report", "bob", "staff", AccessMode::READ).await.unwrap());
+    assert!(!vfs.access("/report", "bob", "staff", rw).await.unwrap());
+    // Other: ---
+    assert!(!vfs.access("/report", "eve", "users", AccessMode::READ).await.unwrap());
+
+    assert!(matches!(
+        vfs.access("/missing", "alice", "staff", AccessMode::READ).await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_write_all_chunked() {
+    let vfs = VfsMem::new();
+    let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+    write_file(&vfs, "/big", b"old contents that will be replaced").await;
+
+    vfs.write_all_chunked("/big", &data, 4096).await.unwrap();
+    assert_eq!(read_file(&vfs, "/big").await, data);
+
+    vfs.write_all_chunked("/new", b"short", 2).await.unwrap();
+    assert_eq!(read_file(&vfs, "/new").await, b"short");
+    assert!(vfs.write_all_chunked("/new", b"x", 0).await.is_err());
+}
+
+#[tokio::test]
+async fn test_allocated_vs_logical_bytes() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    vfs.write(&handle, 0, b"hello").await.unwrap();
+    vfs.reserve(&handle, 1 << 16).await.unwrap();
+    vfs.link("/f", "/g").await.unwrap();
+
+    assert_eq!(vfs.logical_bytes().unwrap(), 5);
+    assert!(vfs.allocated_bytes().unwrap() >= 5 + (1 << 16));
+}
+
+#[tokio::test]
+async fn test_compact() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    vfs.reserve(&handle, 1 << 20).await.unwrap();
+    vfs.write(&handle, 0, b"little").await.unwrap();
+    let before = vfs.allocated_bytes().unwrap();
+
+    let freed = vfs.compact().unwrap();
+    assert!(freed >= 1 << 19);
+    assert_eq!(vfs.allocated_bytes().unwrap(), before - freed);
+    assert_eq!(vfs.allocated_bytes().unwrap(), vfs.logical_bytes().unwrap());
+    assert_eq!(read_file(&vfs, "/f").await, b"little");
+    assert_eq!(vfs.compact().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_set_cwd() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a").await.unwrap();
+    write_file(&vfs, "/a/b", b"relative").await;
+    write_file(&vfs, "/c", b"absolute").await;
+    assert_eq!(vfs.cwd().unwrap(), "/");
+
+    vfs.set_cwd("/a").unwrap();
+    assert_eq!(vfs.cwd().unwrap(), "/a");
+    assert_eq!(vfs.stat("b").await.unwrap().name, "b");
+    assert_eq!(read_file(&vfs, "b").await, b"relative");
+    assert_eq!(read_file(&vfs, "/c").await, b"absolute");
+    assert_eq!(read_file(&vfs, "../c").await, b"absolute");
+    assert!(matches!(vfs.stat("../..").await, Err(VfsError::InvalidPath(_))));
+
+    assert!(matches!(vfs.set_cwd("b"), Err(VfsError::NotADirectory(p)) if p == "/a/b"));
+    assert!(matches!(vfs.set_cwd("/missing"), Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_deep_tree_traversal() {
+    const DEPTH: usize = 10_000;
+    let vfs = VfsMem::new().with_limits(usize::MAX, usize::MAX);
+    let mut path = String::new();
+    {
+        // Going through `create` resolves every ancestor each time, which
+        // is quadratic in depth; insert the chain directly instead
+        let mut nodes = vfs.nodes.write().unwrap();
+        for _ in 0..DEPTH {
+            path.push_str("/d");
+            nodes.insert(path.clone(), Node::new_dir(SystemTime::now()));
+        }
+    }
+
+    let entries = vfs.readdir_recursive("/").await.unwrap();
+    assert_eq!(entries.len(), DEPTH);
+    assert_eq!(entries.last().unwrap().0, path);
+
+    // Rendering is quadratic in depth, so check a shallower part of the chain
+    let tree = vfs.render_tree(&path[..2 * (DEPTH - 500)]).await.unwrap();
+    assert_eq!(tree.lines().count(), 501);
+}
+
+#[tokio::test]
+async fn test_open_any() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/dir/f", b"hello").await;
+
+    match vfs.open_any("/dir/f", ORDWR).await.unwrap() {
+        OpenResult::File(fh) => {
+            vfs.write(&fh, 0, b"J").await.unwrap();
+            assert_eq!(vfs.read(&fh, 0, 5).await.unwrap(), b"Jello");
+        }
+        other => panic!("opened a file as {:?}", other),
+    }
+    match vfs.open_any("/dir", 0).await.unwrap() {
+        OpenResult::Dir(dh) => assert_eq!(vfs.readdir(&dh).await.unwrap()[0].name, "f"),
+        other => panic!("opened a directory as {:?}", other),
+    }
+    // The handle's access follows the mode, so a plain read can't write
+    assert!(matches!(vfs.open_any("/dir/f", 0).await.unwrap(), OpenResult::ReadFile(_)));
+    match vfs.open_any("/dir/f", OWRITE | OTRUNC).await.unwrap() {
+        OpenResult::WriteFile(fh) => vfs.write(&fh, 0, b"bye").await.unwrap(),
+        other => panic!("opened for writing as {:?}", other),
+    };
+    assert_eq!(read_file(&vfs, "/dir/f").await, b"bye");
+
+    assert!(matches!(vfs.open_any("/dir", OWRITE).await, Err(VfsError::PermissionDenied(_))));
+    assert!(matches!(vfs.open_any("/missing", 0).await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_create_is_exclusive() {
+    let vfs = VfsMem::new().with_strict_trailing_slash();
+    write_file(&vfs, "/file", b"data").await;
+    vfs.create_dir_all("/dir").await.unwrap();
+
+    // Same kind
+    assert!(matches!(
+        vfs.create::<ReadWrite, File>("/file", 0o644).await,
+        Err(VfsError::AlreadyExists(p)) if p == "/file"
+    ));
+    assert!(matches!(
+        vfs.create::<ReadOnly, Dir>("/dir", 0o755).await,
+        Err(VfsError::AlreadyExists(p)) if p == "/dir"
+    ));
+    // Different kind, including a trailing slash naming a directory
+    assert!(matches!(
+        vfs.create::<ReadOnly, Dir>("/file", 0o755).await,
+        Err(VfsError::AlreadyExists(p)) if p == "/file"
+    ));
+    for spelling in ["/dir", "/dir/"] {
+        assert!(matches!(
+            vfs.create::<ReadWrite, File>(spelling, 0o644).await,
+            Err(VfsError::AlreadyExists(p)) if p == "/dir"
+        ));
+    }
+    assert_eq!(read_file(&vfs, "/file").await, b"data");
+}
+
+#[tokio::test]
+async fn test_open_or_create() {
+    let vfs = VfsMem::new();
+    let fh = vfs.open_or_create::<ReadWrite, File>("/f", ORDWR).await.unwrap();
+    vfs.write(&fh, 0, b"first").await.unwrap();
+
+    // An existing file is opened, not replaced
+    let again = vfs.open_or_create::<ReadWrite, File>("/f", ORDWR).await.unwrap();
+    assert_eq!(vfs.read(&again, 0, 16).await.unwrap(), b"first");
+    assert_ne!(again.fid, fh.fid);
+
+    vfs.open_or_create::<ReadOnly, Dir>("/d", 0).await.unwrap();
+    assert!(vfs.stat("/d").await.unwrap().is_dir());
+    assert!(matches!(
+        vfs.open_or_create::<ReadWrite, File>("/d", ORDWR).await,
+        Err(VfsError::IsADirectory(_))
+    ));
+    assert!(matches!(
+        vfs.open_or_create::<ReadWrite, File>("/missing/f", ORDWR).await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_readdir_wire() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir/sub").await.unwrap();
+    write_file(&vfs, "/dir/b.txt", b"bee").await;
+    write_file(&vfs, "/dir/a.txt", b"a").await;
+
+    let dir = vfs.open::<ReadOnly, Dir>("/dir", 0).await.unwrap();
+    let all = vfs.readdir_wire(&dir, 0, 8192).await.unwrap();
+    let mut cursor = &all[..];
+    let mut decoded = Vec::new();
+    while !cursor.is_empty() {
+        decoded.push(wire::decode_stat(&mut cursor).unwrap());
+    }
+    let summary: Vec<(&str, u64, bool)> =
+        decoded.iter().map(|s| (s.name.as_str(), s.size, s.is_dir())).collect();
+    assert_eq!(summary, [("a.txt", 1, false), ("b.txt", 3, false), ("sub", 0, true)]);
+    assert_eq!(decoded[1].qid, vfs.stat("/dir/b.txt").await.unwrap().qid);
+
+    // One byte short of two entries gives just the first
+    let first_len = 2 + u16::from_le_bytes([all[0], all[1]]) as usize;
+    let second_len = 2 + u16::from_le_bytes([all[first_len], all[first_len + 1]]) as usize;
+    let mut offset = 0;
+    let mut pieces = Vec::new();
+    loop {
+        let chunk = vfs.readdir_wire(&dir, offset, first_len + second_len - 1).await.unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        offset += chunk.len() as u64;
+        pieces.push(chunk);
+    }
+    // "sub" encodes shorter than "a.txt", so the last two share a read
+    assert_eq!(pieces.len(), 2);
+    assert_eq!(pieces[0], all[..first_len]);
+    assert_eq!(pieces.concat(), all);
 
-        let result = vfs.create::<WriteOnly, File>("/../etc/passwd", 0o644).await;
-        assert!(matches!(result, Err(VfsError::InvalidPath(_))));
+    assert!(matches!(vfs.readdir_wire(&dir, 1, 8192).await, Err(VfsError::BadOffset)));
+    assert!(matches!(vfs.readdir_wire(&dir, 0, 10).await, Err(VfsError::InvalidArgument(_))));
+    assert!(vfs.readdir_wire(&dir, all.len() as u64 + 5, 8192).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_no_gap_writes() {
+    for strict in [false, true] {
+        let vfs = match strict {
+            true => VfsMem::new().with_no_gap_writes(),
+            false => VfsMem::new(),
+        };
+        let fh = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+
+        // Contiguous writes, including one starting exactly at the end
+        vfs.write(&fh, 0, b"abc").await.unwrap();
+        vfs.write(&fh, 3, b"def").await.unwrap();
+        vfs.write(&fh, 1, b"X").await.unwrap();
+        assert_eq!(read_file(&vfs, "/f").await, b"aXcdef");
+
+        let gap = vfs.write(&fh, 10, b"z").await;
+        if strict {
+            assert!(matches!(gap, Err(VfsError::BadOffset)));
+            assert_eq!(vfs.stat("/f").await.unwrap().size, 6);
+        } else {
+            assert_eq!(gap.unwrap(), 1);
+            assert_eq!(read_file(&vfs, "/f").await, b"aXcdef\0\0\0\0z");
+        }
     }
 }
+
+#[tokio::test]
+async fn test_snapshot_delta() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/docs/old").await.unwrap();
+    write_file(&vfs, "/docs/a.txt", b"alpha").await;
+    write_file(&vfs, "/docs/old/b.txt", b"beta").await;
+    write_file(&vfs, "/same.txt", b"untouched").await;
+    write_file(&vfs, "/moved.txt", b"moving").await;
+    write_file(&vfs, "/replaced.txt", b"first").await;
+
+    let base = vfs.snapshot().unwrap();
+    let replica = vfs.fork().unwrap();
+    assert!(vfs.snapshot_since(&base).unwrap().is_empty());
+
+    vfs.put("/docs/a.txt", b"alpha, edited").await.unwrap();
+    vfs.remove::<File>("/docs/old/b.txt").await.unwrap();
+    vfs.remove::<Dir>("/docs/old").await.unwrap();
+    vfs.create_dir_all("/docs/new").await.unwrap();
+    write_file(&vfs, "/docs/new/c.txt", b"gamma").await;
+    vfs.chmod("/same.txt", 0o600).await.unwrap();
+    vfs.rename_opts("/moved.txt", "/replaced.txt", true).await.unwrap();
+
+    let delta = vfs.snapshot_since(&base).unwrap();
+    let added: Vec<&str> = delta.added.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(added, ["/docs/new", "/docs/new/c.txt"]);
+    assert_eq!(delta.removed, ["/docs/old", "/docs/old/b.txt", "/moved.txt"]);
+    let modified: Vec<&str> = delta.modified.iter().map(|(p, _)| p.as_str()).collect();
+    assert!(modified.contains(&"/docs/a.txt"));
+    assert!(modified.contains(&"/replaced.txt"));
+    assert!(modified.contains(&"/same.txt"));
+
+    replica.apply_delta(delta).unwrap();
+    assert_eq!(vfs.diff(&replica).unwrap(), []);
+    assert_eq!(read_file(&replica, "/replaced.txt").await, b"moving");
+}
+
+#[tokio::test]
+async fn test_apply_delta_is_atomic() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/keep").await.unwrap();
+    write_file(&vfs, "/keep/a.txt", b"alpha").await;
+    let replica = vfs.fork().unwrap();
+
+    let bad = VfsDelta {
+        added: vec![(
+            "/missing/b.txt".into(),
+            DeltaEntry::File {
+                mode: 0o644,
+                data: b"beta".to_vec(),
+            },
+        )],
+        modified: vec![],
+        removed: vec!["/keep".into(), "/keep/a.txt".into()],
+    };
+    assert!(matches!(replica.apply_delta(bad), Err(VfsError::NotFound(_))));
+    assert_eq!(vfs.diff(&replica).unwrap(), []);
+
+    let under_file = VfsDelta {
+        added: vec![("/keep/a.txt/c".into(), DeltaEntry::Dir { mode: 0o755 })],
+        ..VfsDelta::default()
+    };
+    assert!(matches!(replica.apply_delta(under_file), Err(VfsError::NotADirectory(_))));
+    assert_eq!(vfs.diff(&replica).unwrap(), []);
+}
+
+#[tokio::test]
+async fn test_apply_delta_removes_subtrees() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/docs").await.unwrap();
+    write_file(&vfs, "/docs/a.txt", b"alpha").await;
+    let base = vfs.snapshot().unwrap();
+    let replica = vfs.fork().unwrap();
+    // Only the replica has this, so the delta does not name it
+    write_file(&replica, "/docs/local.txt", b"extra").await;
+
+    vfs.remove::<File>("/docs/a.txt").await.unwrap();
+    vfs.remove::<Dir>("/docs").await.unwrap();
+    replica.apply_delta(vfs.snapshot_since(&base).unwrap()).unwrap();
+
+    assert!(!replica.exists("/docs/local.txt").await.unwrap());
+    assert_eq!(vfs.diff(&replica).unwrap(), []);
+}
+
+#[tokio::test]
+async fn test_apply_delta_is_journaled() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-delta-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/docs/old").await.unwrap();
+    write_file(&vfs, "/docs/old/a.txt", b"alpha").await;
+    write_file(&vfs, "/b.txt", b"beta").await;
+    let base = vfs.snapshot().unwrap();
+
+    let replica = VfsMem::new().with_journal(path.clone()).unwrap();
+    replica.create_dir_all("/docs/old").await.unwrap();
+    write_file(&replica, "/docs/old/a.txt", b"alpha").await;
+    write_file(&replica, "/b.txt", b"beta").await;
+
+    vfs.remove::<File>("/docs/old/a.txt").await.unwrap();
+    vfs.remove::<Dir>("/docs/old").await.unwrap();
+    vfs.chmod("/docs", 0o700).await.unwrap();
+    vfs.put("/b.txt", b"beta, edited").await.unwrap();
+    vfs.chmod("/b.txt", 0o600).await.unwrap();
+    write_file(&vfs, "/docs/c.txt", b"gamma").await;
+    replica.apply_delta(vfs.snapshot_since(&base).unwrap()).unwrap();
+    assert_eq!(vfs.diff(&replica).unwrap(), []);
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(replica.diff(&replayed).unwrap(), []);
+    assert_eq!(replayed.stat("/b.txt").await.unwrap().mode & 0o777, 0o600);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_stat_many() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/one", b"1").await;
+    vfs.create_dir_all("/dir").await.unwrap();
+
+    let paths: Vec<String> = ["/one", "/missing", "dir"].iter().map(|s| s.to_string()).collect();
+    let results = vfs.stat_many(&paths).await.unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, "/one");
+    assert_eq!(results[0].1.as_ref().unwrap().size, 1);
+    assert!(matches!(&results[1].1, Err(VfsError::NotFound(p)) if p == "/missing"));
+    assert_eq!(results[2].0, "dir");
+    assert_eq!(results[2].1.as_ref().unwrap().name, "dir");
+}
+
+#[tokio::test]
+async fn test_cannot_remove_nonempty_dir() {
+    let vfs = VfsMem::new();
+
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    vfs.create::<WriteOnly, File>("/dir/file.txt", 0o644)
+        .await
+        .unwrap();
+
+    let result = vfs.remove::<Dir>("/dir").await;
+    assert!(matches!(&result, Err(VfsError::DirectoryNotEmpty(p)) if p == "/dir"));
+    assert_eq!(result.unwrap_err().to_string(), "directory not empty: /dir");
+
+    vfs.remove::<File>("/dir/file.txt").await.unwrap();
+    vfs.remove::<Dir>("/dir").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_type_mismatch() {
+    let vfs = VfsMem::new();
+
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    // Try to open directory as file
+    let result = vfs.open::<ReadOnly, File>("/dir", 0).await;
+    assert!(matches!(result, Err(VfsError::IsADirectory(_))));
+}
+
+#[tokio::test]
+async fn test_path_traversal_blocked() {
+    let vfs = VfsMem::new();
+
+    let result = vfs.create::<WriteOnly, File>("/../etc/passwd", 0o644).await;
+    assert!(matches!(result, Err(VfsError::InvalidPath(_))));
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_import_tar() {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_mode(0o700);
+    header.set_mtime(1_000_000);
+    header.set_size(0);
+    builder
+        .append_data(&mut header, "docs/", std::io::empty())
+        .unwrap();
+
+    let content = b"hello from tar";
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o600);
+    header.set_mtime(2_000_000);
+    header.set_size(content.len() as u64);
+    builder
+        .append_data(&mut header, "docs/readme.txt", &content[..])
+        .unwrap();
+
+    // Parent directory is implied, not present in the archive
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(3);
+    builder
+        .append_data(&mut header, "./src/lib.rs", &b"abc"[..])
+        .unwrap();
+
+    let archive = builder.into_inner().unwrap();
+
+    let vfs = VfsMem::new();
+    let loaded = vfs.import_tar(&archive[..]).await.unwrap();
+    assert_eq!(loaded, 3);
+
+    let handle = vfs
+        .open::<ReadOnly, File>("/docs/readme.txt", 0)
+        .await
+        .unwrap();
+    let data = vfs.read(&handle, 0, 100).await.unwrap();
+    assert_eq!(data, content);
+
+    let stat = vfs.stat("/docs/readme.txt").await.unwrap();
+    assert_eq!(stat.mode, 0o600);
+    assert_eq!(
+        stat.mtime,
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000)
+    );
+    let docs = vfs.stat("/docs").await.unwrap();
+    assert_eq!(docs.mode, 0o700);
+    // Importing readme.txt afterwards doesn't restamp the directory
+    assert_eq!(
+        docs.mtime,
+        std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000)
+    );
+    assert_eq!(vfs.stat("/src/lib.rs").await.unwrap().size, 3);
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_import_tar_below_file_imports_nothing() {
+    let mut builder = tar::Builder::new(Vec::new());
+    for name in ["first", "first/nested"] {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(1);
+        builder.append_data(&mut header, name, &b"x"[..]).unwrap();
+    }
+    let archive = builder.into_inner().unwrap();
+
+    let vfs = VfsMem::new();
+    let result = vfs.import_tar(&archive[..]).await;
+    assert!(matches!(result, Err(VfsError::NotADirectory(p)) if p == "/first"));
+    assert!(!vfs.exists("/first").await.unwrap());
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_import_tar_dedups_contents() {
+    let payload: Vec<u8> = (0..16 * 1024).map(|i| (i % 251) as u8).collect();
+    let source = VfsMem::new();
+    write_file(&source, "/a.bin", &payload).await;
+    write_file(&source, "/b.bin", &payload).await;
+    let mut archive = Vec::new();
+    source.export_tar("/", &mut archive).await.unwrap();
+
+    let vfs = VfsMem::new().with_dedup();
+    vfs.import_tar(&archive[..]).await.unwrap();
+    // 16KB is 4 distinct blocks, stored once for both files
+    assert_eq!(vfs.block_count().unwrap(), 4);
+    assert_eq!(read_file(&vfs, "/b.bin").await, payload);
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_import_tar_policies() {
+    use crate::backends::memory::{ImportPolicy, ImportReport};
+
+    let source = VfsMem::new();
+    write_file(&source, "/shared.txt", b"from archive").await;
+    write_file(&source, "/new.txt", b"new").await;
+    let mut archive = Vec::new();
+    source.export_tar("/", &mut archive).await.unwrap();
+
+    let target = || async {
+        let vfs = VfsMem::new();
+        write_file(&vfs, "/shared.txt", b"already here").await;
+        vfs
+    };
+
+    let vfs = target().await;
+    let report = vfs.import_tar_with(&archive[..], ImportPolicy::Merge).await.unwrap();
+    assert_eq!(report, ImportReport { added: 1, overwritten: 0, skipped: 1 });
+    assert_eq!(read_file(&vfs, "/shared.txt").await, b"already here");
+    assert_eq!(read_file(&vfs, "/new.txt").await, b"new");
+
+    let vfs = target().await;
+    let report = vfs.import_tar_with(&archive[..], ImportPolicy::Overwrite).await.unwrap();
+    assert_eq!(report, ImportReport { added: 1, overwritten: 1, skipped: 0 });
+    assert_eq!(read_file(&vfs, "/shared.txt").await, b"from archive");
+
+    let vfs = target().await;
+    let result = vfs.import_tar_with(&archive[..], ImportPolicy::Fail).await;
+    assert!(matches!(result, Err(VfsError::AlreadyExists(p)) if p == "/shared.txt"));
+    assert_eq!(read_file(&vfs, "/shared.txt").await, b"already here");
+    assert!(!vfs.exists("/new.txt").await.unwrap());
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_import_tar_rejects_traversal() {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(1);
+    // `append_data` refuses `..`, so write the name into the header directly
+    header.as_gnu_mut().unwrap().name[..9].copy_from_slice(b"../escape");
+    header.set_cksum();
+    builder.append(&header, &b"x"[..]).unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let vfs = VfsMem::new();
+    let result = vfs.import_tar(&archive[..]).await;
+    assert!(matches!(result, Err(VfsError::InvalidPath(_))));
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_tar_round_trip_keeps_qid_paths() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/f", b"data").await;
+    write_file(&vfs, "/a/g", b"more").await;
+    // Removing and recreating must not shift anything either
+    vfs.remove::<File>("/a/g").await.unwrap();
+    write_file(&vfs, "/a/g", b"again").await;
+
+    let mut archive = Vec::new();
+    vfs.export_tar("/", &mut archive).await.unwrap();
+    let restored = VfsMem::new();
+    restored.import_tar(&archive[..]).await.unwrap();
+
+    let paths = vfs.find(|_, _| true).unwrap();
+    assert_eq!(paths, restored.find(|_, _| true).unwrap());
+    for path in paths {
+        assert_eq!(
+            vfs.stat(&path).await.unwrap().qid.path,
+            restored.stat(&path).await.unwrap().qid.path,
+            "{}",
+            path
+        );
+    }
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_export_tar_round_trip() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/project", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/project/src", 0o755).await.unwrap();
+    let handle = vfs
+        .create::<ReadWrite, File>("/project/src/main.rs", 0o644)
+        .await
+        .unwrap();
+    vfs.write(&handle, 0, b"fn main() {}").await.unwrap();
+    vfs.create::<WriteOnly, File>("/project/empty.txt", 0o644)
+        .await
+        .unwrap();
+    vfs.create::<WriteOnly, File>("/outside.txt", 0o644)
+        .await
+        .unwrap();
+
+    let mut archive = Vec::new();
+    vfs.export_tar("/project", &mut archive).await.unwrap();
+
+    let restored = VfsMem::new();
+    let loaded = restored.import_tar(&archive[..]).await.unwrap();
+    assert_eq!(loaded, 3);
+
+    // Archive paths are relative to the exported root
+    let original = VfsMem::new();
+    original.create::<ReadOnly, Dir>("/src", 0o755).await.unwrap();
+    let handle = original
+        .create::<ReadWrite, File>("/src/main.rs", 0o644)
+        .await
+        .unwrap();
+    original.write(&handle, 0, b"fn main() {}").await.unwrap();
+    original
+        .create::<WriteOnly, File>("/empty.txt", 0o644)
+        .await
+        .unwrap();
+
+    assert!(original.diff(&restored).unwrap().is_empty());
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_export_tar_skips_expired() {
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+    let vfs = VfsMem::new().with_clock(clock.clone());
+    write_file(&vfs, "/kept", b"kept").await;
+    vfs.create_with_ttl::<WriteOnly, File>("/tmp", 0o644, Duration::from_secs(10))
+        .await
+        .unwrap();
+    clock.advance(Duration::from_secs(11));
+
+    let mut archive = Vec::new();
+    vfs.export_tar("/", &mut archive).await.unwrap();
+    let restored = VfsMem::new();
+    assert_eq!(restored.import_tar(&archive[..]).await.unwrap(), 1);
+    assert!(!restored.exists("/tmp").await.unwrap());
+    assert!(matches!(
+        vfs.export_tar("/tmp", &mut Vec::new()).await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_diff_skips_expired() {
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+    let vfs = VfsMem::new().with_clock(clock.clone());
+    write_file(&vfs, "/kept", b"kept").await;
+    vfs.create_with_ttl::<WriteOnly, File>("/tmp", 0o644, Duration::from_secs(10))
+        .await
+        .unwrap();
+    let other = VfsMem::new();
+    write_file(&other, "/kept", b"kept").await;
+    assert_eq!(vfs.diff(&other).unwrap(), [TreeChange::Removed("/tmp".into())]);
+
+    clock.advance(Duration::from_secs(11));
+    assert_eq!(vfs.diff(&other).unwrap(), []);
+    assert_eq!(other.diff(&vfs).unwrap(), []);
+}
+
+#[cfg(feature = "tar")]
+#[tokio::test]
+async fn test_journal_replays_tar_import() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-tar-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let source = VfsMem::new();
+    source.create_dir_all("/pkg/bin").await.unwrap();
+    write_file(&source, "/pkg/bin/tool", b"#!/bin/sh").await;
+    source.chmod("/pkg/bin/tool", 0o755).await.unwrap();
+    write_file(&source, "/pkg/readme", b"new").await;
+    let mut archive = Vec::new();
+    source.export_tar("/", &mut archive).await.unwrap();
+
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+    vfs.create::<ReadOnly, Dir>("/pkg", 0o700).await.unwrap();
+    write_file(&vfs, "/pkg/readme", b"old").await;
+    vfs.import_tar(&archive[..]).await.unwrap();
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+    assert_eq!(read_file(&replayed, "/pkg/readme").await, b"new");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_diff() {
+    let a = VfsMem::new();
+    let b = VfsMem::new();
+
+    a.create::<WriteOnly, File>("/same.txt", 0o644).await.unwrap();
+    b.create::<WriteOnly, File>("/same.txt", 0o644).await.unwrap();
+    a.create::<WriteOnly, File>("/only_a.txt", 0o644).await.unwrap();
+    b.create::<ReadOnly, Dir>("/only_b", 0o755).await.unwrap();
+
+    let handle = a
+        .create::<ReadWrite, File>("/changed.txt", 0o644)
+        .await
+        .unwrap();
+    a.write(&handle, 0, b"one").await.unwrap();
+    let handle = b
+        .create::<ReadWrite, File>("/changed.txt", 0o644)
+        .await
+        .unwrap();
+    b.write(&handle, 0, b"two").await.unwrap();
+
+    assert_eq!(
+        a.diff(&b).unwrap(),
+        vec![
+            TreeChange::Modified("/changed.txt".into()),
+            TreeChange::Removed("/only_a.txt".into()),
+            TreeChange::Added("/only_b".into()),
+        ]
+    );
+    assert!(a.diff(&a.clone()).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_fork_is_independent() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/dir/shared", b"original").await;
+    vfs.link("/dir/shared", "/linked").await.unwrap();
+    let held = vfs.open::<ReadOnly, File>("/dir/shared", 0).await.unwrap();
+
+    let fork = vfs.fork().unwrap();
+    assert!(fork.open_handles().unwrap().is_empty());
+    assert_eq!(read_file(&fork, "/dir/shared").await, b"original");
+
+    // Writes in the fork stay there, and links within it still share data
+    let handle = fork.open::<ReadWrite, File>("/dir/shared", ORDWR).await.unwrap();
+    fork.write(&handle, 0, b"ORIG").await.unwrap();
+    write_file(&fork, "/dir/new", b"fork only").await;
+    assert_eq!(read_file(&fork, "/linked").await, b"ORIGinal");
+
+    assert_eq!(read_file(&vfs, "/dir/shared").await, b"original");
+    assert_eq!(read_file(&vfs, "/linked").await, b"original");
+    assert!(!vfs.exists("/dir/new").await.unwrap());
+
+    // And the other way around
+    vfs.remove::<File>("/linked").await.unwrap();
+    assert!(fork.exists("/linked").await.unwrap());
+    vfs.clunk(held.fid).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_open_append_reads_anywhere_writes_at_end() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/log", b"first\n").await;
+    let handle = vfs.open_append("/log").await.unwrap();
+
+    assert_eq!(vfs.read(&handle, 0, 5).await.unwrap(), b"first");
+    assert_eq!(vfs.write(&handle, 0, b"second\n").await.unwrap(), 7);
+    assert_eq!(vfs.write(&handle, 2, b"third\n").await.unwrap(), 6);
+    assert_eq!(vfs.read(&handle, 6, 100).await.unwrap(), b"second\nthird\n");
+    assert_eq!(read_file(&vfs, "/log").await, b"first\nsecond\nthird\n");
+
+    // Other handles on the same file still write where they are told
+    let plain = vfs.open::<ReadWrite, File>("/log", ORDWR).await.unwrap();
+    vfs.write(&plain, 0, b"FIRST").await.unwrap();
+    assert_eq!(read_file(&vfs, "/log").await, b"FIRST\nsecond\nthird\n");
+}
+
+#[tokio::test]
+async fn test_open_and_create_root() {
+    let vfs = VfsMem::new();
+
+    assert!(matches!(
+        vfs.create::<ReadWrite, File>("/", 0o644).await,
+        Err(VfsError::AlreadyExists(p)) if p == "/"
+    ));
+    assert!(matches!(
+        vfs.create::<ReadOnly, Dir>("/", 0o755).await,
+        Err(VfsError::AlreadyExists(p)) if p == "/"
+    ));
+
+    assert!(matches!(
+        vfs.open::<ReadOnly, File>("/", 0).await,
+        Err(VfsError::IsADirectory(p)) if p == "/"
+    ));
+    assert!(matches!(
+        vfs.open::<ReadWrite, File>("/", ORDWR).await,
+        Err(VfsError::IsADirectory(p)) if p == "/"
+    ));
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    assert_eq!(root.path, "/");
+}
+
+#[tokio::test]
+async fn test_read_bytes_shares_storage() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"hello world").await;
+    let handle = vfs.open::<ReadWrite, File>("/file", ORDWR).await.unwrap();
+
+    let whole = vfs.read_bytes(&handle, 0, 100).await.unwrap();
+    let tail = vfs.read_bytes(&handle, 6, 100).await.unwrap();
+    assert_eq!(&whole[..], b"hello world");
+    assert_eq!(&tail[..], b"world");
+    // Both are views of the same buffer
+    assert_eq!(tail.as_ptr(), whole[6..].as_ptr());
+    assert!(vfs.read_bytes(&handle, 20, 5).await.unwrap().is_empty());
+
+    // Writing copies the buffer rather than changing what readers hold
+    vfs.write(&handle, 0, b"HELLO").await.unwrap();
+    assert_eq!(&whole[..], b"hello world");
+    assert_eq!(&vfs.read_bytes(&handle, 0, 5).await.unwrap()[..], b"HELLO");
+}
+
+#[tokio::test]
+async fn test_removed_file_is_gone_by_default() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"data").await;
+    let handle = vfs.open::<ReadWrite, File>("/file", ORDWR).await.unwrap();
+    vfs.remove::<File>("/file").await.unwrap();
+
+    assert!(matches!(vfs.read(&handle, 0, 4).await, Err(VfsError::NotFound(_))));
+    assert!(matches!(vfs.write(&handle, 0, b"x").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_unlinked_writes() {
+    let vfs = VfsMem::new().with_unlinked_writes();
+    write_file(&vfs, "/file", b"data").await;
+    let handle = vfs.open::<ReadWrite, File>("/file", ORDWR).await.unwrap();
+    vfs.remove::<File>("/file").await.unwrap();
+    assert!(!vfs.exists("/file").await.unwrap());
+
+    assert_eq!(vfs.read(&handle, 0, 10).await.unwrap(), b"data");
+    assert_eq!(vfs.write(&handle, 4, b" more").await.unwrap(), 5);
+    assert_eq!(vfs.read(&handle, 0, 20).await.unwrap(), b"data more");
+
+    // A new file at the same path is a different file
+    write_file(&vfs, "/file", b"new").await;
+    assert_eq!(vfs.read(&handle, 0, 20).await.unwrap(), b"data more");
+    assert_eq!(read_file(&vfs, "/file").await, b"new");
+
+    // The last clunk drops the old data
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(vfs.fids.lock().unwrap().orphaned(handle.fid).is_none());
+}
+
+#[tokio::test]
+async fn test_unlinked_writes_after_rename_over() {
+    let vfs = VfsMem::new().with_unlinked_writes();
+    write_file(&vfs, "/a", b"source").await;
+    write_file(&vfs, "/b", b"target").await;
+    let handle = vfs.open::<ReadWrite, File>("/b", ORDWR).await.unwrap();
+
+    vfs.rename_opts("/a", "/b", true).await.unwrap();
+    assert_eq!(vfs.read(&handle, 0, 20).await.unwrap(), b"target");
+    vfs.write(&handle, 0, b"T").await.unwrap();
+    assert_eq!(vfs.read(&handle, 0, 20).await.unwrap(), b"Target");
+    assert_eq!(read_file(&vfs, "/b").await, b"source");
+
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(vfs.fids.lock().unwrap().orphaned(handle.fid).is_none());
+}
+
+#[tokio::test]
+async fn test_into_files() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/a.txt", b"alpha").await;
+    write_file(&vfs, "/dir/b.txt", b"beta").await;
+    vfs.link("/a.txt", "/dir/a-link").await.unwrap();
+
+    let files = vfs.into_files().unwrap();
+    let expected: std::collections::HashMap<String, Vec<u8>> = [
+        ("/a.txt".to_string(), b"alpha".to_vec()),
+        ("/dir/b.txt".to_string(), b"beta".to_vec()),
+        ("/dir/a-link".to_string(), b"alpha".to_vec()),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(files, expected);
+}
+
+#[tokio::test]
+async fn test_into_files_fails_while_cloned() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a.txt", b"alpha").await;
+    let clone = vfs.clone();
+
+    assert!(matches!(vfs.into_files(), Err(VfsError::InvalidArgument(_))));
+    assert_eq!(read_file(&clone, "/a.txt").await, b"alpha");
+    assert_eq!(clone.into_files().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_create_many_out_of_order() {
+    let vfs = VfsMem::new();
+    vfs.create_many(&[
+        ("/project/src/main.rs".to_string(), NodeKind::File, 0o644),
+        ("/project".to_string(), NodeKind::Dir, 0o755),
+        ("/project/docs/guide/intro.md".to_string(), NodeKind::File, 0o644),
+        ("/project/src".to_string(), NodeKind::Dir, 0o755),
+        ("/project/README.md".to_string(), NodeKind::File, 0o644),
+    ])
+    .await
+    .unwrap();
+
+    let expected = "\
+/project
+├── docs/
+│   └── guide/
+│       └── intro.md (0 bytes)
+├── src/
+│   └── main.rs (0 bytes)
+└── README.md (0 bytes)
+";
+    assert_eq!(vfs.render_tree("/project").await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_create_many_applies_modes() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-many-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+    vfs.create_many(&[
+        ("/private/key".to_string(), NodeKind::File, 0o600),
+        ("/private".to_string(), NodeKind::Dir, 0o700),
+        ("/shared/tool".to_string(), NodeKind::File, 0o100755),
+    ])
+    .await
+    .unwrap();
+
+    let expected = [
+        ("/private", 0o700),
+        ("/private/key", 0o600),
+        ("/shared", 0o755),
+        ("/shared/tool", 0o755),
+    ];
+    for (p, mode) in expected {
+        assert_eq!(vfs.stat(p).await.unwrap().mode & 0o7777, mode, "{}", p);
+    }
+
+    // Replay restores the same modes
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert!(vfs.diff(&replayed).unwrap().is_empty());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_create_many_conflict_changes_nothing() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/taken", b"data").await;
+
+    let result = vfs
+        .create_many(&[
+            ("/fresh".to_string(), NodeKind::Dir, 0o755),
+            ("/taken/child".to_string(), NodeKind::File, 0o644),
+        ])
+        .await;
+    assert!(matches!(result, Err(VfsError::NotADirectory(p)) if p == "/taken"));
+    assert!(!vfs.exists("/fresh").await.unwrap());
+
+    vfs.create_dir_all("/dir").await.unwrap();
+    let result = vfs.create_many(&[("/dir".to_string(), NodeKind::File, 0o644)]).await;
+    assert!(matches!(result, Err(VfsError::IsADirectory(p)) if p == "/dir"));
+}
+
+#[tokio::test]
+async fn test_copy_within_overlapping() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"abcdefgh").await;
+    let handle = vfs.open::<ReadWrite, File>("/file", ORDWR).await.unwrap();
+    let before = vfs.stat("/file").await.unwrap().qid.version;
+
+    // Forward overlap: the source is read before it is overwritten
+    vfs.copy_within(&handle, 0, 2, 5).await.unwrap();
+    assert_eq!(read_file(&vfs, "/file").await, b"ababcdeh");
+    assert_eq!(vfs.stat("/file").await.unwrap().qid.version, before + 1);
+
+    // Backward overlap
+    vfs.copy_within(&handle, 2, 0, 5).await.unwrap();
+    assert_eq!(read_file(&vfs, "/file").await, b"abcdedeh");
+
+    // Destination past the end extends the file
+    vfs.copy_within(&handle, 4, 6, 4).await.unwrap();
+    assert_eq!(read_file(&vfs, "/file").await, b"abcdededeh");
+
+    assert!(matches!(vfs.copy_within(&handle, 8, 0, 5).await, Err(VfsError::BadOffset)));
+    assert_eq!(read_file(&vfs, "/file").await, b"abcdededeh");
+}
+
+#[tokio::test]
+async fn test_render_tree() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/project/src/bin").await.unwrap();
+    vfs.create_dir_all("/project/docs").await.unwrap();
+    write_file(&vfs, "/project/README.md", b"hello").await;
+    write_file(&vfs, "/project/src/lib.rs", b"pub mod a;").await;
+    write_file(&vfs, "/project/src/bin/main.rs", b"fn main() {}").await;
+
+    let expected = "\
+/project
+├── docs/
+├── src/
+│   ├── bin/
+│   │   └── main.rs (12 bytes)
+│   └── lib.rs (10 bytes)
+└── README.md (5 bytes)
+";
+    assert_eq!(vfs.render_tree("/project").await.unwrap(), expected);
+    assert_eq!(vfs.render_tree("/project/src/lib.rs").await.unwrap(), "lib.rs (10 bytes)\n");
+    assert_eq!(vfs.render_tree("/project/docs").await.unwrap(), "/project/docs\n");
+    assert!(matches!(vfs.render_tree("/missing").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_to_json_tree() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/b", 0o755).await.unwrap();
+    let handle = vfs
+        .create::<ReadWrite, File>("/a/b/c.txt", 0o644)
+        .await
+        .unwrap();
+    vfs.write(&handle, 0, b"hi").await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/empty", 0o755).await.unwrap();
+
+    let tree = vfs.to_json_tree("/").unwrap();
+    let file = &tree["a"]["b"]["c.txt"];
+    assert_eq!(file["size"], 2);
+    assert_eq!(file["mode"], 0o644);
+    assert!(file["mtime"].is_u64());
+    assert!(file.get("contents").is_none());
+    assert_eq!(tree["a"]["empty"], serde_json::json!({}));
+
+    let subtree = vfs.to_json_tree_with("/a/b", true).unwrap();
+    assert_eq!(subtree["c.txt"]["contents"], "aGk=");
+}
+
+#[tokio::test]
+async fn test_dedup_shares_identical_blocks() {
+    let vfs = VfsMem::new().with_dedup();
+    let payload: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+
+    for path in ["/a.bin", "/b.bin"] {
+        let handle = vfs.create::<ReadWrite, File>(path, 0o644).await.unwrap();
+        vfs.write(&handle, 0, &payload).await.unwrap();
+    }
+
+    // 64KB is 16 distinct blocks, stored once for both files
+    assert_eq!(vfs.block_count().unwrap(), 16);
+
+    let handle = vfs.open::<ReadOnly, File>("/b.bin", 0).await.unwrap();
+    assert_eq!(vfs.read(&handle, 0, payload.len()).await.unwrap(), payload);
+    assert_eq!(vfs.read(&handle, 4000, 200).await.unwrap(), payload[4000..4200]);
+
+    vfs.remove::<File>("/a.bin").await.unwrap();
+    vfs.remove::<File>("/b.bin").await.unwrap();
+    assert_eq!(vfs.block_count().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_dedup_partial_and_gap_writes() {
+    let vfs = VfsMem::new().with_dedup();
+    let handle = vfs
+        .create::<ReadWrite, File>("/sparse.bin", 0o644)
+        .await
+        .unwrap();
+
+    vfs.write(&handle, 0, b"head").await.unwrap();
+    vfs.write(&handle, 10_000, b"tail").await.unwrap();
+    vfs.write(&handle, 2, b"XY").await.unwrap();
+
+    let data = vfs.read(&handle, 0, 20_000).await.unwrap();
+    assert_eq!(data.len(), 10_004);
+    assert_eq!(&data[..4], b"heXY");
+    assert!(data[4..10_000].iter().all(|b| *b == 0));
+    assert_eq!(&data[10_000..], b"tail");
+}
+
+#[tokio::test]
+async fn test_lru_evicts_least_recently_used() {
+    let vfs = VfsMem::new().with_lru_budget(100);
+    let chunk = [7u8; 40];
+
+    let old = vfs.create::<ReadWrite, File>("/old", 0o644).await.unwrap();
+    vfs.write(&old, 0, &chunk).await.unwrap();
+    let untouched = vfs
+        .create::<ReadWrite, File>("/untouched", 0o644)
+        .await
+        .unwrap();
+    vfs.write(&untouched, 0, &chunk).await.unwrap();
+    vfs.clunk(untouched.fid).await.unwrap();
+
+    // Reading makes "/old" more recent than "/untouched"
+    vfs.read(&old, 0, 1).await.unwrap();
+
+    let new = vfs.create::<ReadWrite, File>("/new", 0o644).await.unwrap();
+    vfs.write(&new, 0, &chunk).await.unwrap();
+
+    assert!(matches!(
+        vfs.stat("/untouched").await,
+        Err(VfsError::NotFound(_))
+    ));
+    assert!(vfs.stat("/old").await.is_ok());
+    assert_eq!(vfs.stat("/new").await.unwrap().size, 40);
+}
+
+#[tokio::test]
+async fn test_lru_never_evicts_file_being_written() {
+    let vfs = VfsMem::new().with_lru_budget(10);
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    let handle = vfs
+        .create::<ReadWrite, File>("/dir/big", 0o644)
+        .await
+        .unwrap();
+    vfs.write(&handle, 0, &[1u8; 50]).await.unwrap();
+
+    assert_eq!(vfs.stat("/dir/big").await.unwrap().size, 50);
+    assert!(vfs.stat("/dir").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_lru_never_evicts_open_files() {
+    let vfs = VfsMem::new().with_lru_budget(100);
+    let chunk = [7u8; 40];
+
+    let held = vfs.create::<ReadWrite, File>("/held", 0o644).await.unwrap();
+    vfs.write(&held, 0, &chunk).await.unwrap();
+    write_file(&vfs, "/closed", &chunk).await;
+    write_file(&vfs, "/new", &chunk).await;
+
+    // "/held" is older but still open, so the closed file goes instead
+    assert!(vfs.stat("/held").await.is_ok());
+    assert!(matches!(vfs.stat("/closed").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_lru_counts_hard_links_once() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-lru-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let vfs = VfsMem::new()
+        .with_lru_budget(100)
+        .with_journal(path.clone())
+        .unwrap();
+    let chunk = [7u8; 40];
+
+    write_file(&vfs, "/a", &chunk).await;
+    vfs.link("/a", "/alias").await.unwrap();
+    write_file(&vfs, "/b", &chunk).await;
+    // Two files of 40 bytes fit, however many links they have
+    assert!(vfs.exists("/alias").await.unwrap());
+
+    let mut events = vfs.subscribe();
+    write_file(&vfs, "/c", &chunk).await;
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        seen.push(event);
+    }
+    // Evicting "/alias" alone frees nothing, so "/a" goes too
+    assert!(seen.contains(&VfsEvent::Evicted { path: "/alias".into() }));
+    assert!(seen.contains(&VfsEvent::Evicted { path: "/a".into() }));
+    assert!(vfs.exists("/b").await.unwrap());
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_ttl_file_expires() {
+    let vfs = VfsMem::new();
+    let handle = vfs
+        .create_with_ttl::<ReadWrite, File>("/scratch", 0o644, Duration::from_millis(50))
+        .await
+        .unwrap();
+    vfs.write(&handle, 0, b"temp").await.unwrap();
+    assert!(vfs.stat("/scratch").await.is_ok());
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+
+    // No reaper running: expiry is enforced lazily
+    assert!(matches!(vfs.stat("/scratch").await, Err(VfsError::NotFound(_))));
+    assert!(matches!(
+        vfs.read(&handle, 0, 10).await,
+        Err(VfsError::NotFound(_))
+    ));
+
+    // The path can be reused
+    vfs.create::<WriteOnly, File>("/scratch", 0o644).await.unwrap();
+    assert_eq!(vfs.stat("/scratch").await.unwrap().size, 0);
+}
+
+#[tokio::test]
+async fn test_poisoned_lock_reports_context() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"data").await;
+
+    let poisoner = vfs.clone();
+    let result = tokio::spawn(async move {
+        let _guard = poisoner.nodes.write().unwrap();
+        panic!("poison the node map");
+    })
+    .await;
+    assert!(result.is_err());
+
+    assert!(matches!(
+        vfs.stat("/f").await,
+        Err(VfsError::LockPoisonedAt { op: "stat", path }) if path == "/f"
+    ));
+    assert!(matches!(
+        vfs.remove::<File>("/f").await,
+        Err(VfsError::LockPoisonedAt { op: "remove", .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_poisoned_lock_never_panics_callers() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"data").await;
+    let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+    let poisoner = vfs.clone();
+    let _ = std::thread::spawn(move || {
+        let _guard = poisoner.nodes.write().unwrap();
+        panic!("poison the node map");
+    })
+    .join();
+
+    let reader = vfs.clone();
+    let read = tokio::spawn(async move { reader.read(&handle, 0, 4).await })
+        .await
+        .expect("read must return an error, not panic");
+    assert!(matches!(read, Err(VfsError::LockPoisonedAt { op: "read", .. })));
+
+    assert!(matches!(vfs.statfs().await, Err(VfsError::LockPoisonedAt { op: "statfs", .. })));
+    assert!(matches!(vfs.rename("/f", "/g").await, Err(VfsError::LockPoisonedAt { op: "rename", .. })));
+    assert!(matches!(
+        vfs.readdir_recursive("/").await,
+        Err(VfsError::LockPoisonedAt { op: "readdir_recursive", .. })
+    ));
+    assert!(matches!(vfs.fork(), Err(VfsError::LockPoisonedAt { op: "fork", .. })));
+    assert!(matches!(
+        vfs.allocated_bytes(),
+        Err(VfsError::LockPoisonedAt { op: "allocated_bytes", .. })
+    ));
+    assert!(matches!(
+        vfs.create::<WriteOnly, File>("/new", 0o644).await,
+        Err(VfsError::LockPoisonedAt { op: "create", .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_manual_clock() {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let clock = Arc::new(ManualClock::new(start));
+    let vfs = VfsMem::new().with_clock(clock.clone());
+
+    let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    assert_eq!(vfs.stat("/f").await.unwrap().mtime, start);
+    assert_eq!(vfs.stat("/").await.unwrap().mtime, start);
+
+    clock.advance(Duration::from_secs(90));
+    vfs.write(&handle, 0, b"tick").await.unwrap();
+    let stat = vfs.stat("/f").await.unwrap();
+    assert_eq!(stat.mtime, start + Duration::from_secs(90));
+    assert_eq!(stat.atime, stat.mtime);
+    assert_eq!(vfs.open_handles().unwrap()[0].opened, start);
+
+    // TTL expiry follows the clock too, without any sleeping
+    vfs.create_with_ttl::<WriteOnly, File>("/tmp", 0o644, Duration::from_secs(10))
+        .await
+        .unwrap();
+    clock.advance(Duration::from_secs(9));
+    assert!(vfs.exists("/tmp").await.unwrap());
+    clock.advance(Duration::from_secs(2));
+    assert!(!vfs.exists("/tmp").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_age_follows_clock() {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let clock = Arc::new(ManualClock::new(start));
+    let vfs = VfsMem::new().with_clock(clock.clone());
+
+    write_file(&vfs, "/f", b"data").await;
+    assert_eq!(vfs.age("/f").await.unwrap(), Duration::ZERO);
+
+    clock.advance(Duration::from_secs(42));
+    assert_eq!(vfs.age("/f").await.unwrap(), Duration::from_secs(42));
+    assert!(matches!(vfs.age("/missing").await, Err(VfsError::NotFound(_))));
+
+    // An mtime ahead of the clock clamps to zero
+    clock.set(start);
+    assert_eq!(vfs.age("/f").await.unwrap(), Duration::ZERO);
+}
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+#[tokio::test]
+async fn test_ttl_reaper_removes_expired() {
+    let vfs = VfsMem::new().with_expiry_every(Duration::from_millis(10));
+    vfs.create_with_ttl::<WriteOnly, File>("/short", 0o644, Duration::from_millis(20))
+        .await
+        .unwrap();
+    vfs.create_with_ttl::<WriteOnly, File>("/long", 0o644, Duration::from_secs(60))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let nodes = vfs.nodes.read().unwrap();
+    assert!(!nodes.contains_key("/short"));
+    assert!(nodes.contains_key("/long"));
+}
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+#[tokio::test]
+async fn test_ttl_reaper_journals_removal() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-reaper-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let vfs = VfsMem::new()
+        .with_journal(path.clone())
+        .unwrap()
+        .with_expiry_every(Duration::from_millis(10));
+    vfs.create_dir_all("/tmp").await.unwrap();
+    vfs.create_with_ttl::<WriteOnly, File>("/tmp/short", 0o644, Duration::from_millis(20))
+        .await
+        .unwrap();
+    let before = vfs.stat("/tmp").await.unwrap().qid.version;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Reaped like a remove: logged, and the parent directory changed
+    assert!(!vfs.nodes.read().unwrap().contains_key("/tmp/short"));
+    assert!(vfs.stat("/tmp").await.unwrap().qid.version > before);
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+
+    vfs.shutdown().await;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+#[tokio::test]
+async fn test_reaper_stops_with_filesystem() {
+    // Long enough that only the stop signal can end the task in time
+    let vfs = VfsMem::new().with_expiry_every(Duration::from_secs(3600));
+    let task = vfs.tasks.running.lock().unwrap().pop().unwrap();
+    drop(vfs);
+    tokio::time::timeout(Duration::from_secs(5), task.join()).await.unwrap();
+
+    let vfs = VfsMem::new().with_expiry_every(Duration::from_secs(3600));
+    let clone = vfs.clone();
+    tokio::time::timeout(Duration::from_secs(5), vfs.shutdown()).await.unwrap();
+    assert!(clone.tasks.running.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_ttl_rejects_directories() {
+    let vfs = VfsMem::new();
+    let result = vfs
+        .create_with_ttl::<ReadOnly, Dir>("/dir", 0o755, Duration::from_secs(1))
+        .await;
+    assert!(matches!(result, Err(VfsError::InvalidArgument(_))));
+    assert!(vfs.stat("/dir").await.is_err());
+}
+
+#[tokio::test]
+async fn test_exclusive_lock_conflicts() {
+    let vfs = VfsMem::new();
+    vfs.create::<WriteOnly, File>("/shared.db", 0o644).await.unwrap();
+
+    let guard = vfs.lock("/shared.db", true).await.unwrap();
+    assert!(matches!(
+        vfs.try_lock("/shared.db", true),
+        Err(VfsError::PermissionDenied(_))
+    ));
+    assert!(vfs.try_lock("/shared.db", false).is_err());
+
+    drop(guard);
+    let guard = vfs.try_lock("/shared.db", true).unwrap();
+    assert!(guard.is_exclusive());
+}
+
+#[tokio::test]
+async fn test_shared_locks_coexist() {
+    let vfs = VfsMem::new();
+    vfs.create::<WriteOnly, File>("/f", 0o644).await.unwrap();
+
+    let a = vfs.try_lock("/f", false).unwrap();
+    let b = vfs.try_lock("f", false).unwrap();
+    assert_eq!(b.path(), "/f");
+    assert!(vfs.try_lock("/f", true).is_err());
+
+    drop(a);
+    assert!(vfs.try_lock("/f", true).is_err());
+    drop(b);
+    assert!(vfs.try_lock("/f", true).is_ok());
+
+    assert!(matches!(
+        vfs.try_lock("/missing", false),
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_lock_waits_for_release() {
+    let vfs = VfsMem::new();
+    vfs.create::<WriteOnly, File>("/f", 0o644).await.unwrap();
+
+    let guard = vfs.lock("/f", true).await.unwrap();
+    let waiter = {
+        let vfs = vfs.clone();
+        tokio::spawn(async move { vfs.lock("/f", true).await.map(|_| ()) })
+    };
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+
+    drop(guard);
+    tokio::time::timeout(Duration::from_secs(1), waiter)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+async fn write_file(vfs: &VfsMem, path: &str, data: &[u8]) {
+    let handle = vfs.create::<ReadWrite, File>(path, 0o644).await.unwrap();
+    vfs.write(&handle, 0, data).await.unwrap();
+    vfs.clunk(handle.fid).await.unwrap();
+}
+
+async fn read_file(vfs: &VfsMem, path: &str) -> Vec<u8> {
+    let handle = vfs.open::<ReadOnly, File>(path, 0).await.unwrap();
+    let data = vfs.read(&handle, 0, usize::MAX).await.unwrap();
+    vfs.clunk(handle.fid).await.unwrap();
+    data
+}
+
+#[tokio::test]
+async fn test_rename_moves_subtree() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/sub", 0o755).await.unwrap();
+    write_file(&vfs, "/a/sub/f.txt", b"data").await;
+
+    vfs.rename("/a", "/b").await.unwrap();
+
+    assert!(vfs.stat("/a").await.is_err());
+    assert!(vfs.stat("/a/sub/f.txt").await.is_err());
+    assert_eq!(read_file(&vfs, "/b/sub/f.txt").await, b"data");
+}
+
+#[tokio::test]
+async fn test_rename_dir_into_itself() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/f.txt", b"data").await;
+
+    assert!(matches!(vfs.rename("/a", "/a").await, Err(VfsError::InvalidArgument(_))));
+    assert!(matches!(vfs.rename("/a", "/a/b/c").await, Err(VfsError::InvalidArgument(_))));
+    assert_eq!(read_file(&vfs, "/a/b/f.txt").await, b"data");
+
+    // A sibling sharing the name as a prefix is not a descendant
+    vfs.rename("/a", "/ab").await.unwrap();
+    assert_eq!(read_file(&vfs, "/ab/b/f.txt").await, b"data");
+    assert!(!vfs.exists("/a").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_rename_overwrite_denied() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/src", b"new").await;
+    write_file(&vfs, "/dst", b"old").await;
+
+    let result = vfs.rename("/src", "/dst").await;
+    assert!(matches!(result, Err(VfsError::AlreadyExists(_))));
+    assert_eq!(read_file(&vfs, "/dst").await, b"old");
+    assert_eq!(read_file(&vfs, "/src").await, b"new");
+}
+
+#[tokio::test]
+async fn test_rename_overwrite_allowed() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/src", b"new").await;
+    write_file(&vfs, "/dst", b"old").await;
+
+    vfs.rename_opts("/src", "/dst", true).await.unwrap();
+    assert_eq!(read_file(&vfs, "/dst").await, b"new");
+    assert!(vfs.stat("/src").await.is_err());
+
+    // An empty directory can be replaced by another directory
+    vfs.create::<ReadOnly, Dir>("/d1", 0o755).await.unwrap();
+    write_file(&vfs, "/d1/inner", b"x").await;
+    vfs.create::<ReadOnly, Dir>("/d2", 0o755).await.unwrap();
+    vfs.rename_opts("/d1", "/d2", true).await.unwrap();
+    assert_eq!(read_file(&vfs, "/d2/inner").await, b"x");
+}
+
+#[tokio::test]
+async fn test_rename_overwrite_type_mismatch() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"x").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/full", 0o755).await.unwrap();
+    write_file(&vfs, "/full/child", b"y").await;
+
+    let result = vfs.rename_opts("/file", "/dir", true).await;
+    assert!(matches!(result, Err(VfsError::IsADirectory(_))));
+
+    let result = vfs.rename_opts("/dir", "/file", true).await;
+    assert!(matches!(result, Err(VfsError::NotADirectory(_))));
+
+    let result = vfs.rename_opts("/dir", "/full", true).await;
+    assert!(matches!(result, Err(VfsError::DirectoryNotEmpty(p)) if p == "/full"));
+    assert_eq!(read_file(&vfs, "/full/child").await, b"y");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_rename_is_atomic_for_readers() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/sub").await.unwrap();
+    write_file(&vfs, "/a/sub/f.txt", b"data").await;
+
+    let renamer = {
+        let vfs = vfs.clone();
+        tokio::spawn(async move {
+            for i in 0..500 {
+                let (from, to) = if i % 2 == 0 { ("/a", "/b") } else { ("/b", "/a") };
+                vfs.rename(from, to).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+
+    // Two separate stats could straddle a rename, so observe both names
+    // through one readdir, which reads the tree under a single lock
+    let root = vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+    while !renamer.is_finished() {
+        let names: Vec<String> = vfs
+            .readdir(&root)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .filter(|n| n == "a" || n == "b")
+            .collect();
+        assert_eq!(names.len(), 1, "saw {:?}", names);
+    }
+    renamer.await.unwrap();
+
+    assert!(vfs.exists("/a/sub/f.txt").await.unwrap());
+    assert!(!vfs.exists("/b").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_path_length_limit() {
+    let vfs = VfsMem::new().with_limits(10, 256);
+
+    // "/" plus 9 bytes is exactly at the limit
+    vfs.create::<WriteOnly, File>("/abcdefghi", 0o644).await.unwrap();
+
+    let result = vfs.create::<WriteOnly, File>("/abcdefghij", 0o644).await;
+    assert!(matches!(result, Err(VfsError::InvalidPath(_))));
+    assert!(matches!(
+        vfs.stat("/abcdefghij").await,
+        Err(VfsError::InvalidPath(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_path_component_limit() {
+    let vfs = VfsMem::new().with_limits(4096, 3);
+    vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/b", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/a/b/c", 0o755).await.unwrap();
+
+    let result = vfs.create::<ReadOnly, Dir>("/a/b/c/d", 0o755).await;
+    assert!(matches!(result, Err(VfsError::InvalidPath(_))));
+}
+
+#[tokio::test]
+async fn test_dir_size_estimate() {
+    for (vfs, expected) in [(VfsMem::new(), 0), (VfsMem::new().with_dir_size_estimate(), 3 + 3 + 5)] {
+        vfs.create_dir_all("/dir/sub").await.unwrap();
+        write_file(&vfs, "/dir/a.b", b"contents").await;
+        write_file(&vfs, "/dir/three", b"").await;
+        write_file(&vfs, "/dir/sub/nested", b"").await;
+
+        // Only immediate children count; "sub", "a.b" and "three"
+        assert_eq!(vfs.stat("/dir").await.unwrap().size, expected);
+        assert_eq!(vfs.stat("/dir/a.b").await.unwrap().size, 8);
+    }
+}
+
+#[tokio::test]
+async fn test_put_reports_outcome() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/uploads").await.unwrap();
+
+    assert_eq!(vfs.put("/uploads/a", b"first version").await.unwrap(), PutOutcome::Created);
+    assert_eq!(read_file(&vfs, "/uploads/a").await, b"first version");
+    let version = vfs.stat("/uploads/a").await.unwrap().qid.version;
+
+    assert_eq!(vfs.put("/uploads/a", b"second").await.unwrap(), PutOutcome::Overwritten);
+    assert_eq!(read_file(&vfs, "/uploads/a").await, b"second");
+    assert_eq!(vfs.stat("/uploads/a").await.unwrap().qid.version, version + 1);
+
+    assert!(matches!(vfs.put("/uploads", b"x").await, Err(VfsError::IsADirectory(_))));
+    assert!(matches!(vfs.put("/missing/a", b"x").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_stat_kind_predicates() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/dir/file", b"x").await;
+
+    let dir = vfs.stat("/dir").await.unwrap();
+    let file = vfs.stat("/dir/file").await.unwrap();
+    assert!(dir.is_dir() && !dir.is_file());
+    assert!(file.is_file() && !file.is_dir());
+}
+
+#[tokio::test]
+async fn test_component_length_limit() {
+    let vfs = VfsMem::new();
+    let max = format!("/{}", "n".repeat(255));
+    vfs.create::<WriteOnly, File>(&max, 0o644).await.unwrap();
+
+    let over = format!("/dir/{}", "n".repeat(256));
+    assert!(matches!(
+        vfs.create::<WriteOnly, File>(&over, 0o644).await,
+        Err(VfsError::InvalidPath(_))
+    ));
+
+    let vfs = VfsMem::new().with_max_name_len(4);
+    vfs.create::<ReadOnly, Dir>("/abcd", 0o755).await.unwrap();
+    assert!(matches!(vfs.stat("/abcd/efghi").await, Err(VfsError::InvalidPath(_))));
+}
+
+#[tokio::test]
+async fn test_nul_in_name_rejected() {
+    let vfs = VfsMem::new();
+    assert!(matches!(
+        vfs.create::<WriteOnly, File>("/bad\0name", 0o644).await,
+        Err(VfsError::InvalidPath(_))
+    ));
+    assert!(matches!(
+        vfs.walk("/", &["bad\0name".to_string()]).await,
+        Err(VfsError::InvalidPath(_))
+    ));
+    assert!(vfs.readdir(&vfs.open::<ReadOnly, Dir>("/", 0).await.unwrap()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_default_limits_are_generous() {
+    let vfs = VfsMem::new();
+    let dir = format!("/{}", "x".repeat(199));
+    // 20 components of 200 bytes each
+    let long_name = dir.repeat(20);
+    vfs.create_dir_all(&dir.repeat(19)).await.unwrap();
+    vfs.create::<WriteOnly, File>(&long_name, 0o644).await.unwrap();
+
+    let too_long = format!("/{}", "x".repeat(4096));
+    assert!(vfs.stat(&too_long).await.is_err());
+}
+
+#[tokio::test]
+async fn test_ancestor_mtime_propagation() {
+    for propagate in [false, true] {
+        let clock = Arc::new(ManualClock::default());
+        let mut vfs = VfsMem::new().with_clock(clock.clone());
+        if propagate {
+            vfs = vfs.with_ancestor_mtime_propagation();
+        }
+        vfs.create_dir_all("/a/b").await.unwrap();
+        let start = SystemTime::UNIX_EPOCH;
+        clock.advance(Duration::from_secs(10));
+
+        let handle = vfs.create::<ReadWrite, File>("/a/b/c.txt", 0o644).await.unwrap();
+        assert!(vfs.stat("/a/b").await.unwrap().mtime > start);
+        assert_eq!(vfs.stat("/a").await.unwrap().mtime > start, propagate);
+        assert_eq!(vfs.stat("/").await.unwrap().mtime > start, propagate);
+
+        // Writes only reach directories with propagation on
+        let b_version = vfs.stat("/a/b").await.unwrap().qid.version;
+        let a_version = vfs.stat("/a").await.unwrap().qid.version;
+        vfs.write(&handle, 0, b"data").await.unwrap();
+        assert_eq!(vfs.stat("/a/b").await.unwrap().qid.version > b_version, propagate);
+        assert_eq!(vfs.stat("/a").await.unwrap().qid.version > a_version, propagate);
+    }
+}
+
+#[tokio::test]
+async fn test_dir_version_bumps_on_child_changes() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    let initial = vfs.stat("/dir").await.unwrap().qid.version;
+    assert_eq!(initial, 0);
+
+    vfs.create::<WriteOnly, File>("/dir/a", 0o644).await.unwrap();
+    let after_create = vfs.stat("/dir").await.unwrap().qid.version;
+    assert!(after_create > initial);
+
+    // Writing to a child changes the child, not the directory
+    let handle = vfs.open::<WriteOnly, File>("/dir/a", 0).await.unwrap();
+    vfs.write(&handle, 0, b"x").await.unwrap();
+    assert_eq!(vfs.stat("/dir").await.unwrap().qid.version, after_create);
+
+    vfs.rename("/dir/a", "/dir/b").await.unwrap();
+    let after_rename = vfs.stat("/dir").await.unwrap().qid.version;
+    assert!(after_rename > after_create);
+
+    vfs.remove::<File>("/dir/b").await.unwrap();
+    let after_remove = vfs.stat("/dir").await.unwrap().qid.version;
+    assert!(after_remove > after_rename);
+
+    // Walk and open report the same version
+    let walked = vfs.walk("/", &["dir".into()]).await.unwrap();
+    assert_eq!(walked.qids[0].version, after_remove);
+    let handle = vfs.open::<ReadOnly, Dir>("/dir", 0).await.unwrap();
+    assert_eq!(handle.qid.version, after_remove);
+}
+
+#[tokio::test]
+async fn test_read_past_eof_lenient() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"12345").await;
+    let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+    assert!(vfs.read(&handle, 5, 10).await.unwrap().is_empty());
+    assert!(vfs.read(&handle, 100, 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_read_past_eof_strict() {
+    let vfs = VfsMem::new().with_strict_offsets();
+    write_file(&vfs, "/f", b"12345").await;
+    let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+    assert_eq!(vfs.read(&handle, 3, 10).await.unwrap(), b"45");
+    assert!(vfs.read(&handle, 5, 10).await.unwrap().is_empty());
+    assert!(matches!(
+        vfs.read(&handle, 6, 10).await,
+        Err(VfsError::BadOffset)
+    ));
+    assert!(matches!(
+        vfs.read(&handle, 100, 10).await,
+        Err(VfsError::BadOffset)
+    ));
+}
+
+#[tokio::test]
+async fn test_read_huge_count() {
+    for vfs in [VfsMem::new(), VfsMem::new().with_dedup()] {
+        write_file(&vfs, "/f", b"12345").await;
+        let handle = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+
+        assert_eq!(vfs.read(&handle, 0, usize::MAX).await.unwrap(), b"12345");
+        assert_eq!(vfs.read(&handle, 4, usize::MAX).await.unwrap(), b"5");
+        assert!(vfs.read(&handle, 5, usize::MAX).await.unwrap().is_empty());
+        assert!(vfs.read(&handle, u64::MAX, usize::MAX).await.unwrap().is_empty());
+        assert!(vfs.read(&handle, usize::MAX as u64, 1).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_reserve_then_small_writes() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    vfs.reserve(&handle, 4096).await.unwrap();
+    assert_eq!(vfs.stat("/f").await.unwrap().size, 0);
+
+    let mut expected = Vec::new();
+    for i in 0..512u32 {
+        let chunk = i.to_le_bytes();
+        vfs.write(&handle, expected.len() as u64, &chunk).await.unwrap();
+        expected.extend_from_slice(&chunk);
+    }
+    assert_eq!(read_file(&vfs, "/f").await, expected);
+
+    assert!(matches!(
+        vfs.reserve(&handle, u64::MAX).await,
+        Err(VfsError::InvalidArgument(_))
+    ));
+    assert_eq!(read_file(&vfs, "/f").await, expected);
+}
+
+#[tokio::test]
+async fn test_write_end_overflow() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"12345").await;
+    let handle = vfs.open::<ReadWrite, File>("/f", 0).await.unwrap();
+    let version = vfs.stat("/f").await.unwrap().qid.version;
+
+    assert!(matches!(
+        vfs.write(&handle, usize::MAX as u64 - 2, b"abcd").await,
+        Err(VfsError::BadOffset)
+    ));
+    assert!(matches!(
+        vfs.write_vectored(&handle, usize::MAX as u64 - 4, &[b"ab", b"cd", b"ef"]).await,
+        Err(VfsError::BadOffset)
+    ));
+
+    let stat = vfs.stat("/f").await.unwrap();
+    assert_eq!(stat.size, 5);
+    assert_eq!(stat.qid.version, version);
+}
+
+#[tokio::test]
+async fn test_write_vectored() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/v", 0o644).await.unwrap();
+    vfs.write(&handle, 0, b"0123456789").await.unwrap();
+    let version = vfs.stat("/v").await.unwrap().qid.version;
+
+    let written = vfs
+        .write_vectored(&handle, 2, &[b"ab", b"", b"cdef", b"ghijkl"])
+        .await
+        .unwrap();
+    assert_eq!(written, 12);
+
+    assert_eq!(vfs.read(&handle, 0, 100).await.unwrap(), b"01abcdefghijkl");
+    assert_eq!(vfs.stat("/v").await.unwrap().qid.version, version + 1);
+}
+
+#[tokio::test]
+async fn test_open_clunk_events() {
+    let vfs = VfsMem::new();
+    let mut events = vfs.subscribe();
+
+    let created = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    let opened = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+    vfs.clunk(opened.fid).await.unwrap();
+    vfs.clunk(created.fid).await.unwrap();
+    // Failed calls emit nothing
+    assert!(vfs.clunk(created.fid).await.is_err());
+    assert!(vfs.open::<ReadOnly, File>("/missing", 0).await.is_err());
+
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        seen.push(event);
+    }
+    assert_eq!(
+        seen,
+        [
+            VfsEvent::Opened { path: "/f".into(), fid: created.fid },
+            VfsEvent::Opened { path: "/f".into(), fid: opened.fid },
+            VfsEvent::Clunked { fid: opened.fid },
+            VfsEvent::Clunked { fid: created.fid },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_rename_exchange_events() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"a").await;
+    write_file(&vfs, "/b", b"b").await;
+    let mut events = vfs.subscribe();
+
+    vfs.rename("/a", "/c").await.unwrap();
+    vfs.exchange("/b", "/c").await.unwrap();
+    // Failed calls and no-op swaps emit nothing
+    assert!(vfs.exchange("/b", "/missing").await.is_err());
+    vfs.exchange("/b", "/b").await.unwrap();
+
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        seen.push(event);
+    }
+    assert_eq!(
+        seen,
+        [
+            VfsEvent::Renamed { from: "/a".into(), to: "/c".into() },
+            VfsEvent::Exchanged { a: "/b".into(), b: "/c".into() },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_write_returning_qid() {
+    let vfs = VfsMem::new();
+    let mut handle = vfs.create::<ReadWrite, File>("/q", 0o644).await.unwrap();
+    let initial = handle.qid.version;
+
+    let first = vfs.write_returning_qid(&handle, 0, b"abc").await.unwrap();
+    assert_eq!(first.written, 3);
+    assert_eq!(first.qid.version, initial + 1);
+    assert_eq!(first.qid.path, handle.qid.path);
+    handle.qid = first.qid;
+
+    let second = vfs.write_returning_qid(&handle, 3, b"de").await.unwrap();
+    assert_eq!(second.written, 2);
+    assert_eq!(second.qid.version, first.qid.version + 1);
+    assert_eq!(vfs.stat("/q").await.unwrap().qid.version, second.qid.version);
+}
+
+#[tokio::test]
+async fn test_write_stream() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<WriteOnly, File>("/upload", 0o644).await.unwrap();
+
+    let chunks: Vec<VfsResult<Vec<u8>>> = vec![
+        Ok(b"first ".to_vec()),
+        Ok(b"second ".to_vec()),
+        Ok(b"third".to_vec()),
+    ];
+    let written = vfs.write_stream(&handle, futures::stream::iter(chunks)).await.unwrap();
+    assert_eq!(written, 18);
+    assert_eq!(read_file(&vfs, "/upload").await, b"first second third");
+
+    let handle = vfs.create::<WriteOnly, File>("/partial", 0o644).await.unwrap();
+    let chunks: Vec<VfsResult<&[u8]>> = vec![
+        Ok(b"kept"),
+        Err(VfsError::InvalidArgument("connection reset".into())),
+        Ok(b"never"),
+    ];
+    let result = vfs.write_stream(&handle, futures::stream::iter(chunks)).await;
+    assert!(matches!(result, Err(VfsError::InvalidArgument(_))));
+    assert_eq!(read_file(&vfs, "/partial").await, b"kept");
+}
+
+#[tokio::test]
+async fn test_write_cas() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/counter", 0o644).await.unwrap();
+    let first = handle.clone();
+    let second = handle.clone();
+
+    let seen = vfs.stat("/counter").await.unwrap().qid.version;
+    assert_eq!(seen, 0);
+
+    assert_eq!(vfs.write_cas(&first, 0, b"1", seen).await.unwrap(), 1);
+    assert_eq!(vfs.stat("/counter").await.unwrap().qid.version, 1);
+
+    assert!(matches!(
+        vfs.write_cas(&second, 0, b"2", seen).await,
+        Err(VfsError::Stale(p)) if p == "/counter"
+    ));
+    assert_eq!(vfs.read(&handle, 0, 10).await.unwrap(), b"1");
+
+    // Retrying with the fresh version succeeds
+    vfs.write_cas(&second, 0, b"2", 1).await.unwrap();
+    assert_eq!(vfs.read(&handle, 0, 10).await.unwrap(), b"2");
+}
+
+/// Spawning needs a `Send` future, so a compound method that held a node
+/// lock guard across an `.await` would fail to compile here
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_compound_methods_are_send() {
+    let vfs = VfsMem::new();
+
+    let v = vfs.clone();
+    tokio::spawn(async move {
+        v.create_dir_all("/a/b").await?;
+        v.write_all("/a/b/f", b"data").await?;
+        v.copy("/a/b/f", "/a/copy").await?;
+        v.rename("/a/copy", "/a/renamed").await?;
+        v.rename_opts("/a/renamed", "/a/b/f", true).await?;
+        v.move_into("/a/b/f", "/a").await?;
+        v.append_to("/a/log", b"line").await?;
+        v.link("/a/log", "/a/log-link").await?;
+        v.clone_file("/a/log", "/a/log-copy").await?;
+        v.create_many(&[("/c/d".to_string(), NodeKind::Dir, 0o755)]).await?;
+        let handle = v.create_with_ttl::<ReadWrite, File>("/tmp", 0o644, Duration::from_secs(60)).await?;
+        v.clunk(handle.fid).await?;
+        let handle = v.open_append("/a/log").await?;
+        v.clunk(handle.fid).await?;
+        v.walk_tree("/", |_, _| async { Ok(()) }).await?;
+        v.render_tree("/").await?;
+        v.transaction(|tx| async move { tx.remove::<File>("/a/log-copy").await }).await
+    })
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(read_file(&vfs, "/a/f").await, b"data");
+    assert!(!vfs.exists("/a/log-copy").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_transaction_rolls_back_on_error() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/keep", b"original").await;
+    let root_version = vfs.stat("/").await.unwrap().qid.version;
+
+    let result: Result<(), VfsError> = vfs
+        .transaction(|tx| async move {
+            tx.create::<ReadOnly, Dir>("/dir", 0o755).await?;
+            tx.create::<WriteOnly, File>("/dir/one", 0o644).await?;
+            let two = tx.create::<ReadWrite, File>("/two", 0o644).await?;
+            tx.write(&two, 0, b"data").await?;
+
+            let keep = tx.open::<ReadWrite, File>("/keep", 0).await?;
+            tx.write(&keep, 0, b"CHANGED").await?;
+            Err(VfsError::InvalidArgument("abort".into()))
+        })
+        .await;
+    assert!(matches!(result, Err(VfsError::InvalidArgument(_))));
+
+    assert!(vfs.stat("/dir").await.is_err());
+    assert!(vfs.stat("/dir/one").await.is_err());
+    assert!(vfs.stat("/two").await.is_err());
+    assert_eq!(read_file(&vfs, "/keep").await, b"original");
+    assert_eq!(vfs.stat("/").await.unwrap().qid.version, root_version);
+}
+
+#[tokio::test]
+async fn test_transaction_rollback_restores_removed_and_renamed() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+    write_file(&vfs, "/a/inner", b"inner").await;
+    write_file(&vfs, "/gone", b"gone").await;
+
+    let result: Result<(), VfsError> = vfs
+        .transaction(|tx| async move {
+            tx.remove::<File>("/gone").await?;
+            tx.rename("/a", "/b").await?;
+            Err(VfsError::BadOffset)
+        })
+        .await;
+    assert!(result.is_err());
+
+    assert_eq!(read_file(&vfs, "/gone").await, b"gone");
+    assert_eq!(read_file(&vfs, "/a/inner").await, b"inner");
+    assert!(vfs.stat("/b").await.is_err());
+    assert!(vfs.stat("/b/inner").await.is_err());
+}
+
+#[tokio::test]
+async fn test_transaction_rollback_keeps_hard_links() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"original").await;
+    vfs.link("/a", "/b").await.unwrap();
+
+    let result: Result<(), VfsError> = vfs
+        .transaction(|tx| async move {
+            let a = tx.open::<WriteOnly, File>("/a", OWRITE).await?;
+            tx.write(&a, 0, b"CHANGED!").await?;
+            Err(VfsError::BadOffset)
+        })
+        .await;
+    assert!(result.is_err());
+
+    assert_eq!(read_file(&vfs, "/b").await, b"original");
+    assert_eq!(vfs.link_count("/a").await.unwrap(), 2);
+    // Still one file: a write through either link shows through the other
+    vfs.put("/a", b"after").await.unwrap();
+    assert_eq!(read_file(&vfs, "/b").await, b"after");
+}
+
+#[tokio::test]
+async fn test_transaction_journals_only_on_commit() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-tx-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+
+    let result: Result<(), VfsError> = vfs
+        .transaction(|tx| async move {
+            let handle = tx.create::<ReadWrite, File>("/aborted", 0o644).await?;
+            tx.write(&handle, 0, b"gone").await?;
+            Err(VfsError::BadOffset)
+        })
+        .await;
+    assert!(result.is_err());
+    vfs.transaction(|tx| async move {
+        let handle = tx.create::<ReadWrite, File>("/committed", 0o644).await?;
+        tx.write(&handle, 0, b"kept").await
+    })
+    .await
+    .unwrap();
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+    assert!(!replayed.exists("/aborted").await.unwrap());
+    assert_eq!(read_file(&replayed, "/committed").await, b"kept");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_transaction_commits_on_success() {
+    let vfs = VfsMem::new();
+
+    let size = vfs
+        .transaction(|tx| async move {
+            let handle = tx.create::<ReadWrite, File>("/committed", 0o644).await?;
+            tx.write(&handle, 0, b"kept").await?;
+            Ok(tx.stat("/committed").await?.size)
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(size, 4);
+    assert_eq!(read_file(&vfs, "/committed").await, b"kept");
+}
+
+#[tokio::test]
+async fn test_read_to_string() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/text.txt", "héllo wörld\n".as_bytes()).await;
+    assert_eq!(vfs.read_to_string("/text.txt").await.unwrap(), "héllo wörld\n");
+
+    write_file(&vfs, "/binary", &[0x66, 0x6f, 0xff, 0xfe]).await;
+    assert!(matches!(
+        vfs.read_to_string("/binary").await,
+        Err(VfsError::InvalidArgument(_))
+    ));
+
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    assert!(matches!(
+        vfs.read_to_string("/dir").await,
+        Err(VfsError::IsADirectory(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_read_all_spans_chunks() {
+    let vfs = VfsMem::new();
+    let payload: Vec<u8> = (0..200_000).map(|i| (i % 256) as u8).collect();
+    write_file(&vfs, "/big", &payload).await;
+    assert_eq!(vfs.read_all("/big").await.unwrap(), payload);
+}
+
+#[tokio::test]
+async fn test_read_lines() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/log", b"first\nsecond line\r\n\nlast without newline").await;
+
+    let expected = vec!["first", "second line", "", "last without newline"];
+    let lines: Vec<String> = vfs
+        .read_lines("/log")
+        .map(|line| line.unwrap())
+        .collect()
+        .await;
+    assert_eq!(lines, expected);
+
+    // Lines spanning chunk boundaries are reassembled
+    let lines: Vec<String> = vfs
+        .read_lines_chunked("/log", 3)
+        .map(|line| line.unwrap())
+        .collect()
+        .await;
+    assert_eq!(lines, expected);
+
+    write_file(&vfs, "/terminated", b"a\nb\n").await;
+    let lines: Vec<String> = vfs
+        .read_lines("/terminated")
+        .map(|line| line.unwrap())
+        .collect()
+        .await;
+    assert_eq!(lines, vec!["a", "b"]);
+
+    let mut missing = Box::pin(vfs.read_lines("/missing"));
+    assert!(matches!(missing.next().await, Some(Err(VfsError::NotFound(_)))));
+    assert!(missing.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_kind() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"data").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    assert_eq!(vfs.kind("/file").await.unwrap(), NodeKind::File);
+    assert_eq!(vfs.kind("/dir").await.unwrap(), NodeKind::Dir);
+    assert_eq!(vfs.kind("/").await.unwrap(), NodeKind::Dir);
+    assert!(matches!(vfs.kind("/missing").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_move_into() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/src", 0o755).await.unwrap();
+    vfs.create::<ReadOnly, Dir>("/src/sub", 0o755).await.unwrap();
+    write_file(&vfs, "/src/sub/inner.txt", b"inner").await;
+    write_file(&vfs, "/note.txt", b"note").await;
+    vfs.create::<ReadOnly, Dir>("/dest", 0o755).await.unwrap();
+
+    let moved = vfs.move_into("/note.txt", "/dest").await.unwrap();
+    assert_eq!(moved, "/dest/note.txt");
+    assert_eq!(read_file(&vfs, "/dest/note.txt").await, b"note");
+
+    let moved = vfs.move_into("/src/sub", "/dest").await.unwrap();
+    assert_eq!(moved, "/dest/sub");
+    assert_eq!(read_file(&vfs, "/dest/sub/inner.txt").await, b"inner");
+    assert!(vfs.stat("/src/sub").await.is_err());
+
+    // Back to the root
+    assert_eq!(vfs.move_into("/dest/note.txt", "/").await.unwrap(), "/note.txt");
+}
+
+#[tokio::test]
+async fn test_move_into_errors() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a.txt", b"a").await;
+    write_file(&vfs, "/file", b"not a dir").await;
+    vfs.create::<ReadOnly, Dir>("/dest", 0o755).await.unwrap();
+    write_file(&vfs, "/dest/a.txt", b"taken").await;
+
+    assert!(matches!(
+        vfs.move_into("/a.txt", "/file").await,
+        Err(VfsError::NotADirectory(_))
+    ));
+    assert!(matches!(
+        vfs.move_into("/a.txt", "/dest").await,
+        Err(VfsError::AlreadyExists(_))
+    ));
+    assert_eq!(read_file(&vfs, "/dest/a.txt").await, b"taken");
+}
+
+#[tokio::test]
+async fn test_hard_link_shares_contents() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/original", b"v1").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    vfs.link("/original", "/dir/alias").await.unwrap();
+    assert_eq!(vfs.link_count("/original").await.unwrap(), 2);
+
+    let fh = vfs.open::<WriteOnly, File>("/dir/alias", 0).await.unwrap();
+    vfs.write(&fh, 0, b"v2 via alias").await.unwrap();
+    assert_eq!(read_file(&vfs, "/original").await, b"v2 via alias");
+    assert_eq!(
+        vfs.stat("/original").await.unwrap().qid.version,
+        vfs.stat("/dir/alias").await.unwrap().qid.version
+    );
+
+    vfs.remove::<File>("/original").await.unwrap();
+    assert_eq!(read_file(&vfs, "/dir/alias").await, b"v2 via alias");
+    assert_eq!(vfs.link_count("/dir/alias").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_link_count_ignores_open_handles() {
+    let vfs = VfsMem::new().with_unlinked_writes();
+    write_file(&vfs, "/a", b"data").await;
+    vfs.link("/a", "/b").await.unwrap();
+    let handle = vfs.open::<ReadOnly, File>("/a", 0).await.unwrap();
+
+    // The handle keeps the inode alive, but only paths count as links
+    vfs.remove::<File>("/a").await.unwrap();
+    assert_eq!(vfs.link_count("/b").await.unwrap(), 1);
+    assert_eq!(vfs.stat("/b").await.unwrap().nlink, 1);
+
+    vfs.link("/b", "/c").await.unwrap();
+    vfs.rename("/c", "/d").await.unwrap();
+    assert_eq!(vfs.link_count("/d").await.unwrap(), 2);
+    let fork = vfs.fork().unwrap();
+    assert_eq!(fork.link_count("/d").await.unwrap(), 2);
+    vfs.clone_file("/d", "/e").await.unwrap();
+    assert_eq!(vfs.link_count("/e").await.unwrap(), 1);
+    assert_eq!(vfs.link_count("/d").await.unwrap(), 2);
+    assert_eq!(vfs.read(&handle, 0, 4).await.unwrap(), b"data");
+}
+
+#[tokio::test]
+async fn test_hard_link_errors() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"data").await;
+    write_file(&vfs, "/taken", b"other").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    assert!(matches!(vfs.link("/dir", "/dir2").await, Err(VfsError::IsADirectory(_))));
+    assert!(matches!(vfs.link("/file", "/taken").await, Err(VfsError::AlreadyExists(_))));
+    assert!(matches!(vfs.link("/missing", "/new").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_max_open_handles() {
+    let vfs = VfsMem::new().with_max_open(3);
+    write_file(&vfs, "/file", b"data").await;
+
+    let mut handles = Vec::new();
+    for _ in 0..3 {
+        handles.push(vfs.open::<ReadOnly, File>("/file", 0).await.unwrap());
+    }
+    assert!(matches!(
+        vfs.open::<ReadOnly, File>("/file", 0).await,
+        Err(VfsError::TooManyOpenFiles)
+    ));
+    assert!(matches!(
+        vfs.create::<ReadWrite, File>("/other", 0o644).await,
+        Err(VfsError::TooManyOpenFiles)
+    ));
+    assert!(matches!(vfs.stat("/other").await, Err(VfsError::NotFound(_))));
+
+    vfs.clunk(handles.pop().unwrap().fid).await.unwrap();
+    vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_capped_truncating_open_changes_nothing() {
+    let vfs = VfsMem::new().with_max_open(1);
+    write_file(&vfs, "/file", b"data").await;
+    let version = vfs.stat("/file").await.unwrap().qid.version;
+    let held = vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+
+    assert!(matches!(
+        vfs.open::<WriteOnly, File>("/file", OWRITE | OTRUNC).await,
+        Err(VfsError::TooManyOpenFiles)
+    ));
+    assert_eq!(vfs.stat("/file").await.unwrap().qid.version, version);
+    assert_eq!(vfs.read(&held, 0, 10).await.unwrap(), b"data");
+}
+
+#[tokio::test]
+async fn test_fid_wraparound_skips_live_fids() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"data").await;
+    *vfs.fids.lock().unwrap() = FidTable::with_space(3);
+
+    let first = vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+    let second = vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+    let third = vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+    assert_eq!((first.fid, second.fid, third.fid), (1, 2, 3));
+
+    // Wrapping back around lands on 1 and 3 is still live, so 2 is next
+    vfs.clunk(second.fid).await.unwrap();
+    let reused = vfs.open::<ReadOnly, File>("/file", 0).await.unwrap();
+    assert_eq!(reused.fid, 2);
+
+    assert!(matches!(
+        vfs.open::<ReadOnly, File>("/file", 0).await,
+        Err(VfsError::TooManyOpenFiles)
+    ));
+    vfs.clunk(first.fid).await.unwrap();
+    assert_eq!(vfs.open::<ReadOnly, File>("/file", 0).await.unwrap().fid, 1);
+}
+
+#[tokio::test]
+async fn test_clunk_unknown_fid() {
+    let vfs = VfsMem::new();
+    let handle = vfs.create::<ReadWrite, File>("/file", 0o644).await.unwrap();
+
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(matches!(
+        vfs.clunk(handle.fid).await,
+        Err(VfsError::InvalidArgument(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_open_handles() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"a").await;
+    write_file(&vfs, "/b", b"b").await;
+    write_file(&vfs, "/c", b"c").await;
+
+    let a = vfs.open::<ReadOnly, File>("/a", 0).await.unwrap();
+    let b = vfs.open::<ReadWrite, File>("/b", ORDWR).await.unwrap();
+    let c = vfs.open::<ReadOnly, File>("/c", 0).await.unwrap();
+    vfs.clunk(b.fid).await.unwrap();
+
+    let handles = vfs.open_handles().unwrap();
+    let paths: Vec<&str> = handles.iter().map(|h| h.path.as_str()).collect();
+    assert_eq!(paths, ["/a", "/c"]);
+    assert_eq!(handles[0].fid, a.fid);
+    assert_eq!(handles[1].fid, c.fid);
+    assert_eq!(handles[1].mode, 0);
+
+    assert!(vfs.leaked_handles(Duration::from_secs(60)).unwrap().is_empty());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(vfs.leaked_handles(Duration::from_millis(10)).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_clone_file_shares_blocks() {
+    let vfs = VfsMem::new().with_dedup();
+    let payload: Vec<u8> = (0..1024 * 1024u32).map(|i| (i / 4096 + i) as u8).collect();
+    write_file(&vfs, "/big", &payload).await;
+    let blocks = vfs.block_count().unwrap();
+    assert!(blocks > 1);
+
+    vfs.clone_file("/big", "/big.clone").await.unwrap();
+    assert_eq!(vfs.block_count().unwrap(), blocks);
+    assert_eq!(read_file(&vfs, "/big.clone").await, payload);
+
+    let fh = vfs.open::<WriteOnly, File>("/big.clone", 0).await.unwrap();
+    vfs.write(&fh, 0, b"changed").await.unwrap();
+    assert_eq!(vfs.block_count().unwrap(), blocks + 1);
+    assert_eq!(read_file(&vfs, "/big").await, payload);
+    assert_eq!(&read_file(&vfs, "/big.clone").await[..7], b"changed");
+    assert_eq!(vfs.link_count("/big").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_clone_file_errors() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"data").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    assert!(matches!(vfs.clone_file("/dir", "/dir2").await, Err(VfsError::IsADirectory(_))));
+    assert!(matches!(vfs.clone_file("/file", "/dir").await, Err(VfsError::AlreadyExists(_))));
+    assert!(matches!(vfs.clone_file("/missing", "/x").await, Err(VfsError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_walk_path() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/c.txt", b"c").await;
+
+    let names: Vec<String> = ["a", "b", "c.txt"].iter().map(|s| s.to_string()).collect();
+    let by_names = vfs.walk("/", &names).await.unwrap();
+    let by_path = vfs.walk_path("/a/b/c.txt").await.unwrap();
+    assert_eq!(by_path.qids, by_names.qids);
+    assert_eq!(by_path.qids.len(), 3);
+
+    assert!(vfs.walk_path("/").await.unwrap().qids.is_empty());
+    assert_eq!(vfs.walk_path("a//b/").await.unwrap().qids.len(), 2);
+}
+
+#[tokio::test]
+async fn test_walk_from() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/c.txt", b"c").await;
+
+    let dir = vfs.open::<ReadOnly, Dir>("/a", 0).await.unwrap();
+    let names = vec!["b".to_string(), "c.txt".to_string()];
+    let relative = vfs.walk_from(&dir, &names).await.unwrap();
+    let absolute = vfs.walk("/a", &names).await.unwrap();
+    assert_eq!(relative.qids, absolute.qids);
+    assert_eq!(relative.qids.len(), 2);
+    assert_eq!(relative.qids[1], vfs.stat("/a/b/c.txt").await.unwrap().qid);
+}
+
+#[tokio::test]
+async fn test_walk_from_and_reopen_after_rename() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/c.txt", b"c").await;
+
+    let dir = vfs.open::<ReadOnly, Dir>("/a", 0).await.unwrap();
+    let reader = vfs.open::<ReadOnly, File>("/a/b/c.txt", 0).await.unwrap();
+    vfs.rename("/a", "/moved").await.unwrap();
+
+    let names = vec!["b".to_string(), "c.txt".to_string()];
+    assert_eq!(vfs.walk_from(&dir, &names).await.unwrap().qids.len(), 2);
+    let writer = vfs.reopen::<ReadWrite, _>(&reader, ORDWR).await.unwrap();
+    assert_eq!(writer.path, "/moved/b/c.txt");
+}
+
+#[tokio::test]
+async fn test_walk_strict() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/a/b").await.unwrap();
+    write_file(&vfs, "/a/b/c.txt", b"c").await;
+
+    let names: Vec<String> = ["a", "b", "c.txt"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(vfs.walk_strict("/", &names).await.unwrap().qids.len(), 3);
+
+    let names: Vec<String> = ["a", "missing", "c.txt"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(vfs.walk("/", &names).await.unwrap().qids.len(), 1);
+    assert!(matches!(
+        vfs.walk_strict("/", &names).await,
+        Err(VfsError::NotFound(p)) if p == "/a/missing"
+    ));
+
+    let names = vec!["b".to_string(), "nope".to_string()];
+    assert!(matches!(
+        vfs.walk_strict("/a", &names).await,
+        Err(VfsError::NotFound(p)) if p == "/a/b/nope"
+    ));
+}
+
+#[tokio::test]
+async fn test_readdir_ignores_prefix_siblings() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/foo").await.unwrap();
+    vfs.create_dir_all("/foobar").await.unwrap();
+    write_file(&vfs, "/foo/a", b"a").await;
+    write_file(&vfs, "/foobar/b", b"b").await;
+
+    let dir = vfs.open::<ReadOnly, Dir>("/foo", 0).await.unwrap();
+    let names: Vec<String> = vfs.readdir(&dir).await.unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names, ["a"]);
+
+    let paths: Vec<String> = vfs.readdir_recursive("/foo").await.unwrap().into_iter().map(|(p, _)| p).collect();
+    assert_eq!(paths, ["/foo/a"]);
+    assert_eq!(vfs.stat("/foo").await.unwrap().nlink, 1);
+}
+
+#[tokio::test]
+async fn test_is_empty_dir() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/empty").await.unwrap();
+    vfs.create_dir_all("/full").await.unwrap();
+    write_file(&vfs, "/full/f", b"x").await;
+
+    assert!(vfs.is_empty_dir("/empty").await.unwrap());
+    assert!(!vfs.is_empty_dir("/full").await.unwrap());
+    assert!(!vfs.is_empty_dir("/").await.unwrap());
+    assert!(matches!(
+        vfs.is_empty_dir("/full/f").await,
+        Err(VfsError::NotADirectory(p)) if p == "/full/f"
+    ));
+    assert!(matches!(vfs.is_empty_dir("/nope").await, Err(VfsError::NotFound(_))));
+
+    vfs.remove::<File>("/full/f").await.unwrap();
+    assert!(vfs.is_empty_dir("/full").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_readdir_recursive() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/top/mid/low").await.unwrap();
+    write_file(&vfs, "/top/a.txt", b"a").await;
+    write_file(&vfs, "/top/mid/b.txt", b"bb").await;
+    write_file(&vfs, "/top/mid/low/c.txt", b"ccc").await;
+    write_file(&vfs, "/outside.txt", b"x").await;
+
+    let entries = vfs.readdir_recursive("/top").await.unwrap();
+    let paths: Vec<&str> = entries.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec!["/top/a.txt", "/top/mid", "/top/mid/b.txt", "/top/mid/low", "/top/mid/low/c.txt"]
+    );
+    assert_eq!(entries[4].1.name, "c.txt");
+    assert_eq!(entries[4].1.size, 3);
+
+    assert_eq!(vfs.readdir_recursive("/").await.unwrap().len(), 7);
+    assert!(matches!(
+        vfs.readdir_recursive("/top/a.txt").await,
+        Err(VfsError::NotADirectory(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_walk_tree() {
+    let vfs = VfsMem::new();
+    vfs.create_dir_all("/top/mid/low").await.unwrap();
+    write_file(&vfs, "/top/a.txt", b"a").await;
+    write_file(&vfs, "/top/mid/b.txt", b"bb").await;
+    write_file(&vfs, "/top/mid/low/c.txt", b"ccc").await;
+    write_file(&vfs, "/top/z.txt", b"z").await;
+
+    let mut visited = Vec::new();
+    let mut files = 0;
+    vfs.walk_tree("/top", |path, stat| {
+        visited.push(path.to_string());
+        if stat.is_file() {
+            files += 1;
+        }
+        async { Ok(()) }
+    })
+    .await
+    .unwrap();
+    assert_eq!(files, 4);
+    assert_eq!(
+        visited,
+        ["/top/a.txt", "/top/mid", "/top/mid/b.txt", "/top/mid/low", "/top/mid/low/c.txt", "/top/z.txt"]
+    );
+
+    let mut visited = Vec::new();
+    let result = vfs
+        .walk_tree("/top", |path, _| {
+            visited.push(path.to_string());
+            let stop = path == "/top/mid/b.txt";
+            async move {
+                if stop {
+                    return Err(VfsError::InvalidArgument("stop".into()));
+                }
+                Ok(())
+            }
+        })
+        .await;
+    assert!(matches!(result, Err(VfsError::InvalidArgument(msg)) if msg == "stop"));
+    assert_eq!(visited, ["/top/a.txt", "/top/mid", "/top/mid/b.txt"]);
+
+    assert!(matches!(
+        vfs.walk_tree("/top/a.txt", |_, _| async { Ok(()) }).await,
+        Err(VfsError::NotADirectory(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_default_owner_and_chown() {
+    let vfs = VfsMem::new().with_default_owner("alice", "staff");
+    write_file(&vfs, "/file", b"data").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    let stat = vfs.stat("/file").await.unwrap();
+    assert_eq!((stat.uid.as_str(), stat.gid.as_str()), ("alice", "staff"));
+    assert_eq!(vfs.stat("/dir").await.unwrap().uid, "alice");
+
+    vfs.chown("/file", "bob", "wheel").await.unwrap();
+    let stat = vfs.stat("/file").await.unwrap();
+    assert_eq!((stat.uid.as_str(), stat.gid.as_str()), ("bob", "wheel"));
+
+    vfs.chown("/dir", "carol", "staff").await.unwrap();
+    assert_eq!(vfs.stat("/dir").await.unwrap().uid, "carol");
+
+    assert!(matches!(
+        vfs.chown("/missing", "bob", "wheel").await,
+        Err(VfsError::NotFound(_))
+    ));
+    assert_eq!(VfsMem::new().stat("/").await.unwrap().uid, "user");
+}
+
+#[tokio::test]
+async fn test_open_otrunc_empties_file() {
+    for vfs in [VfsMem::new(), VfsMem::new().with_dedup()] {
+        let payload = vec![3u8; 10_000];
+        write_file(&vfs, "/file", &payload).await;
+        let before = vfs.stat("/file").await.unwrap().qid.version;
+
+        let fh = vfs.open::<WriteOnly, File>("/file", OWRITE | OTRUNC).await.unwrap();
+        let stat = vfs.stat("/file").await.unwrap();
+        assert_eq!(stat.size, 0);
+        assert!(stat.qid.version > before);
+
+        vfs.write(&fh, 0, b"fresh").await.unwrap();
+        assert_eq!(read_file(&vfs, "/file").await, b"fresh");
+    }
+}
+
+#[tokio::test]
+async fn test_orclose_removes_on_clunk() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/tmp", b"scratch").await;
+
+    let handle = vfs.open::<ReadWrite, File>("/tmp", ORDWR | ORCLOSE).await.unwrap();
+    vfs.write(&handle, 0, b"more").await.unwrap();
+    assert!(vfs.exists("/tmp").await.unwrap());
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(!vfs.exists("/tmp").await.unwrap());
+
+    // Already gone is fine
+    write_file(&vfs, "/gone", b"x").await;
+    let handle = vfs.open::<ReadOnly, File>("/gone", ORCLOSE).await.unwrap();
+    vfs.remove::<File>("/gone").await.unwrap();
+    vfs.clunk(handle.fid).await.unwrap();
+
+    // A non-empty directory stays, but the fid is released
+    vfs.create_dir_all("/dir").await.unwrap();
+    write_file(&vfs, "/dir/f", b"x").await;
+    let handle = vfs.open::<ReadOnly, Dir>("/dir", ORCLOSE).await.unwrap();
+    assert!(matches!(vfs.clunk(handle.fid).await, Err(VfsError::DirectoryNotEmpty(_))));
+    assert!(vfs.exists("/dir/f").await.unwrap());
+    assert!(vfs.open_handles().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_orclose_on_create() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-orclose-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+
+    let handle = vfs.create::<ReadWrite, File>("/tmp", ORDWR | ORCLOSE).await.unwrap();
+    vfs.write(&handle, 0, b"scratch").await.unwrap();
+    assert!(vfs.exists("/tmp").await.unwrap());
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(!vfs.exists("/tmp").await.unwrap());
+
+    // Permission bits that overlap ORCLOSE are not open flags
+    let handle = vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    vfs.clunk(handle.fid).await.unwrap();
+    assert!(vfs.exists("/dir").await.unwrap());
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_orclose_spares_file_renamed_over_it() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/a", b"source").await;
+    write_file(&vfs, "/b", b"target").await;
+
+    let handle = vfs.open::<ReadOnly, File>("/b", ORCLOSE).await.unwrap();
+    vfs.rename_opts("/a", "/b", true).await.unwrap();
+    vfs.clunk(handle.fid).await.unwrap();
+
+    // ORCLOSE applied to the replaced node, not the one renamed in
+    assert_eq!(read_file(&vfs, "/b").await, b"source");
+}
+
+#[tokio::test]
+async fn test_reopen_for_writing() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/f", b"hello").await;
+
+    let reader = vfs.open::<ReadOnly, File>("/f", 0).await.unwrap();
+    let writer = vfs.reopen::<ReadWrite, _>(&reader, ORDWR).await.unwrap();
+    assert_ne!(writer.fid, reader.fid);
+    assert_eq!(writer.path, "/f");
+
+    vfs.write(&writer, 0, b"HE").await.unwrap();
+    assert_eq!(vfs.read(&reader, 0, 5).await.unwrap(), b"HEllo");
+
+    // The new mode is checked like a fresh open
+    assert!(vfs.reopen::<ReadOnly, _>(&writer, OWRITE).await.is_err());
+    vfs.remove::<File>("/f").await.unwrap();
+    assert!(matches!(
+        vfs.reopen::<ReadWrite, _>(&reader, ORDWR).await,
+        Err(VfsError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_open_truncating() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/keep", b"0123456789").await;
+    write_file(&vfs, "/fresh", b"0123456789").await;
+
+    let fh = vfs.open::<WriteOnly, File>("/keep", OWRITE).await.unwrap();
+    vfs.write(&fh, 0, b"abc").await.unwrap();
+    assert_eq!(read_file(&vfs, "/keep").await, b"abc3456789");
+
+    let fh = vfs.open_truncating::<WriteOnly>("/fresh").await.unwrap();
+    vfs.write(&fh, 0, b"abc").await.unwrap();
+    assert_eq!(read_file(&vfs, "/fresh").await, b"abc");
+
+    let fh = vfs.open_truncating::<ReadWrite>("/keep").await.unwrap();
+    assert_eq!(fh.mode, ORDWR | OTRUNC);
+    assert!(vfs.read(&fh, 0, 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_open_mode_must_match_marker() {
+    let vfs = VfsMem::new();
+    write_file(&vfs, "/file", b"keep").await;
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+
+    assert!(matches!(
+        vfs.open::<ReadOnly, File>("/file", OWRITE).await,
+        Err(VfsError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        vfs.open::<ReadOnly, File>("/file", OTRUNC).await,
+        Err(VfsError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        vfs.open::<WriteOnly, File>("/file", ORDWR).await,
+        Err(VfsError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        vfs.open::<ReadWrite, Dir>("/dir", ORDWR).await,
+        Err(VfsError::IsADirectory(_))
+    ));
+    assert_eq!(read_file(&vfs, "/file").await, b"keep");
+    vfs.open::<ReadWrite, File>("/file", ORDWR).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sparse_write_allocates_only_data() {
+    let vfs = VfsMem::new();
+    let far = 1u64 << 30;
+    let fh = vfs.create::<ReadWrite, File>("/sparse", 0o644).await.unwrap();
+    vfs.write(&fh, 0, b"head").await.unwrap();
+    vfs.write(&fh, far, b"x").await.unwrap();
+
+    assert_eq!(vfs.stat("/sparse").await.unwrap().size, far + 1);
+    assert!(vfs.is_sparse("/sparse").await.unwrap());
+    let usage = vfs.statfs().await.unwrap();
+    assert_eq!(usage.logical_bytes, far + 1);
+    assert_eq!(usage.allocated_bytes, 5);
+
+    assert_eq!(vfs.read(&fh, 0, 6).await.unwrap(), b"head\0\0");
+    assert_eq!(vfs.read(&fh, far - 2, 10).await.unwrap(), b"\0\0x");
+}
+
+#[tokio::test]
+async fn test_sparse_writes_merge_extents() {
+    let vfs = VfsMem::new();
+    let fh = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+    let mut model = vec![0u8; 40_000];
+
+    for (offset, len, byte) in [(20_000, 100, 1), (30_000, 50, 2), (20_050, 10_000, 3), (39_990, 10, 4)] {
+        let data = vec![byte; len];
+        vfs.write(&fh, offset as u64, &data).await.unwrap();
+        model[offset..offset + len].copy_from_slice(&data);
+    }
+
+    assert_eq!(vfs.read(&fh, 0, model.len()).await.unwrap(), model);
+    assert!(vfs.is_sparse("/f").await.unwrap());
+    assert_eq!(vfs.statfs().await.unwrap().allocated_bytes, 10_050 + 10);
+
+    // Filling the hole in the front leaves nothing sparse
+    vfs.write(&fh, 0, &[0u8; 20_000]).await.unwrap();
+    vfs.write(&fh, 30_050, &[0u8; 9_940]).await.unwrap();
+    assert!(!vfs.is_sparse("/f").await.unwrap());
+    assert_eq!(vfs.read(&fh, 0, model.len()).await.unwrap(), model);
+}
+
+#[tokio::test]
+async fn test_statfs_counts() {
+    let vfs = VfsMem::new();
+    vfs.create::<ReadOnly, Dir>("/dir", 0o755).await.unwrap();
+    write_file(&vfs, "/dir/a", b"abc").await;
+    write_file(&vfs, "/b", b"de").await;
+
+    let usage = vfs.statfs().await.unwrap();
+    assert_eq!((usage.files, usage.dirs), (2, 2));
+    assert_eq!(usage.logical_bytes, 5);
+    assert_eq!(usage.allocated_bytes, 5);
+    assert!(!vfs.is_sparse("/b").await.unwrap());
+    assert!(matches!(vfs.is_sparse("/dir").await, Err(VfsError::IsADirectory(_))));
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn test_compression_round_trip() {
+    let vfs = VfsMem::new().with_compression();
+    let payload = b"all work and no play makes jack a dull boy\n".repeat(2_000);
+    write_file(&vfs, "/log", &payload).await;
+
+    assert_eq!(read_file(&vfs, "/log").await, payload);
+    assert_eq!(vfs.stat("/log").await.unwrap().size, payload.len() as u64);
+    let usage = vfs.statfs().await.unwrap();
+    assert_eq!(usage.logical_bytes, payload.len() as u64);
+    assert!(usage.allocated_bytes < payload.len() as u64 / 10);
+
+    // Partial overwrite and truncate go through the compressed form too
+    let fh = vfs.open::<ReadWrite, File>("/log", 0).await.unwrap();
+    vfs.write(&fh, 4, b"WORK").await.unwrap();
+    assert_eq!(vfs.read(&fh, 0, 12).await.unwrap(), b"all WORK and");
+    vfs.open::<WriteOnly, File>("/log", OWRITE | OTRUNC).await.unwrap();
+    assert_eq!(vfs.stat("/log").await.unwrap().size, 0);
+}
+
+#[tokio::test]
+async fn test_journal_replay() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+    vfs.create::<ReadOnly, Dir>("/docs", 0o755).await.unwrap();
+    write_file(&vfs, "/docs/a.txt", b"first draft").await;
+    write_file(&vfs, "/docs/b.txt", b"scratch").await;
+    vfs.write_vectored(
+        &vfs.open::<WriteOnly, File>("/docs/a.txt", 0).await.unwrap(),
+        6,
+        &[b"fin", b"al!"],
+    )
+    .await
+    .unwrap();
+    vfs.rename("/docs/b.txt", "/docs/c.txt").await.unwrap();
+    write_file(&vfs, "/gone", b"x").await;
+    vfs.remove::<File>("/gone").await.unwrap();
+    write_file(&vfs, "/emptied", b"content").await;
+    vfs.open::<WriteOnly, File>("/emptied", OWRITE | OTRUNC).await.unwrap();
+    // Rejected operations leave no record
+    assert!(vfs.create::<ReadOnly, Dir>("/docs", 0o755).await.is_err());
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert!(vfs.diff(&replayed).unwrap().is_empty());
+    assert_eq!(read_file(&replayed, "/docs/a.txt").await, b"first final!");
+
+    // A record torn by a crash ends the replay without an error
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"op\":\"remove\",\"pa")
+        .unwrap();
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert!(vfs.diff(&replayed).unwrap().is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_journal_replays_links_clones_and_owners() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-links-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+    write_file(&vfs, "/a", b"shared").await;
+    vfs.link("/a", "/b").await.unwrap();
+    vfs.clone_file("/a", "/c").await.unwrap();
+    vfs.chown("/c", "alice", "staff").await.unwrap();
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert_eq!(vfs.diff(&replayed).unwrap(), []);
+    assert_eq!(replayed.link_count("/b").await.unwrap(), 2);
+    assert_eq!(replayed.link_count("/c").await.unwrap(), 1);
+    let owner = replayed.stat("/c").await.unwrap();
+    assert_eq!((owner.uid.as_str(), owner.gid.as_str()), ("alice", "staff"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_journal_replay_reports_failed_record() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-bad-{}.log", std::process::id()));
+    std::fs::write(
+        &path,
+        concat!(
+            "{\"op\":\"create\",\"path\":\"/a\",\"kind\":\"File\",\"mode\":0}\n",
+            "{\"op\":\"remove\",\"path\":\"/missing\"}\n",
+        ),
+    )
+    .unwrap();
+
+    let Err(VfsError::ReplayFailed { index, source }) = VfsMem::replay(&path).await else {
+        panic!("replay must fail on the second record");
+    };
+    assert_eq!(index, 1);
+    assert!(matches!(*source, VfsError::NotFound(_)));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_journal_replays_clear() {
+    let path = std::env::temp_dir().join(format!("bulkhead-journal-clear-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let vfs = VfsMem::new().with_journal(path.clone()).unwrap();
+    write_file(&vfs, "/before", b"x").await;
+    vfs.clear().unwrap();
+    write_file(&vfs, "/after", b"y").await;
+
+    let replayed = VfsMem::replay(&path).await.unwrap();
+    assert!(!replayed.exists("/before").await.unwrap());
+    assert_eq!(read_file(&replayed, "/after").await, b"y");
+
+    std::fs::remove_file(&path).unwrap();
+}
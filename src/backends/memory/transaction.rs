@@ -0,0 +1,220 @@
+//! Transactional grouping of mutations with rollback.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::backend::VfsBackend;
+use crate::error::VfsResult;
+use crate::types::{Access, File, FileHandle, NodeType, Stat};
+use crate::{CanRead, CanWrite};
+
+use super::fs::{VfsMem, parent_path};
+use super::journal::Journal;
+use super::node::{Inode, Node, SharedInode};
+
+/// What was at a path before a transaction first touched it
+#[derive(Debug)]
+enum Prior {
+    Absent,
+    Dir(Node),
+    /// The file's own inode, so hard links stay shared after a rollback
+    File {
+        inode: SharedInode,
+        expires: Option<SystemTime>,
+    },
+}
+
+/// Prior state of everything a transaction touched
+#[derive(Debug, Default)]
+struct UndoLog {
+    paths: HashMap<String, Prior>,
+    /// Each inode's contents when first seen, restored in place so every
+    /// link to it sees the rollback
+    inodes: Vec<(SharedInode, Inode)>,
+}
+
+impl UndoLog {
+    /// Remember `node` as the prior state of `path`, unless already known
+    fn record(&mut self, path: &str, node: Option<&Node>) -> VfsResult<()> {
+        if self.paths.contains_key(path) {
+            return Ok(());
+        }
+        let prior = match node {
+            None => Prior::Absent,
+            Some(Node::File { inode, expires }) => {
+                if !self.inodes.iter().any(|(seen, _)| Arc::ptr_eq(seen, inode)) {
+                    let contents = inode.read()?.clone();
+                    self.inodes.push((inode.clone(), contents));
+                }
+                Prior::File {
+                    inode: inode.clone(),
+                    expires: *expires,
+                }
+            }
+            Some(dir) => Prior::Dir(dir.clone()),
+        };
+        self.paths.insert(path.to_string(), prior);
+        Ok(())
+    }
+}
+
+/// Handle passed to a transaction closure.
+///
+/// Every mutation made through it records the prior state of the paths it
+/// touches, so the transaction can be rolled back if the closure fails.
+#[derive(Debug)]
+pub struct TxContext {
+    vfs: VfsMem,
+    undo: Arc<Mutex<UndoLog>>,
+}
+
+impl TxContext {
+    /// Record the current state of `paths` and their subtrees, once per path
+    fn record(&self, paths: &[&str]) -> VfsResult<()> {
+        let nodes = self.vfs.read_nodes("transaction", "/")?;
+        let mut undo = self.undo.lock()?;
+
+        for path in paths {
+            let prefix = format!("{}/", path);
+            undo.record(path, nodes.get(path))?;
+            for (child, node) in nodes.iter().filter(|(p, _)| p.starts_with(&prefix)) {
+                undo.record(child, Some(node))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a file or directory
+    pub async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        let path = self.vfs.resolve_path(path)?;
+        self.record(&[&path, parent_path(&path)])?;
+        self.vfs.create::<M, T>(&path, mode).await
+    }
+
+    /// Write to an open file
+    pub async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        self.record(&[&self.vfs.handle_path(handle)?])?;
+        self.vfs.write(handle, offset, data).await
+    }
+
+    /// Remove a file or empty directory
+    pub async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        let path = self.vfs.resolve_path(path)?;
+        self.record(&[&path, parent_path(&path)])?;
+        self.vfs.remove::<T>(&path).await
+    }
+
+    /// Rename a path, failing if the target exists
+    pub async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        let from = self.vfs.resolve_path(from)?;
+        let to = self.vfs.resolve_path(to)?;
+        self.record(&[&from, parent_path(&from), &to, parent_path(&to)])?;
+        self.vfs.rename(&from, &to).await
+    }
+
+    /// Open an existing file or directory
+    pub async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        self.vfs.open::<M, T>(path, mode).await
+    }
+
+    /// Read from an open file
+    pub async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        self.vfs.read(handle, offset, count).await
+    }
+
+    /// Get metadata for a path
+    pub async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        self.vfs.stat(path).await
+    }
+}
+
+/// Put every recorded path back into its prior state
+fn rollback(vfs: &VfsMem, undo: UndoLog) -> VfsResult<()> {
+    let mut nodes = vfs.write_nodes("rollback", "/")?;
+
+    for (shared, contents) in undo.inodes {
+        let mut inode = shared.write()?;
+        // Links are counted by the node map, which the loop below restores
+        let links = std::mem::take(&mut inode.links);
+        *inode = contents;
+        inode.links = links;
+    }
+    for (path, prior) in undo.paths {
+        match prior {
+            Prior::Dir(node) => {
+                nodes.insert(path, node);
+            }
+            Prior::File { inode, expires } => {
+                nodes.insert(path, Node::File { inode, expires });
+            }
+            Prior::Absent => {
+                // Anything created below a path that didn't exist goes too
+                let prefix = format!("{}/", path);
+                nodes.retain(|p, _| *p != path && !p.starts_with(&prefix));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl VfsMem {
+    /// Run `f` as a transaction, undoing its mutations if it returns `Err`.
+    ///
+    /// Only mutations made through the [`TxContext`] are tracked. The
+    /// transaction isolates nothing: concurrent operations can observe its
+    /// intermediate state, and rollback overwrites touched paths wholesale.
+    /// A touched file's contents are restored in place, so its hard links
+    /// roll back with it. With a journal, the transaction's records are
+    /// written only once `f` succeeds.
+    pub async fn transaction<F, Fut, R>(&self, f: F) -> VfsResult<R>
+    where
+        F: FnOnce(TxContext) -> Fut,
+        Fut: Future<Output = VfsResult<R>>,
+    {
+        let undo = Arc::new(Mutex::new(UndoLog::default()));
+        let mut vfs = self.clone();
+        vfs.journal = self
+            .journal
+            .as_ref()
+            .map(|journal| Arc::new(Journal::buffered(journal.clone())));
+        let journal = vfs.journal.clone();
+        let tx = TxContext {
+            vfs,
+            undo: undo.clone(),
+        };
+
+        match f(tx).await {
+            Ok(result) => {
+                if let Some(journal) = journal {
+                    journal.commit()?;
+                }
+                Ok(result)
+            }
+            Err(err) => {
+                let undo = std::mem::take(&mut *undo.lock()?);
+                rollback(self, undo)?;
+                Err(err)
+            }
+        }
+    }
+}
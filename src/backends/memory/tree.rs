@@ -0,0 +1,166 @@
+//! Hierarchical views of the in-memory filesystem.
+
+use std::time::SystemTime;
+
+use base64::Engine;
+use serde_json::{Map, Value, json};
+
+use crate::error::{VfsError, VfsResult};
+
+use super::fs::{VfsMem, join_path};
+use super::index::NodeMap;
+use super::node::Node;
+
+/// JSON metadata object describing a file
+fn file_json(node: &Node, include_contents: bool) -> Value {
+    let mtime = node
+        .mtime()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut value = json!({
+        "size": node.size(),
+        "mode": node.mode(),
+        "mtime": mtime,
+    });
+
+    if include_contents && let Some(inode) = node.inode() {
+        value["contents"] =
+            Value::String(base64::engine::general_purpose::STANDARD.encode(inode.data.to_vec()));
+    }
+    value
+}
+
+impl VfsMem {
+    /// Dump the subtree under `root` as nested JSON.
+    ///
+    /// Directories are objects mapping child names to their own objects;
+    /// files are `{ "size", "mode", "mtime" }` objects with `mtime` in
+    /// milliseconds since the Unix epoch.
+    pub fn to_json_tree(&self, root: &str) -> VfsResult<Value> {
+        self.to_json_tree_with(root, false)
+    }
+
+    /// Like [`to_json_tree`](Self::to_json_tree), optionally adding each
+    /// file's contents base64-encoded under `"contents"`.
+    pub fn to_json_tree_with(&self, root: &str, include_contents: bool) -> VfsResult<Value> {
+        let root = self.resolve_path(root)?;
+        let nodes = self.read_nodes("to_json_tree", &root)?;
+
+        let root_node = nodes
+            .get(&root)
+            .ok_or_else(|| VfsError::NotFound(root.clone()))?;
+        if root_node.is_file() {
+            return Ok(file_json(root_node, include_contents));
+        }
+
+        let prefix = if root == "/" {
+            "/".to_string()
+        } else {
+            format!("{}/", root)
+        };
+        let mut descendants: Vec<(&str, &Node)> = nodes
+            .iter()
+            .filter_map(|(path, node)| {
+                path.strip_prefix(&prefix)
+                    .filter(|rel| !rel.is_empty())
+                    .map(|rel| (rel, node))
+            })
+            .collect();
+        // Sorting puts every directory before its contents
+        descendants.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut tree = Map::new();
+        for (rel, node) in descendants {
+            let (parents, name) = match rel.rsplit_once('/') {
+                Some((parents, name)) => (Some(parents), name),
+                None => (None, rel),
+            };
+
+            let mut cursor = &mut tree;
+            for component in parents.into_iter().flat_map(|p| p.split('/')) {
+                cursor = cursor
+                    .entry(component)
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                    .ok_or_else(|| VfsError::NotADirectory(component.to_string()))?;
+            }
+
+            let value = match node {
+                Node::File { .. } => file_json(node, include_contents),
+                Node::Dir { .. } => Value::Object(Map::new()),
+            };
+            cursor.entry(name).or_insert(value);
+        }
+
+        Ok(Value::Object(tree))
+    }
+
+    /// Render the subtree under `root` like the `tree` command.
+    ///
+    /// The first line is `root` itself. In each directory, subdirectories
+    /// come before files and each group is sorted by name; directory names
+    /// end in `/` and files show their size in bytes. A file `root` renders
+    /// as that one line.
+    pub async fn render_tree(&self, root: &str) -> VfsResult<String> {
+        let root = self.resolve_path(root)?;
+        let nodes = self.read_nodes("render_tree", &root)?;
+        let node = self.lookup(&nodes, &root).ok_or_else(|| VfsError::NotFound(root.clone()))?;
+
+        if node.is_file() {
+            let name = root.rsplit('/').next().unwrap_or(&root);
+            return Ok(format!("{} ({} bytes)\n", name, node.size()));
+        }
+
+        let mut out = format!("{}\n", root);
+        self.render_children(&nodes, &root, &mut out);
+        Ok(out)
+    }
+
+    /// Append the entries of `dir` and everything below it to `out`.
+    ///
+    /// Walks with an explicit stack rather than recursion, so a very deep
+    /// tree costs heap, not call stack.
+    fn render_children(&self, nodes: &NodeMap, dir: &str, out: &mut String) {
+        // Each entry is a line to print: path, name, and the indent before it
+        let mut pending = self.render_entries(nodes, dir, "");
+        while let Some((path, name, indent, last)) = pending.pop() {
+            let Some(node) = self.lookup(nodes, &path) else { continue };
+            let (branch, continuation) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            if node.is_dir() {
+                out.push_str(&format!("{}{}{}/\n", indent, branch, name));
+                let indent = format!("{}{}", indent, continuation);
+                pending.extend(self.render_entries(nodes, &path, &indent));
+            } else {
+                out.push_str(&format!("{}{}{} ({} bytes)\n", indent, branch, name, node.size()));
+            }
+        }
+    }
+
+    /// Entries of `dir` in reverse display order, ready to be popped
+    fn render_entries(
+        &self,
+        nodes: &NodeMap,
+        dir: &str,
+        indent: &str,
+    ) -> Vec<(String, String, String, bool)> {
+        let mut entries: Vec<(&String, &Node)> = nodes
+            .children(dir)
+            .filter_map(|name| Some((name, self.lookup(nodes, &join_path(dir, name))?)))
+            .collect();
+        // Names come sorted, and the sort is stable
+        entries.sort_by_key(|(_, node)| node.is_file());
+
+        let count = entries.len();
+        entries
+            .into_iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (name, _))| (join_path(dir, name), name.clone(), indent.to_string(), i + 1 == count))
+            .collect()
+    }
+}
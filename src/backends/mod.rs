@@ -1,4 +1,17 @@
-
+pub mod cache;
+#[cfg(feature = "crypto")]
+pub mod crypto;
 pub mod memory;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod ratelimit;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod timeout;
 
+pub use cache::CacheBackend;
+#[cfg(feature = "crypto")]
+pub use crypto::EncryptedBackend;
 pub use memory::VfsMem;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub use ratelimit::RateLimitBackend;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub use timeout::TimeoutBackend;
@@ -0,0 +1,254 @@
+//! Rate-limiting backend decorator.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::VfsBackend;
+use crate::error::VfsResult;
+use crate::runtime;
+use crate::types::{Access, Dir, DirEntry, File, FileHandle, NodeKind, NodeType, Stat, WalkResult};
+use crate::{CanRead, CanWrite};
+
+/// Token bucket refilled continuously by elapsed time
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    rate: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(ops_per_sec: u32) -> Self {
+        let capacity = ops_per_sec as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            rate: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Add the tokens accrued since the last refill
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Take a token, or return how long to wait until one is available
+    fn try_take(&mut self) -> Result<(), Duration> {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// Backend wrapper that throttles every operation to `ops_per_sec`.
+///
+/// Bursts of up to `ops_per_sec` operations are served immediately; after
+/// that each operation waits for a token to refill.
+#[derive(Debug)]
+pub struct RateLimitBackend<B> {
+    inner: B,
+    ops_per_sec: u32,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl<B> RateLimitBackend<B> {
+    /// Wrap `inner`, allowing at most `ops_per_sec` operations per second
+    pub fn new(inner: B, ops_per_sec: u32) -> Self {
+        let ops_per_sec = ops_per_sec.max(1);
+        Self {
+            inner,
+            ops_per_sec,
+            bucket: Mutex::new(TokenBucket::new(ops_per_sec)),
+        }
+    }
+
+    /// Get the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get the configured operation rate
+    pub fn ops_per_sec(&self) -> u32 {
+        self.ops_per_sec
+    }
+
+    /// Number of whole tokens currently available
+    pub fn available_tokens(&self) -> VfsResult<u32> {
+        let mut bucket = self.bucket.lock()?;
+        bucket.refill();
+        Ok(bucket.tokens as u32)
+    }
+
+    /// Wait until a token is available and consume it
+    async fn acquire(&self) -> VfsResult<()> {
+        loop {
+            let wait = match self.bucket.lock()?.try_take() {
+                Ok(()) => return Ok(()),
+                Err(wait) => wait,
+            };
+            runtime::sleep(wait).await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<B: VfsBackend> VfsBackend for RateLimitBackend<B> {
+    async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        self.acquire().await?;
+        self.inner.walk(start, names).await
+    }
+
+    async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        self.acquire().await?;
+        self.inner.stat(path).await
+    }
+
+    async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        self.acquire().await?;
+        self.inner.open::<M, T>(path, mode).await
+    }
+
+    async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        self.acquire().await?;
+        self.inner.create::<M, T>(path, mode).await
+    }
+
+    async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        self.acquire().await?;
+        self.inner.read(handle, offset, count).await
+    }
+
+    async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        self.acquire().await?;
+        self.inner.write(handle, offset, data).await
+    }
+
+    async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        self.acquire().await?;
+        self.inner.remove::<T>(path).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.acquire().await?;
+        self.inner.rename(from, to).await
+    }
+
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        self.acquire().await?;
+        self.inner.readdir(handle).await
+    }
+
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.acquire().await?;
+        self.inner.clunk(fid).await
+    }
+
+    async fn reserve<M: CanWrite>(&self, handle: &FileHandle<File, M>, additional: u64) -> VfsResult<()> {
+        self.acquire().await?;
+        self.inner.reserve(handle, additional).await
+    }
+
+    async fn kind(&self, path: &str) -> VfsResult<NodeKind> {
+        self.acquire().await?;
+        self.inner.kind(path).await
+    }
+
+    async fn walk_path(&self, path: &str) -> VfsResult<WalkResult> {
+        self.acquire().await?;
+        self.inner.walk_path(path).await
+    }
+
+    async fn walk_from<M: Send + Sync + 'static>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        names: &[String],
+    ) -> VfsResult<WalkResult> {
+        self.acquire().await?;
+        self.inner.walk_from(handle, names).await
+    }
+
+    async fn reopen<M2, M>(&self, handle: &FileHandle<File, M>, mode: u32) -> VfsResult<FileHandle<File, M2>>
+    where
+        M2: Access,
+        M: Send + Sync + 'static,
+    {
+        self.acquire().await?;
+        self.inner.reopen::<M2, M>(handle, mode).await
+    }
+
+    async fn readdirplus<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<DirEntry>> {
+        self.acquire().await?;
+        self.inner.readdirplus(handle).await
+    }
+
+    async fn read_into<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        self.acquire().await?;
+        self.inner.read_into(handle, offset, buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::VfsMem;
+
+    #[tokio::test]
+    async fn test_burst_is_throttled() {
+        let backend = RateLimitBackend::new(VfsMem::new(), 10);
+        assert_eq!(backend.available_tokens().unwrap(), 10);
+
+        let start = Instant::now();
+        // 10 ops drain the bucket, the remaining 5 refill at 10/s
+        for _ in 0..15 {
+            backend.stat("/").await.unwrap();
+        }
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= Duration::from_millis(450), "elapsed: {:?}", elapsed);
+        assert_eq!(backend.available_tokens().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_within_burst_is_immediate() {
+        let backend = RateLimitBackend::new(VfsMem::new(), 100);
+
+        let start = Instant::now();
+        for _ in 0..50 {
+            backend.stat("/").await.unwrap();
+        }
+
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+}
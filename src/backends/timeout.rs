@@ -0,0 +1,244 @@
+//! Per-operation timeout backend decorator.
+
+use std::time::Duration;
+
+use crate::backend::VfsBackend;
+use crate::error::{VfsError, VfsResult};
+use crate::runtime;
+use crate::types::{Access, Dir, DirEntry, File, FileHandle, NodeKind, NodeType, Stat, WalkResult};
+use crate::{CanRead, CanWrite};
+
+/// Backend wrapper that fails any operation taking longer than `per_op`.
+///
+/// An operation that runs out of time returns `Timeout` and its future is
+/// dropped. Whether a mutating call that was cut short left a partial
+/// change behind depends on the inner backend's cancellation safety; a
+/// timed-out write may or may not have happened.
+#[derive(Debug)]
+pub struct TimeoutBackend<B> {
+    inner: B,
+    per_op: Duration,
+}
+
+impl<B> TimeoutBackend<B> {
+    /// Wrap `inner`, allowing each operation at most `per_op`
+    pub fn new(inner: B, per_op: Duration) -> Self {
+        Self { inner, per_op }
+    }
+
+    /// Get the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get the configured per-operation limit
+    pub fn per_op(&self) -> Duration {
+        self.per_op
+    }
+
+    /// Run `op`, failing with `Timeout` if it takes longer than `per_op`
+    async fn bounded<T>(&self, op: impl Future<Output = VfsResult<T>>) -> VfsResult<T> {
+        runtime::timeout(self.per_op, op)
+            .await
+            .ok_or(VfsError::Timeout)?
+    }
+}
+
+#[async_trait::async_trait]
+impl<B: VfsBackend> VfsBackend for TimeoutBackend<B> {
+    async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        self.bounded(self.inner.walk(start, names)).await
+    }
+
+    async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        self.bounded(self.inner.stat(path)).await
+    }
+
+    async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        self.bounded(self.inner.open::<M, T>(path, mode)).await
+    }
+
+    async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        self.bounded(self.inner.create::<M, T>(path, mode)).await
+    }
+
+    async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        self.bounded(self.inner.read(handle, offset, count)).await
+    }
+
+    async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        self.bounded(self.inner.write(handle, offset, data)).await
+    }
+
+    async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        self.bounded(self.inner.remove::<T>(path)).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.bounded(self.inner.rename(from, to)).await
+    }
+
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        self.bounded(self.inner.readdir(handle)).await
+    }
+
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.bounded(self.inner.clunk(fid)).await
+    }
+
+    async fn kind(&self, path: &str) -> VfsResult<NodeKind> {
+        self.bounded(self.inner.kind(path)).await
+    }
+
+    async fn reserve<M: CanWrite>(&self, handle: &FileHandle<File, M>, additional: u64) -> VfsResult<()> {
+        self.bounded(self.inner.reserve(handle, additional)).await
+    }
+
+    async fn walk_path(&self, path: &str) -> VfsResult<WalkResult> {
+        self.bounded(self.inner.walk_path(path)).await
+    }
+
+    async fn walk_from<M: Send + Sync + 'static>(
+        &self,
+        handle: &FileHandle<Dir, M>,
+        names: &[String],
+    ) -> VfsResult<WalkResult> {
+        self.bounded(self.inner.walk_from(handle, names)).await
+    }
+
+    async fn reopen<M2, M>(&self, handle: &FileHandle<File, M>, mode: u32) -> VfsResult<FileHandle<File, M2>>
+    where
+        M2: Access,
+        M: Send + Sync + 'static,
+    {
+        self.bounded(self.inner.reopen::<M2, M>(handle, mode)).await
+    }
+
+    async fn readdirplus<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<DirEntry>> {
+        self.bounded(self.inner.readdirplus(handle)).await
+    }
+
+    async fn read_into<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        self.bounded(self.inner.read_into(handle, offset, buf)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadOnly;
+    use crate::backend::VfsBackendExt;
+    use crate::backends::VfsMem;
+
+    /// Backend whose `stat` takes `delay`
+    struct Slow {
+        inner: VfsMem,
+        delay: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl VfsBackend for Slow {
+        async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+            self.inner.walk(start, names).await
+        }
+
+        async fn stat(&self, path: &str) -> VfsResult<Stat> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.stat(path).await
+        }
+
+        async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Access,
+            T: NodeType,
+        {
+            self.inner.open(path, mode).await
+        }
+
+        async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Send + Sync + 'static,
+            T: NodeType,
+        {
+            self.inner.create(path, mode).await
+        }
+
+        async fn read<M: CanRead>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            count: usize,
+        ) -> VfsResult<Vec<u8>> {
+            self.inner.read(handle, offset, count).await
+        }
+
+        async fn write<M: CanWrite>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            data: &[u8],
+        ) -> VfsResult<usize> {
+            self.inner.write(handle, offset, data).await
+        }
+
+        async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+            self.inner.remove::<T>(path).await
+        }
+
+        async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+            self.inner.readdir(handle).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_op_times_out() {
+        let slow = Slow {
+            inner: VfsMem::new(),
+            delay: Duration::from_secs(5),
+        };
+        let backend = TimeoutBackend::new(slow, Duration::from_millis(50));
+
+        let start = std::time::Instant::now();
+        assert!(matches!(backend.stat("/").await, Err(VfsError::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // Fast operations pass straight through
+        let dir = backend.create::<ReadOnly, Dir>("/a", 0o755).await.unwrap();
+        backend.clunk(dir.fid).await.unwrap();
+        assert!(backend.inner().inner.exists("/a").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_fast_op_within_limit() {
+        let slow = Slow {
+            inner: VfsMem::new(),
+            delay: Duration::from_millis(10),
+        };
+        let backend = TimeoutBackend::new(slow, Duration::from_secs(5));
+
+        assert!(backend.stat("/").await.is_ok());
+    }
+}
@@ -0,0 +1,217 @@
+//! Read-ahead buffering for sequential reads through a file handle.
+
+use crate::backend::VfsBackend;
+use crate::error::VfsResult;
+use crate::{CanRead, File, FileHandle};
+
+/// Default read-ahead size in bytes
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Sequential reader over an open file that fetches data in large chunks.
+///
+/// The first read prefetches `capacity` bytes from the current position and
+/// later small reads are served from that buffer until it runs out. Before
+/// every read the file's version is checked, so a write through any handle
+/// invalidates the buffer. Reads at least `capacity` long bypass it.
+#[derive(Debug)]
+pub struct BufferedHandle<'a, B, M> {
+    backend: &'a B,
+    handle: FileHandle<File, M>,
+    capacity: usize,
+    buf: Vec<u8>,
+    buf_start: u64,
+    version: Option<u32>,
+    pos: u64,
+}
+
+impl<'a, B: VfsBackend, M: CanRead> BufferedHandle<'a, B, M> {
+    /// Wrap `handle` with a read-ahead buffer of [`DEFAULT_BUFFER_SIZE`]
+    pub fn new(backend: &'a B, handle: FileHandle<File, M>) -> Self {
+        Self::with_capacity(backend, handle, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Wrap `handle` with a read-ahead buffer of `capacity` bytes
+    pub fn with_capacity(backend: &'a B, handle: FileHandle<File, M>, capacity: usize) -> Self {
+        Self {
+            backend,
+            handle,
+            capacity: capacity.max(1),
+            buf: Vec::new(),
+            buf_start: 0,
+            version: None,
+            pos: 0,
+        }
+    }
+
+    /// Offset the next read starts at
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Move the read position; the buffer is kept if it still covers it
+    pub fn seek(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// The wrapped handle
+    pub fn handle(&self) -> &FileHandle<File, M> {
+        &self.handle
+    }
+
+    /// Unwrap the handle, dropping any buffered data
+    pub fn into_inner(self) -> FileHandle<File, M> {
+        self.handle
+    }
+
+    /// Read up to `count` bytes from the current position and advance it.
+    ///
+    /// Returns fewer than `count` bytes only at end of file.
+    pub async fn read(&mut self, count: usize) -> VfsResult<Vec<u8>> {
+        let version = self.backend.stat(&self.handle.path).await?.qid.version;
+        if self.version != Some(version) {
+            self.buf.clear();
+            self.version = Some(version);
+        }
+
+        if count >= self.capacity {
+            let data = self.backend.read(&self.handle, self.pos, count).await?;
+            self.pos += data.len() as u64;
+            return Ok(data);
+        }
+
+        let mut out = Vec::with_capacity(count);
+        while out.len() < count {
+            let buf_end = self.buf_start + self.buf.len() as u64;
+            if self.pos < self.buf_start || self.pos >= buf_end {
+                self.buf = self.backend.read(&self.handle, self.pos, self.capacity).await?;
+                self.buf_start = self.pos;
+                if self.buf.is_empty() {
+                    break;
+                }
+            }
+
+            let start = (self.pos - self.buf_start) as usize;
+            let take = (count - out.len()).min(self.buf.len() - start);
+            out.extend_from_slice(&self.buf[start..start + take]);
+            self.pos += take as u64;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::VfsBackendExt;
+    use crate::backends::VfsMem;
+    use crate::{Access, CanWrite, Dir, NodeType, ReadOnly, ReadWrite, Stat, WalkResult};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Backend counting the reads that reach it
+    #[derive(Default)]
+    struct Counting {
+        inner: VfsMem,
+        reads: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl VfsBackend for Counting {
+        async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+            self.inner.walk(start, names).await
+        }
+
+        async fn stat(&self, path: &str) -> VfsResult<Stat> {
+            self.inner.stat(path).await
+        }
+
+        async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Access,
+            T: NodeType,
+        {
+            self.inner.open(path, mode).await
+        }
+
+        async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+        where
+            M: Send + Sync + 'static,
+            T: NodeType,
+        {
+            self.inner.create(path, mode).await
+        }
+
+        async fn read<M: CanRead>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            count: usize,
+        ) -> VfsResult<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read(handle, offset, count).await
+        }
+
+        async fn write<M: CanWrite>(
+            &self,
+            handle: &FileHandle<File, M>,
+            offset: u64,
+            data: &[u8],
+        ) -> VfsResult<usize> {
+            self.inner.write(handle, offset, data).await
+        }
+
+        async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+            self.inner.remove::<T>(path).await
+        }
+
+        async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+            self.inner.readdir(handle).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffered_sequential_reads() {
+        let vfs = Counting::default();
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        vfs.write_all("/data.bin", &content).await.unwrap();
+        vfs.reads.store(0, Ordering::SeqCst);
+
+        let handle = vfs.open::<ReadOnly, File>("/data.bin", 0).await.unwrap();
+        let mut reader = BufferedHandle::with_capacity(&vfs, handle, 256);
+
+        let mut out = Vec::new();
+        let mut chunks = 0;
+        loop {
+            let chunk = reader.read(16).await.unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            out.extend_from_slice(&chunk);
+            chunks += 1;
+        }
+
+        assert_eq!(out, content);
+        assert_eq!(chunks, 63);
+        // Four 256-byte fills, plus one EOF probe for each of the last two reads
+        let reads = vfs.reads.load(Ordering::SeqCst);
+        assert_eq!(reads, 6);
+        assert!(reads < chunks);
+    }
+
+    #[tokio::test]
+    async fn test_buffered_invalidates_on_write() {
+        let vfs = VfsMem::new();
+        let handle = vfs.create::<ReadWrite, File>("/f", 0o644).await.unwrap();
+        vfs.write(&handle, 0, b"aaaaaaaa").await.unwrap();
+
+        let writer = handle.clone();
+        let mut reader = BufferedHandle::with_capacity(&vfs, handle, 64);
+        assert_eq!(reader.read(4).await.unwrap(), b"aaaa");
+
+        vfs.write(&writer, 4, b"bbbb").await.unwrap();
+        assert_eq!(reader.read(4).await.unwrap(), b"bbbb");
+
+        reader.seek(0);
+        assert_eq!(reader.read(16).await.unwrap(), b"aaaabbbb");
+        assert_eq!(reader.position(), 8);
+    }
+}
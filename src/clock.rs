@@ -0,0 +1,62 @@
+//! Time sources for timestamps.
+
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time for mtimes, TTLs, and other timestamps
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The real system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock that only moves when told to, for deterministic tests
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at `start`
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    /// Jump to `time`
+    pub fn set(&self, time: SystemTime) {
+        *self.lock() = time;
+    }
+
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.lock() += by;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SystemTime> {
+        // A plain timestamp can't be left half-updated, so ignore poisoning
+        self.now.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.lock()
+    }
+}
@@ -18,6 +18,9 @@ pub enum VfsError {
     #[error("is a directory: {0}")]
     IsADirectory(String),
 
+    #[error("directory not empty: {0}")]
+    DirectoryNotEmpty(String),
+
     #[error("invalid argument: {0}")]
     InvalidArgument(String),
 
@@ -27,21 +30,347 @@ pub enum VfsError {
     #[error("invalid offset")]
     BadOffset,
 
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+
+    #[error("operation timed out")]
+    Timeout,
+
+    #[error("too many open files")]
+    TooManyOpenFiles,
+
+    #[error("stale version: {0}")]
+    Stale(String),
+
+    /// The backend can't perform this operation at all, as opposed to a
+    /// policy refusing it
+    #[error("unsupported operation: {0}")]
+    Unsupported(&'static str),
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("lock poisoned")]
     LockPoisoned,
+
+    #[error("lock poisoned during {op} on {path}")]
+    LockPoisonedAt { op: &'static str, path: String },
+
+    /// A journal record, counted from 0, failed when replayed
+    #[error("journal record {index} failed to replay: {source}")]
+    ReplayFailed { index: usize, source: Box<VfsError> },
 }
 
 pub type VfsResult<T> = Result<T, VfsError>;
 
+/// Stable numeric code for each `VfsError` variant.
+///
+/// `Display` strings may change between releases; these numbers won't, so
+/// they can cross an FFI or RPC boundary. New variants get new numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum VfsErrorCode {
+    NotFound = 1,
+    PermissionDenied = 2,
+    AlreadyExists = 3,
+    NotADirectory = 4,
+    IsADirectory = 5,
+    DirectoryNotEmpty = 6,
+    InvalidArgument = 7,
+    InvalidPath = 8,
+    BadOffset = 9,
+    UnexpectedEof = 10,
+    Timeout = 11,
+    TooManyOpenFiles = 12,
+    Stale = 13,
+    Unsupported = 14,
+    Io = 15,
+    LockPoisoned = 16,
+    LockPoisonedAt = 17,
+    ReplayFailed = 18,
+}
+
+impl TryFrom<u16> for VfsErrorCode {
+    type Error = u16;
+
+    /// Fails with the unknown number itself
+    fn try_from(code: u16) -> Result<Self, u16> {
+        use VfsErrorCode::*;
+
+        const ALL: [VfsErrorCode; 18] = [
+            NotFound,
+            PermissionDenied,
+            AlreadyExists,
+            NotADirectory,
+            IsADirectory,
+            DirectoryNotEmpty,
+            InvalidArgument,
+            InvalidPath,
+            BadOffset,
+            UnexpectedEof,
+            Timeout,
+            TooManyOpenFiles,
+            Stale,
+            Unsupported,
+            Io,
+            LockPoisoned,
+            LockPoisonedAt,
+            ReplayFailed,
+        ];
+        ALL.into_iter().find(|c| *c as u16 == code).ok_or(code)
+    }
+}
+
+impl VfsError {
+    /// The stable code for this error's variant
+    pub fn code(&self) -> VfsErrorCode {
+        match self {
+            VfsError::NotFound(_) => VfsErrorCode::NotFound,
+            VfsError::PermissionDenied(_) => VfsErrorCode::PermissionDenied,
+            VfsError::AlreadyExists(_) => VfsErrorCode::AlreadyExists,
+            VfsError::NotADirectory(_) => VfsErrorCode::NotADirectory,
+            VfsError::IsADirectory(_) => VfsErrorCode::IsADirectory,
+            VfsError::DirectoryNotEmpty(_) => VfsErrorCode::DirectoryNotEmpty,
+            VfsError::InvalidArgument(_) => VfsErrorCode::InvalidArgument,
+            VfsError::InvalidPath(_) => VfsErrorCode::InvalidPath,
+            VfsError::BadOffset => VfsErrorCode::BadOffset,
+            VfsError::UnexpectedEof => VfsErrorCode::UnexpectedEof,
+            VfsError::Timeout => VfsErrorCode::Timeout,
+            VfsError::TooManyOpenFiles => VfsErrorCode::TooManyOpenFiles,
+            VfsError::Stale(_) => VfsErrorCode::Stale,
+            VfsError::Unsupported(_) => VfsErrorCode::Unsupported,
+            VfsError::Io(_) => VfsErrorCode::Io,
+            VfsError::LockPoisoned => VfsErrorCode::LockPoisoned,
+            VfsError::LockPoisonedAt { .. } => VfsErrorCode::LockPoisonedAt,
+            VfsError::ReplayFailed { .. } => VfsErrorCode::ReplayFailed,
+        }
+    }
+
+    /// Rebuild an error from its code and message, such as one received
+    /// across an FFI or RPC boundary.
+    ///
+    /// `msg` becomes the variant's string; variants without one ignore it.
+    /// `Io` comes back as an `ErrorKind::Other` error carrying `msg`. The
+    /// operation names in `Unsupported` and `LockPoisonedAt` can't be
+    /// recovered and read `"unknown"`, with `msg` as the latter's path.
+    /// `ReplayFailed` comes back at index 0 with an `Io` error carrying
+    /// `msg` as its cause.
+    pub fn from_code(code: VfsErrorCode, msg: String) -> VfsError {
+        match code {
+            VfsErrorCode::NotFound => VfsError::NotFound(msg),
+            VfsErrorCode::PermissionDenied => VfsError::PermissionDenied(msg),
+            VfsErrorCode::AlreadyExists => VfsError::AlreadyExists(msg),
+            VfsErrorCode::NotADirectory => VfsError::NotADirectory(msg),
+            VfsErrorCode::IsADirectory => VfsError::IsADirectory(msg),
+            VfsErrorCode::DirectoryNotEmpty => VfsError::DirectoryNotEmpty(msg),
+            VfsErrorCode::InvalidArgument => VfsError::InvalidArgument(msg),
+            VfsErrorCode::InvalidPath => VfsError::InvalidPath(msg),
+            VfsErrorCode::BadOffset => VfsError::BadOffset,
+            VfsErrorCode::UnexpectedEof => VfsError::UnexpectedEof,
+            VfsErrorCode::Timeout => VfsError::Timeout,
+            VfsErrorCode::TooManyOpenFiles => VfsError::TooManyOpenFiles,
+            VfsErrorCode::Stale => VfsError::Stale(msg),
+            VfsErrorCode::Unsupported => VfsError::Unsupported("unknown"),
+            VfsErrorCode::Io => VfsError::Io(std::io::Error::other(msg)),
+            VfsErrorCode::LockPoisoned => VfsError::LockPoisoned,
+            VfsErrorCode::LockPoisonedAt => VfsError::LockPoisonedAt {
+                op: "unknown",
+                path: msg,
+            },
+            VfsErrorCode::ReplayFailed => VfsError::ReplayFailed {
+                index: 0,
+                source: Box::new(VfsError::Io(std::io::Error::other(msg))),
+            },
+        }
+    }
+}
+
 impl<T> From<std::sync::PoisonError<T>> for VfsError {
     fn from(_: std::sync::PoisonError<T>) -> Self {
         VfsError::LockPoisoned
     }
 }
 
+/// Carry a `VfsError` through APIs that speak `std::io::Error`.
+///
+/// Each variant maps to the closest `ErrorKind`; an `Io` error is unwrapped
+/// as is, and the message is kept for the rest.
+impl From<VfsError> for std::io::Error {
+    fn from(e: VfsError) -> Self {
+        use std::io::ErrorKind;
+
+        if let VfsError::Io(io) = e {
+            return io;
+        }
+        let kind = match &e {
+            VfsError::NotFound(_) => ErrorKind::NotFound,
+            VfsError::PermissionDenied(_) => ErrorKind::PermissionDenied,
+            VfsError::AlreadyExists(_) => ErrorKind::AlreadyExists,
+            VfsError::NotADirectory(_) => ErrorKind::NotADirectory,
+            VfsError::IsADirectory(_) => ErrorKind::IsADirectory,
+            VfsError::DirectoryNotEmpty(_) => ErrorKind::DirectoryNotEmpty,
+            VfsError::InvalidArgument(_) | VfsError::InvalidPath(_) | VfsError::BadOffset => {
+                ErrorKind::InvalidInput
+            }
+            VfsError::UnexpectedEof => ErrorKind::UnexpectedEof,
+            VfsError::Timeout => ErrorKind::TimedOut,
+            VfsError::Unsupported(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+/// Translate an OS error on `path` into the matching `VfsError` variant.
+///
+/// Kinds without a dedicated variant fall back to `VfsError::Io`.
+pub fn map_io_error(path: &str, e: std::io::Error) -> VfsError {
+    use std::io::ErrorKind;
+
+    match e.kind() {
+        ErrorKind::NotFound => VfsError::NotFound(path.to_string()),
+        ErrorKind::AlreadyExists => VfsError::AlreadyExists(path.to_string()),
+        ErrorKind::PermissionDenied => VfsError::PermissionDenied(path.to_string()),
+        ErrorKind::IsADirectory => VfsError::IsADirectory(path.to_string()),
+        ErrorKind::NotADirectory => VfsError::NotADirectory(path.to_string()),
+        ErrorKind::DirectoryNotEmpty => VfsError::DirectoryNotEmpty(path.to_string()),
+        ErrorKind::UnexpectedEof => VfsError::UnexpectedEof,
+        _ => VfsError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Fresh scratch directory under the system temp dir
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bulkhead-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn map(path: &Path, e: std::io::Error) -> VfsError {
+        map_io_error(path.to_str().unwrap(), e)
+    }
+
+    #[test]
+    fn test_map_io_error_kinds() {
+        let dir = scratch_dir("map-io-error");
+        let file = dir.join("file");
+        fs::write(&file, b"x").unwrap();
+
+        let missing = dir.join("missing");
+        let e = fs::read(&missing).unwrap_err();
+        assert!(matches!(map(&missing, e), VfsError::NotFound(p) if p.ends_with("missing")));
+
+        let e = fs::create_dir(&dir).unwrap_err();
+        assert!(matches!(map(&dir, e), VfsError::AlreadyExists(_)));
+
+        let nested = file.join("child");
+        let e = fs::read(&nested).unwrap_err();
+        assert!(matches!(map(&nested, e), VfsError::NotADirectory(_)));
+
+        #[cfg(unix)]
+        {
+            let e = fs::remove_file(&dir).unwrap_err();
+            assert!(matches!(map(&dir, e), VfsError::IsADirectory(_)));
+
+            let e = fs::remove_dir(&dir).unwrap_err();
+            assert!(matches!(map(&dir, e), VfsError::DirectoryNotEmpty(_)));
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_map_io_error_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("map-io-perm");
+        let locked = dir.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let target = locked.join("file");
+        let result = fs::write(&target, b"x");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Privileged users bypass permission bits entirely
+        if let Err(e) = result {
+            assert!(matches!(map(&target, e), VfsError::PermissionDenied(_)));
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_error_codes_round_trip() {
+        let cases = [
+            (VfsError::NotFound("/a".into()), 1),
+            (VfsError::PermissionDenied("/a".into()), 2),
+            (VfsError::AlreadyExists("/a".into()), 3),
+            (VfsError::NotADirectory("/a".into()), 4),
+            (VfsError::IsADirectory("/a".into()), 5),
+            (VfsError::DirectoryNotEmpty("/a".into()), 6),
+            (VfsError::InvalidArgument("bad".into()), 7),
+            (VfsError::InvalidPath("bad".into()), 8),
+            (VfsError::BadOffset, 9),
+            (VfsError::UnexpectedEof, 10),
+            (VfsError::Timeout, 11),
+            (VfsError::TooManyOpenFiles, 12),
+            (VfsError::Stale("/a".into()), 13),
+            (VfsError::Unsupported("symlink"), 14),
+            (VfsError::Io(std::io::Error::other("disk")), 15),
+            (VfsError::LockPoisoned, 16),
+            (VfsError::LockPoisonedAt { op: "stat", path: "/a".into() }, 17),
+            (
+                VfsError::ReplayFailed {
+                    index: 3,
+                    source: Box::new(VfsError::NotFound("/a".into())),
+                },
+                18,
+            ),
+        ];
+        for (error, number) in cases {
+            let code = error.code();
+            assert_eq!(code as u16, number);
+            assert_eq!(VfsErrorCode::try_from(number), Ok(code));
+            assert_eq!(VfsError::from_code(code, "/a".into()).code(), code);
+        }
+
+        assert!(matches!(
+            VfsError::from_code(VfsErrorCode::NotFound, "/x".into()),
+            VfsError::NotFound(p) if p == "/x"
+        ));
+        assert_eq!(VfsErrorCode::try_from(0), Err(0));
+        assert_eq!(VfsErrorCode::try_from(19), Err(19));
+    }
+
+    #[test]
+    fn test_unsupported_into_io_error() {
+        let e = VfsError::Unsupported("symlink");
+        assert_eq!(e.to_string(), "unsupported operation: symlink");
+
+        let io = std::io::Error::from(e);
+        assert_eq!(io.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(io.to_string(), "unsupported operation: symlink");
+
+        let io = std::io::Error::from(VfsError::NotFound("/x".into()));
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_map_io_error_fallback() {
+        let e = std::io::Error::new(std::io::ErrorKind::TimedOut, "slow");
+        assert!(matches!(map_io_error("/x", e), VfsError::Io(_)));
+    }
+}
+
 // use serde::{Deserialize, Serialize};
 // use thiserror::Error;
 
@@ -1,9 +1,17 @@
 pub mod backend;
 pub mod backends;
+pub mod buffered;
+pub mod clock;
+pub mod mode;
 pub mod error;
+pub mod path;
+mod runtime;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod types;
+pub mod wire;
 
 // Re-export
-pub use error::{VfsError, VfsResult};
+pub use error::{VfsError, VfsErrorCode, VfsResult, map_io_error};
 pub use types::*;
 pub use types::{CanRead, CanWrite};
@@ -0,0 +1,142 @@
+//! 9P open mode flags.
+
+use std::ops::BitOr;
+
+use crate::error::{VfsError, VfsResult};
+use crate::types::Access;
+
+/// Open for reading
+pub const OREAD: u32 = 0;
+/// Open for writing
+pub const OWRITE: u32 = 1;
+/// Open for reading and writing
+pub const ORDWR: u32 = 2;
+/// Open for execution (checked like reading)
+pub const OEXEC: u32 = 3;
+/// Truncate the file to zero length on open
+pub const OTRUNC: u32 = 0x10;
+/// Remove the file when its fid is clunked
+pub const ORCLOSE: u32 = 0x40;
+
+/// Mask of the access bits
+const ACCESS_MASK: u32 = 0x3;
+
+/// Parsed 9P open mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenMode {
+    /// One of `OREAD`, `OWRITE`, `ORDWR`, or `OEXEC`
+    pub access: u32,
+    pub truncate: bool,
+    pub remove_on_close: bool,
+}
+
+impl OpenMode {
+    /// Split a raw mode into its parts, rejecting unknown bits
+    pub fn parse(mode: u32) -> VfsResult<Self> {
+        let unknown = mode & !(ACCESS_MASK | OTRUNC | ORCLOSE);
+        if unknown != 0 {
+            return Err(VfsError::InvalidArgument(format!(
+                "unknown open mode bits {:#x}",
+                unknown
+            )));
+        }
+
+        Ok(Self {
+            access: mode & ACCESS_MASK,
+            truncate: mode & OTRUNC != 0,
+            remove_on_close: mode & ORCLOSE != 0,
+        })
+    }
+
+    /// Whether the mode needs write access
+    pub fn wants_write(&self) -> bool {
+        matches!(self.access, OWRITE | ORDWR) || self.truncate
+    }
+
+    /// Whether the mode explicitly asks for read access.
+    ///
+    /// `OREAD` is zero and so also what callers pass when they don't care;
+    /// it is accepted with any marker.
+    pub fn wants_read(&self) -> bool {
+        matches!(self.access, ORDWR | OEXEC)
+    }
+
+    /// Check the mode against the handle's compile-time access marker
+    pub fn check<M: Access>(&self, path: &str) -> VfsResult<()> {
+        if (self.wants_write() && !M::WRITE) || (self.wants_read() && !M::READ) {
+            return Err(VfsError::PermissionDenied(format!(
+                "{}: open mode doesn't match handle access",
+                path
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Permissions to check against a node's mode bits; combine with `|`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccessMode(u32);
+
+impl AccessMode {
+    pub const READ: Self = Self(0o4);
+    pub const WRITE: Self = Self(0o2);
+    pub const EXEC: Self = Self(0o1);
+
+    /// Whether every permission in `other` is also in `self`
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether `mode` grants all of these permissions.
+    ///
+    /// Like Unix, only one class applies: the owner bits if `is_owner`,
+    /// else the group bits if `in_group`, else the other bits. An owner
+    /// denied by the owner bits is denied even if the other bits allow it.
+    pub fn granted_by(self, mode: u32, is_owner: bool, in_group: bool) -> bool {
+        let shift = match (is_owner, in_group) {
+            (true, _) => 6,
+            (false, true) => 3,
+            (false, false) => 0,
+        };
+        Self((mode >> shift) & 0o7).contains(self)
+    }
+}
+
+impl BitOr for AccessMode {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ReadOnly, ReadWrite, WriteOnly};
+
+    #[test]
+    fn test_parse() {
+        let mode = OpenMode::parse(ORDWR | OTRUNC | ORCLOSE).unwrap();
+        assert_eq!(mode.access, ORDWR);
+        assert!(mode.truncate && mode.remove_on_close);
+
+        assert!(OpenMode::parse(0x80).is_err());
+    }
+
+    #[test]
+    fn test_check_against_marker() {
+        let read = OpenMode::parse(OREAD).unwrap();
+        let write = OpenMode::parse(OWRITE).unwrap();
+        let rdwr = OpenMode::parse(ORDWR).unwrap();
+
+        assert!(read.check::<ReadOnly>("/f").is_ok());
+        assert!(read.check::<WriteOnly>("/f").is_ok());
+        assert!(write.check::<WriteOnly>("/f").is_ok());
+        assert!(rdwr.check::<ReadWrite>("/f").is_ok());
+
+        assert!(write.check::<ReadOnly>("/f").is_err());
+        assert!(rdwr.check::<WriteOnly>("/f").is_err());
+        assert!(OpenMode::parse(OTRUNC).unwrap().check::<ReadOnly>("/f").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Path helpers shared by all backends.
+
+use std::path::Path;
+
+use crate::error::{VfsError, VfsResult};
+
+/// Resolve a path to its canonical absolute form without touching any backend.
+///
+/// Empty and `.` components are dropped and `..` removes the preceding
+/// component. Relative paths are taken from `/`. A `..` that would climb above
+/// the root is an `InvalidPath` error rather than being clamped.
+pub fn canonicalize(path: &str) -> VfsResult<String> {
+    if path.is_empty() {
+        return Err(VfsError::InvalidPath("empty path".into()));
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(VfsError::InvalidPath(format!("{} escapes root", path)));
+                }
+            }
+            name => components.push(name),
+        }
+    }
+
+    Ok(format!("/{}", components.join("/")))
+}
+
+/// Normalize a path the way [`VfsMem`](crate::backends::VfsMem) does.
+///
+/// Leading and trailing slashes are dropped, `.` components are dropped,
+/// and relative paths are taken from `/`; so `///`, `/.`, and `/./` all
+/// name the root. Unlike [`canonicalize`], `..` is never resolved: any path
+/// containing `..` or a NUL byte, an empty path, and empty components
+/// between names such as `a//b` are all `InvalidPath` errors.
+pub fn normalize_path(path: &str) -> VfsResult<String> {
+    if path.contains("..") {
+        return Err(VfsError::InvalidPath(".. traversal not allowed".into()));
+    }
+
+    if path.contains('\0') {
+        return Err(VfsError::InvalidPath("NUL byte in path".into()));
+    }
+
+    if path.is_empty() {
+        return Err(VfsError::InvalidPath("empty path".into()));
+    }
+
+    let clean = path.trim_matches('/');
+    if clean.is_empty() {
+        return Ok("/".to_string());
+    }
+
+    // Check for empty components (e.g., "//")
+    if clean.split('/').any(|s| s.is_empty()) {
+        return Err(VfsError::InvalidPath("empty path component".into()));
+    }
+
+    let components: Vec<&str> = clean.split('/').filter(|c| *c != ".").collect();
+    Ok(format!("/{}", components.join("/")))
+}
+
+/// Convert an OS path into a canonical VFS path.
+///
+/// Only `/` separates components: a backslash is an ordinary character, so
+/// `a\b` is a single name even on Windows. Non-UTF-8 paths are rejected
+/// with `InvalidPath`.
+pub fn vfs_path(path: impl AsRef<Path>) -> VfsResult<String> {
+    let path = path.as_ref();
+    let path = path
+        .to_str()
+        .ok_or_else(|| VfsError::InvalidPath(format!("{} is not valid utf-8", path.display())))?;
+    canonicalize(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize("/a/./b/../c").unwrap(), "/a/c");
+        assert_eq!(canonicalize("/").unwrap(), "/");
+        assert_eq!(canonicalize("a//b/").unwrap(), "/a/b");
+        assert_eq!(canonicalize("/a/..").unwrap(), "/");
+        assert_eq!(canonicalize("/./.").unwrap(), "/");
+    }
+
+    #[test]
+    fn test_canonicalize_rejects_escape() {
+        assert!(matches!(canonicalize("/../x"), Err(VfsError::InvalidPath(_))));
+        assert!(canonicalize("/a/../../b").is_err());
+        assert!(canonicalize("").is_err());
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("/").unwrap(), "/");
+        assert_eq!(normalize_path("/foo").unwrap(), "/foo");
+        assert_eq!(normalize_path("foo").unwrap(), "/foo");
+        assert_eq!(normalize_path("/foo/bar/").unwrap(), "/foo/bar");
+
+        assert!(normalize_path("..").is_err());
+        assert!(normalize_path("/foo/../bar").is_err());
+        assert!(normalize_path("").is_err());
+        assert!(matches!(normalize_path("/foo/b\0r"), Err(VfsError::InvalidPath(_))));
+    }
+
+    #[test]
+    fn test_normalize_path_root_forms() {
+        assert_eq!(normalize_path("///").unwrap(), "/");
+        assert_eq!(normalize_path("/.").unwrap(), "/");
+        assert_eq!(normalize_path("/./").unwrap(), "/");
+        assert_eq!(normalize_path("/a/./b/.").unwrap(), "/a/b");
+
+        assert!(matches!(normalize_path("//a//b"), Err(VfsError::InvalidPath(_))));
+    }
+
+    #[test]
+    fn test_vfs_path() {
+        assert_eq!(vfs_path(Path::new("/a/b")).unwrap(), "/a/b");
+        assert_eq!(vfs_path(std::path::PathBuf::from("a/./b/")).unwrap(), "/a/b");
+        assert_eq!(vfs_path("/dir\\file").unwrap(), "/dir\\file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_vfs_path_rejects_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/bad\xff"));
+        assert!(matches!(vfs_path(path), Err(VfsError::InvalidPath(_))));
+    }
+}
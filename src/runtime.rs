@@ -0,0 +1,101 @@
+//! Timers and task spawning for the async runtime in use.
+//!
+//! The filesystem core only needs `std` locks and runtime-agnostic
+//! channels, so it runs on any executor. The pieces that sleep, time out,
+//! or spawn tasks ([`TimeoutBackend`](crate::backends::TimeoutBackend),
+//! [`RateLimitBackend`](crate::backends::RateLimitBackend), and the expiry
+//! reaper) need one of the runtime features: `tokio` (the default) or
+//! `async-std`. With both enabled, Tokio is used.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+use std::time::Duration;
+
+/// A spawned background task that can be polled for completion and awaited
+pub(crate) struct Task {
+    done: Arc<AtomicBool>,
+    join: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("finished", &self.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Task {
+    /// Whether the task has run to completion
+    pub fn is_finished(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    /// Wait for the task to end; a task that panicked has ended all the same
+    pub async fn join(self) {
+        self.join.await
+    }
+}
+
+/// Let other tasks run before continuing; works on any executor
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx: &mut Context<'_>| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+/// Run `future` in the background on the current runtime
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub(crate) fn spawn<F>(future: F) -> Task
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let done = Arc::new(AtomicBool::new(false));
+    let flag = done.clone();
+    let future = async move {
+        future.await;
+        flag.store(true, Ordering::Release);
+    };
+
+    #[cfg(feature = "tokio")]
+    let join = {
+        let handle = tokio::spawn(future);
+        Box::pin(async move {
+            let _ = handle.await;
+        })
+    };
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    let join = Box::pin(async_std::task::spawn(future));
+
+    Task { done, join }
+}
+
+/// Wait for `duration` to pass
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    async_std::task::sleep(duration).await;
+}
+
+/// Run `future`, giving up with `None` once `duration` has passed
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    #[cfg(feature = "tokio")]
+    let result = tokio::time::timeout(duration, future).await.ok();
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    let result = async_std::future::timeout(duration, future).await.ok();
+    result
+}
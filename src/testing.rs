@@ -0,0 +1,496 @@
+//! Helpers for testing code built on [`VfsBackend`].
+//!
+//! [`MockBackend`] stores nothing. It records each primitive call and
+//! answers with replies queued per method, falling back to a fixed default
+//! once a method's queue is empty. Default trait methods go through the
+//! primitives, so they show up as the primitive calls they make.
+//!
+//! [`stress`] runs random concurrent operations against a real backend and
+//! checks the results.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use futures::executor::block_on;
+
+use crate::backend::{VfsBackend, VfsBackendExt};
+use crate::error::{VfsError, VfsResult};
+use crate::mode::OWRITE;
+use crate::types::{Access, Dir, File, FileHandle, NodeType, Qid, Stat, WalkResult, WriteOnly};
+use crate::{CanRead, CanWrite};
+
+/// One call made to a [`MockBackend`], with its arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedCall {
+    Walk { start: String, names: Vec<String> },
+    Stat { path: String },
+    Open { path: String, mode: u32 },
+    Create { path: String, mode: u32 },
+    Read { fid: u64, offset: u64, count: usize },
+    Write { fid: u64, offset: u64, data: Vec<u8> },
+    Remove { path: String },
+    Rename { from: String, to: String },
+    Readdir { fid: u64 },
+    Clunk { fid: u64 },
+}
+
+/// Replies waiting to be returned, per method
+#[derive(Debug, Default)]
+struct Replies {
+    walk: VecDeque<VfsResult<WalkResult>>,
+    stat: VecDeque<VfsResult<Stat>>,
+    open: VecDeque<VfsResult<()>>,
+    create: VecDeque<VfsResult<()>>,
+    read: VecDeque<VfsResult<Vec<u8>>>,
+    write: VecDeque<VfsResult<usize>>,
+    remove: VecDeque<VfsResult<()>>,
+    rename: VecDeque<VfsResult<()>>,
+    readdir: VecDeque<VfsResult<Vec<Stat>>>,
+}
+
+/// Backend that records every call and returns programmed replies.
+///
+/// Without a queued reply, `walk` finds nothing, `stat` fails with
+/// `NotFound`, `open` and `create` succeed, `read` returns no data, `write`
+/// accepts everything, and `remove`, `rename`, and `readdir` succeed with
+/// nothing.
+/// Handles from `open` and `create` get fresh fids and a qid whose path is
+/// the fid.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    calls: Mutex<Vec<RecordedCall>>,
+    replies: Mutex<Replies>,
+    next_fid: AtomicU64,
+}
+
+/// Lock a mutex, ignoring poisoning; the mock's state is always consistent
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every call made so far, oldest first
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        lock(&self.calls).clone()
+    }
+
+    /// Forget the calls recorded so far
+    pub fn clear_calls(&self) {
+        lock(&self.calls).clear();
+    }
+
+    /// Queue the result of the next `walk`
+    pub fn on_walk(&self, reply: VfsResult<WalkResult>) -> &Self {
+        lock(&self.replies).walk.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `stat`
+    pub fn on_stat(&self, reply: VfsResult<Stat>) -> &Self {
+        lock(&self.replies).stat.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `open`; `Ok` hands out a new handle
+    pub fn on_open(&self, reply: VfsResult<()>) -> &Self {
+        lock(&self.replies).open.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `create`; `Ok` hands out a new handle
+    pub fn on_create(&self, reply: VfsResult<()>) -> &Self {
+        lock(&self.replies).create.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `read`
+    pub fn on_read(&self, reply: VfsResult<Vec<u8>>) -> &Self {
+        lock(&self.replies).read.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `write`
+    pub fn on_write(&self, reply: VfsResult<usize>) -> &Self {
+        lock(&self.replies).write.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `remove`
+    pub fn on_remove(&self, reply: VfsResult<()>) -> &Self {
+        lock(&self.replies).remove.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `rename`
+    pub fn on_rename(&self, reply: VfsResult<()>) -> &Self {
+        lock(&self.replies).rename.push_back(reply);
+        self
+    }
+
+    /// Queue the result of the next `readdir`
+    pub fn on_readdir(&self, reply: VfsResult<Vec<Stat>>) -> &Self {
+        lock(&self.replies).readdir.push_back(reply);
+        self
+    }
+
+    fn record(&self, call: RecordedCall) {
+        lock(&self.calls).push(call);
+    }
+
+    /// A handle with a fresh fid, unless the queued reply is an error
+    fn handle<T: NodeType, M>(
+        &self,
+        reply: Option<VfsResult<()>>,
+        path: &str,
+        mode: u32,
+    ) -> VfsResult<FileHandle<T, M>> {
+        reply.unwrap_or(Ok(()))?;
+        let fid = self.next_fid.fetch_add(1, Ordering::Relaxed) + 1;
+        Ok(FileHandle::new(fid, Qid::new(fid, 0), path.to_string(), mode))
+    }
+}
+
+#[async_trait::async_trait]
+impl VfsBackend for MockBackend {
+    async fn walk(&self, start: &str, names: &[String]) -> VfsResult<WalkResult> {
+        self.record(RecordedCall::Walk {
+            start: start.to_string(),
+            names: names.to_vec(),
+        });
+        let reply = lock(&self.replies).walk.pop_front();
+        reply.unwrap_or_else(|| Ok(WalkResult { qids: Vec::new() }))
+    }
+
+    async fn stat(&self, path: &str) -> VfsResult<Stat> {
+        self.record(RecordedCall::Stat { path: path.to_string() });
+        let reply = lock(&self.replies).stat.pop_front();
+        reply.unwrap_or_else(|| Err(VfsError::NotFound(path.to_string())))
+    }
+
+    async fn open<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Access,
+        T: NodeType,
+    {
+        self.record(RecordedCall::Open { path: path.to_string(), mode });
+        let reply = lock(&self.replies).open.pop_front();
+        self.handle(reply, path, mode)
+    }
+
+    async fn create<M, T>(&self, path: &str, mode: u32) -> VfsResult<FileHandle<T, M>>
+    where
+        M: Send + Sync + 'static,
+        T: NodeType,
+    {
+        self.record(RecordedCall::Create { path: path.to_string(), mode });
+        let reply = lock(&self.replies).create.pop_front();
+        self.handle(reply, path, mode)
+    }
+
+    async fn read<M: CanRead>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        count: usize,
+    ) -> VfsResult<Vec<u8>> {
+        self.record(RecordedCall::Read { fid: handle.fid, offset, count });
+        let reply = lock(&self.replies).read.pop_front();
+        reply.unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    async fn write<M: CanWrite>(
+        &self,
+        handle: &FileHandle<File, M>,
+        offset: u64,
+        data: &[u8],
+    ) -> VfsResult<usize> {
+        self.record(RecordedCall::Write {
+            fid: handle.fid,
+            offset,
+            data: data.to_vec(),
+        });
+        let reply = lock(&self.replies).write.pop_front();
+        reply.unwrap_or(Ok(data.len()))
+    }
+
+    async fn remove<T>(&self, path: &str) -> VfsResult<()> {
+        self.record(RecordedCall::Remove { path: path.to_string() });
+        let reply = lock(&self.replies).remove.pop_front();
+        reply.unwrap_or(Ok(()))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> VfsResult<()> {
+        self.record(RecordedCall::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        let reply = lock(&self.replies).rename.pop_front();
+        reply.unwrap_or(Ok(()))
+    }
+
+    async fn readdir<M: CanRead>(&self, handle: &FileHandle<Dir, M>) -> VfsResult<Vec<Stat>> {
+        self.record(RecordedCall::Readdir { fid: handle.fid });
+        let reply = lock(&self.replies).readdir.pop_front();
+        reply.unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    async fn clunk(&self, fid: u64) -> VfsResult<()> {
+        self.record(RecordedCall::Clunk { fid });
+        Ok(())
+    }
+}
+
+/// Files every stress worker overwrites, to catch torn writes
+const SHARED_FILES: usize = 4;
+
+/// Length of each write to a shared file
+const SHARED_LEN: usize = 64;
+
+/// Private files per stress worker
+const PRIVATE_FILES: u64 = 8;
+
+/// An error describing a broken invariant
+fn violation(message: String) -> VfsError {
+    VfsError::Io(io::Error::other(format!("stress invariant violated: {}", message)))
+}
+
+/// Small xorshift generator; the stress run needs variety, not quality
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Run `ops` random operations against `backend` from `threads` threads,
+/// checking the invariants [`VfsMem`](crate::backends::VfsMem) upholds.
+///
+/// Everything happens under `/stress`. Each thread creates, writes, reads,
+/// renames, and removes its own files and checks them against what it last
+/// wrote, so no single-writer data may be lost. All threads also overwrite a few
+/// shared files with runs of one repeated byte and read them back; a read
+/// mixing two bytes is a torn write. Each thread drives its backend clone
+/// with a plain blocking executor, so the backend must not need a runtime.
+///
+/// Renames are skipped if the backend answers `Unsupported`. Returns an
+/// `Io` error describing the first violation found, a worker panic
+/// included, or the first unexpected backend error.
+pub fn stress<B: VfsBackend + Clone>(backend: B, ops: usize, threads: usize) -> VfsResult<()> {
+    let threads = threads.max(1);
+    block_on(async {
+        backend.create_dir_all("/stress").await?;
+        for k in 0..SHARED_FILES {
+            backend.write_all(&shared_path(k), &[0; SHARED_LEN]).await?;
+        }
+        Ok::<_, VfsError>(())
+    })?;
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|id| {
+                let backend = backend.clone();
+                // Spread the remainder over the first few workers
+                let ops = ops / threads + usize::from(id < ops % threads);
+                scope.spawn(move || block_on(stress_worker(&backend, id, ops)))
+            })
+            .collect();
+
+        let mut first = Ok(());
+        for (id, worker) in workers.into_iter().enumerate() {
+            let result = worker
+                .join()
+                .unwrap_or_else(|_| Err(violation(format!("worker {} panicked", id))));
+            if first.is_ok() {
+                first = result;
+            }
+        }
+        first
+    })
+}
+
+fn shared_path(k: usize) -> String {
+    format!("/stress/shared{}", k)
+}
+
+/// One stress thread's share of the operations
+async fn stress_worker<B: VfsBackend>(backend: &B, id: usize, ops: usize) -> VfsResult<()> {
+    let dir = format!("/stress/t{}", id);
+    backend.create_dir_all(&dir).await?;
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15 ^ (id as u64 + 1));
+    // What each private file should hold, by path
+    let mut model: HashMap<String, Vec<u8>> = HashMap::new();
+
+    for _ in 0..ops {
+        let path = format!("{}/f{}", dir, rng.below(PRIVATE_FILES));
+        match rng.below(7) {
+            0 => {
+                let created = backend.create::<WriteOnly, File>(&path, 0o644).await;
+                match (created, model.contains_key(&path)) {
+                    (Ok(handle), false) => {
+                        backend.clunk(handle.fid).await?;
+                        model.insert(path, Vec::new());
+                    }
+                    (Err(VfsError::AlreadyExists(_)), true) => {}
+                    (Ok(_), true) => {
+                        return Err(violation(format!("created {} over an existing file", path)));
+                    }
+                    (Err(e), _) => return Err(e),
+                }
+            }
+            1 => {
+                let len = rng.below(256) as usize;
+                let data: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+                backend.write_all(&path, &data).await?;
+                model.insert(path, data);
+            }
+            2 => check_private(backend, &path, model.get(&path)).await?,
+            3 => match (backend.remove::<File>(&path).await, model.remove(&path)) {
+                (Ok(()), Some(_)) | (Err(VfsError::NotFound(_)), None) => {}
+                (Ok(()), None) => return Err(violation(format!("removed missing {}", path))),
+                (Err(e), _) => return Err(e),
+            },
+            4 => {
+                let to = format!("{}/f{}", dir, rng.below(PRIVATE_FILES));
+                let renamed = backend.rename(&path, &to).await;
+                match (renamed, model.contains_key(&path), model.contains_key(&to)) {
+                    (Err(VfsError::Unsupported(_)), _, _) => {}
+                    (Ok(()), _, _) if path == to => {}
+                    (Ok(()), true, false) => {
+                        let data = model.remove(&path).unwrap_or_default();
+                        model.insert(to, data);
+                    }
+                    (Err(VfsError::NotFound(_)), false, _) => {}
+                    (Err(VfsError::AlreadyExists(_)), true, true) => {}
+                    (Ok(()), _, _) => {
+                        return Err(violation(format!("renamed {} to {} unexpectedly", path, to)));
+                    }
+                    (Err(e), _, _) => return Err(e),
+                }
+            }
+            5 => {
+                let fill = rng.next() as u8;
+                let path = shared_path(rng.below(SHARED_FILES as u64) as usize);
+                let handle = backend.open::<WriteOnly, File>(&path, OWRITE).await?;
+                let written = backend.write(&handle, 0, &[fill; SHARED_LEN]).await;
+                backend.clunk(handle.fid).await?;
+                if written? != SHARED_LEN {
+                    return Err(violation("short write to a shared file".into()));
+                }
+            }
+            _ => {
+                let path = shared_path(rng.below(SHARED_FILES as u64) as usize);
+                let data = backend.read_all(&path).await?;
+                if data.len() != SHARED_LEN || data.iter().any(|b| *b != data[0]) {
+                    return Err(violation(format!("torn write in {}: {:?}", path, data)));
+                }
+            }
+        }
+    }
+
+    for k in 0..PRIVATE_FILES {
+        let path = format!("{}/f{}", dir, k);
+        check_private(backend, &path, model.get(&path)).await?;
+    }
+    Ok(())
+}
+
+/// Check a private file against what its worker last wrote
+async fn check_private<B: VfsBackend>(
+    backend: &B,
+    path: &str,
+    expected: Option<&Vec<u8>>,
+) -> VfsResult<()> {
+    match (backend.read_all(path).await, expected) {
+        (Ok(data), Some(expected)) if data == *expected => Ok(()),
+        (Ok(data), Some(expected)) => Err(violation(format!(
+            "{} holds {} bytes, expected the {} last written",
+            path,
+            data.len(),
+            expected.len()
+        ))),
+        (Ok(_), None) => Err(violation(format!("{} exists after removal", path))),
+        (Err(VfsError::NotFound(_)), None) => Ok(()),
+        (Err(VfsError::NotFound(_)), Some(_)) => Err(violation(format!("{} was lost", path))),
+        (Err(e), _) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ReadOnly, ReadWrite};
+
+    #[tokio::test]
+    async fn test_records_calls_in_order() {
+        let mock = MockBackend::new();
+        let handle = mock.create::<ReadWrite, File>("/a.txt", 0o644).await.unwrap();
+        mock.write(&handle, 0, b"hi").await.unwrap();
+        mock.read(&handle, 0, 2).await.unwrap();
+        mock.clunk(handle.fid).await.unwrap();
+        let dir = mock.open::<ReadOnly, Dir>("/", 0).await.unwrap();
+        mock.readdir(&dir).await.unwrap();
+        mock.remove::<File>("/a.txt").await.unwrap();
+
+        assert_eq!(
+            mock.calls(),
+            vec![
+                RecordedCall::Create { path: "/a.txt".into(), mode: 0o644 },
+                RecordedCall::Write { fid: handle.fid, offset: 0, data: b"hi".to_vec() },
+                RecordedCall::Read { fid: handle.fid, offset: 0, count: 2 },
+                RecordedCall::Clunk { fid: handle.fid },
+                RecordedCall::Open { path: "/".into(), mode: 0 },
+                RecordedCall::Readdir { fid: dir.fid },
+                RecordedCall::Remove { path: "/a.txt".into() },
+            ]
+        );
+        assert_ne!(dir.fid, handle.fid);
+    }
+
+    #[tokio::test]
+    async fn test_programmed_replies() {
+        let mock = MockBackend::new();
+        mock.on_open(Err(VfsError::PermissionDenied("/secret".into())))
+            .on_read(Ok(b"canned".to_vec()));
+
+        let denied = mock.open::<ReadOnly, File>("/secret", 0).await;
+        assert!(matches!(denied, Err(VfsError::PermissionDenied(p)) if p == "/secret"));
+
+        // Queue used up; the default applies again
+        let handle = mock.open::<ReadOnly, File>("/secret", 0).await.unwrap();
+        assert_eq!(mock.read(&handle, 0, 64).await.unwrap(), b"canned");
+        assert!(mock.read(&handle, 0, 64).await.unwrap().is_empty());
+        assert!(matches!(mock.stat("/x").await, Err(VfsError::NotFound(_))));
+        assert_eq!(mock.calls().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_write_all_stops_on_zero_write() {
+        let mock = MockBackend::new();
+        mock.on_write(Ok(0));
+        let result = mock.write_all("/f", b"data").await;
+        assert!(matches!(result, Err(VfsError::Io(e)) if e.kind() == io::ErrorKind::WriteZero));
+        assert!(matches!(mock.calls().last(), Some(RecordedCall::Clunk { .. })));
+    }
+
+    #[test]
+    fn test_stress_vfs_mem() {
+        let vfs = crate::backends::VfsMem::new();
+        stress(vfs.clone(), 4000, 4).unwrap();
+        assert!(block_on(vfs.exists("/stress/t3")).unwrap());
+    }
+}
@@ -1,15 +1,61 @@
 // types.rs
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::marker::PhantomData;
 use std::time::SystemTime;
 
 /// Object types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct File;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Dir;
 
+/// Kind of a filesystem node, for branching without a full `Stat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeKind {
+    File,
+    Dir,
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for super::File {}
+    impl Sealed for super::Dir {}
+    impl Sealed for super::ReadOnly {}
+    impl Sealed for super::WriteOnly {}
+    impl Sealed for super::ReadWrite {}
+}
+
+/// Object type markers accepted by `open` and `create`.
+///
+/// Sealed, so dispatch depends only on [`File`] and [`Dir`] and never on
+/// how another type happens to be named:
+///
+/// ```compile_fail
+/// use bulkhead::backend::VfsBackend;
+/// use bulkhead::backends::VfsMem;
+/// use bulkhead::ReadOnly;
+///
+/// struct DirectoryEntry;
+///
+/// # async fn f() {
+/// let vfs = VfsMem::new();
+/// vfs.create::<ReadOnly, DirectoryEntry>("/entry", 0o755).await;
+/// # }
+/// ```
+pub trait NodeType: sealed::Sealed + Send + Sync + 'static {
+    const KIND: NodeKind;
+}
+
+impl NodeType for File {
+    const KIND: NodeKind = NodeKind::File;
+}
+
+impl NodeType for Dir {
+    const KIND: NodeKind = NodeKind::Dir;
+}
+
 /// Access modes
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct ReadOnly;
@@ -29,7 +75,33 @@ pub trait CanWrite: Send + Sync {}
 impl CanWrite for WriteOnly {}
 impl CanWrite for ReadWrite {}
 
-/// Unique file identifier (like 9P `qid`)
+/// Access markers accepted by `open`, checked against the 9P open mode
+pub trait Access: sealed::Sealed + Send + Sync + 'static {
+    const READ: bool;
+    const WRITE: bool;
+}
+
+impl Access for ReadOnly {
+    const READ: bool = true;
+    const WRITE: bool = false;
+}
+
+impl Access for WriteOnly {
+    const READ: bool = false;
+    const WRITE: bool = true;
+}
+
+impl Access for ReadWrite {
+    const READ: bool = true;
+    const WRITE: bool = true;
+}
+
+/// Unique file identifier (like 9P `qid`).
+///
+/// `Qid<File>` and `Qid<Dir>` record the kind in the type, so a directory
+/// qid can't stand in for a file qid (see `tests/ui/qid_dir_as_file.rs`).
+///
+/// The erased `Qid` (`Qid<()>`) holds either kind, as in [`WalkResult`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Qid<T = ()> {
     pub ty: u8,
@@ -39,45 +111,219 @@ pub struct Qid<T = ()> {
     pub _marker: PhantomData<T>,
 }
 
-impl<T> Qid<T> {
-    /// Create a new Qid for a file (ty = 0x00)
-    pub fn new_file(path: u64, version: u32) -> Self {
+impl<T: NodeType> Qid<T> {
+    /// Create a Qid whose type bits match `T`
+    pub fn new(path: u64, version: u32) -> Self {
+        let ty = match T::KIND {
+            NodeKind::File => 0x00,
+            NodeKind::Dir => 0x80,
+        };
         Self {
-            ty: 0x00,
+            ty,
             version,
             path,
             _marker: PhantomData,
         }
     }
+}
 
+impl Qid<File> {
+    /// Create a new Qid for a file (ty = 0x00)
+    pub fn new_file(path: u64, version: u32) -> Self {
+        Self::new(path, version)
+    }
+}
+
+impl Qid<Dir> {
     /// Create a new Qid for a directory (ty = 0x80)
     pub fn new_dir(path: u64, version: u32) -> Self {
-        Self {
-            ty: 0x80,
-            version,
-            path,
+        Self::new(path, version)
+    }
+}
+
+impl<T> Qid<T> {
+    /// Whether the type bits mark a directory.
+    ///
+    /// The `ty` byte is the source of truth, since `T` is erased in a
+    /// `Qid<()>` and not serialized at all.
+    pub fn is_dir(&self) -> bool {
+        self.ty & 0x80 != 0
+    }
+
+    /// Whether the type bits mark a file
+    pub fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+
+    /// Forget the kind carried in the type; `ty` still tells them apart
+    pub fn erase(self) -> Qid {
+        Qid {
+            ty: self.ty,
+            version: self.version,
+            path: self.path,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Pack into the 13-byte 9P layout: type[1] version[4] path[8], all
+    /// little-endian
+    pub fn to_bytes(&self) -> [u8; 13] {
+        let mut bytes = [0; 13];
+        bytes[0] = self.ty;
+        bytes[1..5].copy_from_slice(&self.version.to_le_bytes());
+        bytes[5..].copy_from_slice(&self.path.to_le_bytes());
+        bytes
+    }
+}
+
+impl Qid {
+    /// Unpack the 13-byte 9P layout written by [`to_bytes`](Qid::to_bytes)
+    pub fn from_bytes(bytes: &[u8; 13]) -> Self {
+        let (version, path) = bytes[1..].split_at(4);
+        Qid {
+            ty: bytes[0],
+            version: u32::from_le_bytes(version.try_into().expect("4 bytes")),
+            path: u64::from_le_bytes(path.try_into().expect("8 bytes")),
             _marker: PhantomData,
         }
     }
 }
 
+impl<T: NodeType> From<Qid<T>> for Qid {
+    fn from(qid: Qid<T>) -> Self {
+        qid.erase()
+    }
+}
+
 /// Result of a walk operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalkResult {
     pub qids: Vec<Qid>,
 }
 
-/// File metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One entry of a [`readdirplus`](crate::backend::VfsBackend::readdirplus)
+/// listing: the child's name within the directory, its qid, and its stat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub qid: Qid,
+    pub stat: Stat,
+}
+
+/// Result of a write: bytes written and the file's qid afterwards
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WriteResult {
+    pub written: usize,
+    pub qid: Qid<File>,
+}
+
+/// Whether a `put` created its file or replaced an existing one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PutOutcome {
+    Created,
+    Overwritten,
+}
+
+/// Opaque point in a filesystem's change history, for `changed_since`.
+///
+/// Tokens only compare meaningfully against the filesystem that issued
+/// them. The default token predates every change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChangeToken(u64);
+
+impl ChangeToken {
+    pub(crate) fn new(generation: u64) -> Self {
+        Self(generation)
+    }
+
+    pub(crate) fn generation(self) -> u64 {
+        self.0
+    }
+}
+
+/// Serde helpers storing a `SystemTime` as signed nanoseconds since the
+/// Unix epoch, so times before 1970 work and every format sees an `i64`
+mod unix_nanos {
+    use std::time::{Duration, SystemTime};
+
+    use serde::{Deserialize, Deserializer, Serializer, de, ser};
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let nanos = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_nanos()),
+            Err(before) => i64::try_from(before.duration().as_nanos()).map(|n| -n),
+        };
+        let nanos = nanos.map_err(|_| ser::Error::custom("time out of range for i64 nanoseconds"))?;
+        serializer.serialize_i64(nanos)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let nanos = i64::deserialize(deserializer)?;
+        let offset = Duration::from_nanos(nanos.unsigned_abs());
+        let time = if nanos >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(offset)
+        } else {
+            SystemTime::UNIX_EPOCH.checked_sub(offset)
+        };
+        time.ok_or_else(|| de::Error::custom("time out of range for this platform"))
+    }
+}
+
+/// File metadata.
+///
+/// Serialized times are `i64` nanoseconds since the Unix epoch, negative
+/// before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Stat<T = ()> {
     pub qid: Qid<T>,
     pub name: String,
     pub size: u64,
     pub mode: u32,
+    #[serde(with = "unix_nanos")]
     pub atime: SystemTime,
+    #[serde(with = "unix_nanos")]
     pub mtime: SystemTime,
     pub uid: String,
     pub gid: String,
+    /// Hard-link count for files; number of immediate entries for directories
+    #[serde(default)]
+    pub nlink: u64,
+    /// Creation generation; a path removed and created again gets a new one
+    #[serde(default)]
+    pub generation: u64,
+}
+
+impl<T> Stat<T> {
+    /// Whether this is a directory, by its qid's type bits
+    pub fn is_dir(&self) -> bool {
+        self.qid.is_dir()
+    }
+
+    /// Whether this is a file, by its qid's type bits
+    pub fn is_file(&self) -> bool {
+        self.qid.is_file()
+    }
+}
+
+/// `ls -l`-style line: permissions, size, mtime (Unix seconds), and name
+impl<T> fmt::Display for Stat<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.is_dir() { 'd' } else { '-' };
+        let mut perms = String::with_capacity(9);
+        for shift in [6, 3, 0] {
+            let bits = self.mode >> shift;
+            perms.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            perms.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            perms.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+        }
+        let mtime = self
+            .mtime
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        write!(f, "{}{} {:>8} {} {}", kind, perms, self.size, mtime, self.name)
+    }
 }
 
 /// Open file/directory handle
@@ -102,3 +348,149 @@ impl<T, M> FileHandle<T, M> {
         }
     }
 }
+
+/// Handle from `open_any`, typed by the kind of node that was opened
+#[derive(Debug, Clone)]
+pub enum OpenResult {
+    /// A file opened with `ORDWR`
+    File(FileHandle<File, ReadWrite>),
+    /// A file opened with `OREAD` or `OEXEC`
+    ReadFile(FileHandle<File, ReadOnly>),
+    /// A file opened with `OWRITE`, or truncated without asking to read
+    WriteFile(FileHandle<File, WriteOnly>),
+    Dir(FileHandle<Dir, ReadOnly>),
+}
+
+impl OpenResult {
+    /// Fid of the handle, whichever kind it is
+    pub fn fid(&self) -> u64 {
+        match self {
+            OpenResult::File(handle) => handle.fid,
+            OpenResult::ReadFile(handle) => handle.fid,
+            OpenResult::WriteFile(handle) => handle.fid,
+            OpenResult::Dir(handle) => handle.fid,
+        }
+    }
+
+    /// Kind of the node the handle refers to
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            OpenResult::File(_) | OpenResult::ReadFile(_) | OpenResult::WriteFile(_) => NodeKind::File,
+            OpenResult::Dir(_) => NodeKind::Dir,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample(name: &str, mode: u32, size: u64) -> Stat {
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        Stat {
+            qid: Qid::new_file(7, 1).into(),
+            name: name.into(),
+            size,
+            mode,
+            atime: mtime,
+            mtime,
+            uid: "user".into(),
+            gid: "group".into(),
+            nlink: 1,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn test_qid_bytes() {
+        let qid = Qid::new_dir(0x0102_0304_0506_0708, 0x0a0b_0c0d);
+        let bytes = qid.to_bytes();
+        assert_eq!(
+            bytes,
+            [0x80, 0x0d, 0x0c, 0x0b, 0x0a, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+        );
+
+        let back = Qid::from_bytes(&bytes);
+        assert_eq!(back, qid.erase());
+        assert!(back.is_dir());
+        let file: Qid = Qid::new_file(u64::MAX, 7).into();
+        assert_eq!(Qid::from_bytes(&file.to_bytes()), file);
+    }
+
+    #[test]
+    fn test_stat_display() {
+        assert_eq!(
+            sample("notes.txt", 0o644, 42).to_string(),
+            "-rw-r--r--       42 1700000000 notes.txt"
+        );
+
+        let mut dir = sample("src", 0o755, 0);
+        dir.qid = Qid::new_dir(8, 0).into();
+        assert_eq!(dir.to_string(), "drwxr-xr-x        0 1700000000 src");
+    }
+
+    #[test]
+    fn test_stat_serde_round_trip() {
+        let mut dir = sample("src", 0o755, 0);
+        dir.qid = Qid::new_dir(8, 3).into();
+
+        for stat in [sample("notes.txt", 0o644, 42), dir] {
+            let json = serde_json::to_string(&stat).unwrap();
+            let back: Stat = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, stat);
+            assert_eq!(back.qid.is_dir(), stat.name == "src");
+            assert_eq!(back.qid.is_file(), stat.name != "src");
+        }
+    }
+
+    #[test]
+    fn test_typed_qid_from_erased_json() {
+        let json = serde_json::to_string(&Qid::new_file(7, 2).erase()).unwrap();
+        let qid: Qid<File> = serde_json::from_str(&json).unwrap();
+        assert_eq!(qid, Qid::new_file(7, 2));
+        assert!(qid.is_file());
+
+        // The type parameter isn't checked: `ty` still says file
+        let mislabeled: Qid<Dir> = serde_json::from_str(&json).unwrap();
+        assert!(!mislabeled.is_dir());
+    }
+
+    #[test]
+    fn test_walk_result_and_handle_serde_round_trip() {
+        let walk = WalkResult {
+            qids: vec![Qid::new_dir(1, 0).erase(), Qid::new_file(2, 5).erase()],
+        };
+        let back: WalkResult = serde_json::from_str(&serde_json::to_string(&walk).unwrap()).unwrap();
+        assert_eq!(back.qids, walk.qids);
+        assert!(back.qids[0].is_dir() && back.qids[1].is_file());
+
+        let handle: FileHandle<File, ReadOnly> = FileHandle::new(9, Qid::new_file(2, 5), "/a/b".into(), 0);
+        let back: FileHandle<File, ReadOnly> =
+            serde_json::from_str(&serde_json::to_string(&handle).unwrap()).unwrap();
+        assert_eq!((back.fid, back.qid, back.path.as_str(), back.mode), (9, handle.qid, "/a/b", 0));
+    }
+
+    #[test]
+    fn test_stat_eq() {
+        assert_eq!(sample("a", 0o644, 1), sample("a", 0o644, 1));
+        assert_ne!(sample("a", 0o644, 1), sample("a", 0o600, 1));
+    }
+
+    #[test]
+    fn test_stat_times_as_unix_nanos() {
+        let mut stat = sample("a", 0o644, 1);
+        stat.mtime += Duration::from_nanos(5);
+        let json: serde_json::Value = serde_json::to_value(&stat).unwrap();
+        assert_eq!(json["atime"], 1_700_000_000_000_000_000i64);
+        assert_eq!(json["mtime"], 1_700_000_000_000_000_005i64);
+        let back: Stat = serde_json::from_value(json).unwrap();
+        assert_eq!(back, stat);
+
+        // Before the epoch is negative
+        stat.mtime = SystemTime::UNIX_EPOCH - Duration::from_millis(1500);
+        let json = serde_json::to_value(&stat).unwrap();
+        assert_eq!(json["mtime"], -1_500_000_000i64);
+        assert_eq!(serde_json::from_value::<Stat>(json).unwrap().mtime, stat.mtime);
+    }
+}
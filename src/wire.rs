@@ -0,0 +1,228 @@
+//! 9P2000 wire encoding of qids and stats.
+//!
+//! All integers are little-endian and strings are prefixed with a 2-byte
+//! length. Decoders take a `&mut &[u8]` and advance it past what they read,
+//! so consecutive structures can be decoded from one buffer.
+
+use std::time::{Duration, SystemTime};
+
+use crate::error::{VfsError, VfsResult};
+use crate::types::{Qid, Stat};
+
+/// Encoded size of a qid
+pub const QID_LEN: usize = 13;
+
+/// Directory bit in a 9P stat mode
+pub const DMDIR: u32 = 0x8000_0000;
+
+/// Append a qid: type[1] version[4] path[8]
+pub fn encode_qid<T>(q: &Qid<T>, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&q.to_bytes());
+}
+
+/// Append a stat in the 9P2000 layout.
+///
+/// `size[2] type[2] dev[4] qid[13] mode[4] atime[4] mtime[4] length[8]
+/// name[s] uid[s] gid[s] muid[s]`. `type` and `dev` are zero, `muid` repeats
+/// `uid`, and times are truncated to whole seconds. Directories get `DMDIR`
+/// in their mode.
+///
+/// # Panics
+///
+/// If a string field is longer than `u16::MAX` bytes; see
+/// [`try_encode_stat`] for a fallible version.
+pub fn encode_stat<T>(s: &Stat<T>, buf: &mut Vec<u8>) {
+    try_encode_stat(s, buf).expect("stat too large for 9P");
+}
+
+/// Append a stat as [`encode_stat`] does, or fail with `InvalidArgument`
+/// if it does not fit the 9P size fields, leaving `buf` as it was.
+pub fn try_encode_stat<T>(s: &Stat<T>, buf: &mut Vec<u8>) -> VfsResult<()> {
+    let start = buf.len();
+    let result = encode_stat_body(s, buf, start);
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+fn encode_stat_body<T>(s: &Stat<T>, buf: &mut Vec<u8>, start: usize) -> VfsResult<()> {
+    buf.extend_from_slice(&[0, 0]); // size, filled in below
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    encode_qid(&s.qid, buf);
+
+    let mode = if s.is_dir() { s.mode | DMDIR } else { s.mode };
+    buf.extend_from_slice(&mode.to_le_bytes());
+    buf.extend_from_slice(&unix_secs(s.atime).to_le_bytes());
+    buf.extend_from_slice(&unix_secs(s.mtime).to_le_bytes());
+    buf.extend_from_slice(&s.size.to_le_bytes());
+    for field in [&s.name, &s.uid, &s.gid, &s.uid] {
+        encode_str(field, buf)?;
+    }
+
+    let size = u16::try_from(buf.len() - start - 2)
+        .map_err(|_| VfsError::InvalidArgument(format!("stat of {} too large for 9P", s.name)))?;
+    buf[start..start + 2].copy_from_slice(&size.to_le_bytes());
+    Ok(())
+}
+
+/// Read a qid
+pub fn decode_qid(buf: &mut &[u8]) -> VfsResult<Qid> {
+    Ok(Qid::from_bytes(&take::<QID_LEN>(buf)?))
+}
+
+/// Read a stat written by [`encode_stat`] (or any 9P2000 peer).
+///
+/// 9P stats carry no link count or generation, so `nlink` is always 1 and
+/// `generation` is 0.
+pub fn decode_stat(buf: &mut &[u8]) -> VfsResult<Stat> {
+    let size = u16::from_le_bytes(take(buf)?) as usize;
+    if buf.len() < size {
+        return Err(truncated());
+    }
+    let (mut body, rest) = buf.split_at(size);
+    *buf = rest;
+
+    let _ty = u16::from_le_bytes(take(&mut body)?);
+    let _dev = u32::from_le_bytes(take(&mut body)?);
+    let qid = decode_qid(&mut body)?;
+    let mode = u32::from_le_bytes(take(&mut body)?) & !DMDIR;
+    let atime = from_unix_secs(u32::from_le_bytes(take(&mut body)?));
+    let mtime = from_unix_secs(u32::from_le_bytes(take(&mut body)?));
+    let size = u64::from_le_bytes(take(&mut body)?);
+    let name = decode_str(&mut body)?;
+    let uid = decode_str(&mut body)?;
+    let gid = decode_str(&mut body)?;
+    let _muid = decode_str(&mut body)?;
+
+    Ok(Stat {
+        qid,
+        name,
+        size,
+        mode,
+        atime,
+        mtime,
+        uid,
+        gid,
+        nlink: 1,
+        generation: 0,
+    })
+}
+
+fn encode_str(s: &str, buf: &mut Vec<u8>) -> VfsResult<()> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| VfsError::InvalidArgument(format!("{}-byte string too long for 9P", s.len())))?;
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn decode_str(buf: &mut &[u8]) -> VfsResult<String> {
+    let len = u16::from_le_bytes(take(buf)?) as usize;
+    if buf.len() < len {
+        return Err(truncated());
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    String::from_utf8(bytes.to_vec()).map_err(|_| VfsError::InvalidArgument("invalid utf-8".into()))
+}
+
+/// Split off the next `N` bytes
+fn take<const N: usize>(buf: &mut &[u8]) -> VfsResult<[u8; N]> {
+    let (head, rest) = buf.split_first_chunk::<N>().ok_or_else(truncated)?;
+    *buf = rest;
+    Ok(*head)
+}
+
+fn truncated() -> VfsError {
+    VfsError::InvalidArgument("truncated 9P message".into())
+}
+
+fn unix_secs(time: SystemTime) -> u32 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+fn from_unix_secs(secs: u32) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_stat() -> Stat {
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        Stat {
+            qid: Qid::new_dir(0xdead_beef_cafe, 7).into(),
+            name: "docs".into(),
+            size: 0,
+            mode: 0o755,
+            atime: mtime + Duration::from_secs(5),
+            mtime,
+            uid: "alice".into(),
+            gid: "staff".into(),
+            nlink: 1,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn test_qid_round_trip() {
+        let qid: Qid = Qid::new_file(0x0102_0304_0506_0708, 42).into();
+        let mut buf = Vec::new();
+        encode_qid(&qid, &mut buf);
+        assert_eq!(buf.len(), QID_LEN);
+        assert_eq!(buf[..5], [0x00, 42, 0, 0, 0]);
+
+        let mut cursor = &buf[..];
+        assert_eq!(decode_qid(&mut cursor).unwrap(), qid);
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn test_stat_round_trip() {
+        let stat = sample_stat();
+        let mut buf = Vec::new();
+        encode_stat(&stat, &mut buf);
+
+        let size = u16::from_le_bytes([buf[0], buf[1]]) as usize;
+        assert_eq!(size + 2, buf.len());
+        // mode follows size, type, dev, and qid
+        let mode = u32::from_le_bytes(buf[21..25].try_into().unwrap());
+        assert_eq!(mode, 0o755 | DMDIR);
+
+        let mut cursor = &buf[..];
+        assert_eq!(decode_stat(&mut cursor).unwrap(), stat);
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn test_encode_oversized_stat() {
+        let mut buf = vec![1, 2, 3];
+        let mut stat = sample_stat();
+        stat.name = "n".repeat(usize::from(u16::MAX) + 1);
+        assert!(matches!(try_encode_stat(&stat, &mut buf), Err(VfsError::InvalidArgument(_))));
+        assert_eq!(buf, [1, 2, 3]);
+
+        // Every string fits, but not all of them together
+        stat.name = "n".repeat(40_000);
+        stat.uid = "u".repeat(40_000);
+        assert!(matches!(try_encode_stat(&stat, &mut buf), Err(VfsError::InvalidArgument(_))));
+        assert_eq!(buf, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let mut buf = Vec::new();
+        encode_stat(&sample_stat(), &mut buf);
+        buf.pop();
+
+        assert!(matches!(
+            decode_stat(&mut &buf[..]),
+            Err(VfsError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,8 @@
+//! Misuses the type system is meant to reject, checked against pinned
+//! compiler output.
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,8 @@
+use bulkhead::{Dir, File, Qid};
+
+fn takes_file(_: Qid<File>) {}
+
+fn main() {
+    let dir: Qid<Dir> = Qid::new_dir(1, 0);
+    takes_file(dir);
+}